@@ -0,0 +1,203 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// Color of an ANSI SGR sequence, either one of the 256 palette colors or a true color.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AnsiColor {
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+/// xterm colors of the first 16 palette entries
+const BASE_COLORS: [(u8, u8, u8); 16] = [
+    (0, 0, 0), (205, 0, 0), (0, 205, 0), (205, 205, 0),
+    (0, 0, 238), (205, 0, 205), (0, 205, 205), (229, 229, 229),
+    (127, 127, 127), (255, 0, 0), (0, 255, 0), (255, 255, 0),
+    (92, 92, 255), (255, 0, 255), (0, 255, 255), (255, 255, 255),
+];
+
+impl AnsiColor {
+    pub fn rgb(&self) -> (u8, u8, u8) {
+        match *self {
+            AnsiColor::Rgb(r, g, b) => (r, g, b),
+            AnsiColor::Indexed(idx) if idx < 16 => BASE_COLORS[idx as usize],
+            AnsiColor::Indexed(idx) if idx < 232 => {
+                let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+                let idx = idx - 16;
+                (level(idx / 36), level(idx / 6 % 6), level(idx % 6))
+            }
+            AnsiColor::Indexed(idx) => {
+                let gray = 8 + (idx - 232) * 10;
+                (gray, gray, gray)
+            }
+        }
+    }
+
+    pub fn css(&self) -> String {
+        let (r, g, b) = self.rgb();
+        format!("rgb({},{},{})", r, g, b)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct AnsiStyle {
+    pub foreground: Option<AnsiColor>,
+    pub background: Option<AnsiColor>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub strikethrough: bool,
+}
+
+impl AnsiStyle {
+    /// Name of the text tag rendering the style.
+    pub fn tag_name(&self) -> String {
+        let color = |c: Option<AnsiColor>| c.map(|c| c.css()).unwrap_or_default();
+        format!(
+            "ansi:{}:{}:{}{}{}{}",
+            color(self.foreground),
+            color(self.background),
+            self.bold as u8,
+            self.italic as u8,
+            self.underline as u8,
+            self.strikethrough as u8
+        )
+    }
+
+    /// Applies the parameters of a SGR sequence, e.g. `1;31` of `ESC[1;31m`.
+    fn apply(&self, params: &str) -> AnsiStyle {
+        let mut style = *self;
+        let mut codes = params.split(|c| c == ';' || c == ':')
+            // An empty parameter means 0, unknown ones are ignored
+            .map(|p| if p.is_empty() { 0 } else { p.parse::<u8>().unwrap_or(u8::MAX) });
+        while let Some(code) = codes.next() {
+            match code {
+                0 => style = AnsiStyle::default(),
+                1 => style.bold = true,
+                3 => style.italic = true,
+                4 => style.underline = true,
+                9 => style.strikethrough = true,
+                22 => style.bold = false,
+                23 => style.italic = false,
+                24 => style.underline = false,
+                29 => style.strikethrough = false,
+                30..=37 => style.foreground = Some(AnsiColor::Indexed(code - 30)),
+                38 => style.foreground = extended_color(&mut codes),
+                39 => style.foreground = None,
+                40..=47 => style.background = Some(AnsiColor::Indexed(code - 40)),
+                48 => style.background = extended_color(&mut codes),
+                49 => style.background = None,
+                90..=97 => style.foreground = Some(AnsiColor::Indexed(code - 90 + 8)),
+                100..=107 => style.background = Some(AnsiColor::Indexed(code - 100 + 8)),
+                _ => {}
+            }
+        }
+        style
+    }
+}
+
+/// Parses `5;n` and `2;r;g;b` following 38 and 48.
+fn extended_color<I: Iterator<Item = u8>>(codes: &mut I) -> Option<AnsiColor> {
+    match codes.next()? {
+        5 => Some(AnsiColor::Indexed(codes.next()?)),
+        2 => Some(AnsiColor::Rgb(codes.next()?, codes.next()?, codes.next()?)),
+        _ => None,
+    }
+}
+
+/// Styled range of a text, in chars.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StyleSpan {
+    pub start: usize,
+    pub end: usize,
+    pub style: AnsiStyle,
+}
+
+/// Strips escape sequences from the text and returns it along with the ranges styled by SGR sequences,
+/// or None if there are no escape sequences.
+pub fn parse_ansi(text: &str) -> Option<(String, Vec<StyleSpan>)> {
+    if !text.contains('\x1b') {
+        return None;
+    }
+    let mut plain = String::with_capacity(text.len());
+    let mut spans = vec![];
+    let mut style = AnsiStyle::default();
+    let mut span_start = 0;
+    let mut offset = 0;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            plain.push(c);
+            offset += 1;
+            continue;
+        }
+        if let Some(params) = skip_escape_sequence(&mut chars) {
+            let next = style.apply(&params);
+            if next != style {
+                if offset > span_start && style != AnsiStyle::default() {
+                    spans.push(StyleSpan { start: span_start, end: offset, style });
+                }
+                span_start = offset;
+                style = next;
+            }
+        }
+    }
+    if offset > span_start && style != AnsiStyle::default() {
+        spans.push(StyleSpan { start: span_start, end: offset, style });
+    }
+    Some((plain, spans))
+}
+
+/// Skips the sequence following an escape char and returns the parameters if it is a SGR sequence.
+fn skip_escape_sequence(chars: &mut Peekable<Chars>) -> Option<String> {
+    match chars.next()? {
+        // CSI, e.g. colors or erasing the line
+        '[' => {
+            let mut params = String::new();
+            for c in chars.by_ref() {
+                if ('\x40'..='\x7e').contains(&c) {
+                    return if c == 'm' { Some(params) } else { None };
+                }
+                params.push(c);
+            }
+            None
+        }
+        // OSC, e.g. hyperlinks or the window title, terminated by BEL or ST
+        ']' => {
+            while let Some(c) = chars.next() {
+                if c == '\x07' {
+                    break;
+                }
+                if c == '\x1b' {
+                    chars.next_if_eq(&'\\');
+                    break;
+                }
+            }
+            None
+        }
+        _ => None,
+    }
+}
+
+#[test]
+pub fn test_parse_ansi() {
+    assert_eq!(parse_ansi("plain text\n"), None);
+
+    let (text, spans) = parse_ansi("\x1b[32mINFO\x1b[0m \x1b[1;31mfailed\x1b[22m to connect\x1b[K\n").unwrap();
+    assert_eq!(text, "INFO failed to connect\n");
+    let red = Some(AnsiColor::Indexed(1));
+    assert_eq!(spans, vec![
+        StyleSpan { start: 0, end: 4, style: AnsiStyle { foreground: Some(AnsiColor::Indexed(2)), ..AnsiStyle::default() } },
+        StyleSpan { start: 5, end: 11, style: AnsiStyle { foreground: red, bold: true, ..AnsiStyle::default() } },
+        StyleSpan { start: 11, end: 23, style: AnsiStyle { foreground: red, ..AnsiStyle::default() } },
+    ]);
+
+    // Offsets are in chars
+    let (text, spans) = parse_ansi("ü \x1b[38;5;196;48;2;0;0;255mred\x1b[m \x1b]8;;http://a\x07link\x1b]8;;\x1b\\").unwrap();
+    assert_eq!(text, "ü red link");
+    assert_eq!(spans.len(), 1);
+    assert_eq!((spans[0].start, spans[0].end), (2, 5));
+    assert_eq!(spans[0].style.foreground.map(|c| c.css()), Some("rgb(255,0,0)".to_string()));
+    assert_eq!(spans[0].style.background, Some(AnsiColor::Rgb(0, 0, 255)));
+    assert_eq!(AnsiColor::Indexed(244).rgb(), (128, 128, 128));
+}
@@ -0,0 +1,465 @@
+use std::io::Write;
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, bail};
+use argh::FromArgs;
+use chrono::{SecondsFormat, Utc};
+use futures::StreamExt;
+use regex::Regex;
+use reqwest::StatusCode;
+use crate::ansi::parse_ansi;
+use crate::cluster_list_view::NamespaceViewData;
+use crate::config::{CONFIG, Workspace};
+use crate::k8s_client::{KubeClient, KubeConfig};
+use crate::local_source::LocalSource;
+use crate::log_stream::{LogData, LogStreamEvent, PreviousLogs, StreamState};
+use crate::log_view::SINCE_10M;
+use crate::pod_list_view::{pod_view_data, PodViewData};
+use crate::result::{AppError, AppResult};
+use crate::tokio;
+
+/// Lines of different pods which arrive within this time are printed ordered by their timestamp.
+const MERGE_DELAY: Duration = Duration::from_millis(250);
+
+// argh has no shared options, so the tail subcommand repeats the options of the gui
+
+/// Kubernetes log viewer, opens the given pods right away.
+#[derive(FromArgs)]
+pub struct Args {
+    /// kube config path list, defaults to KUBECONFIG or ~/.kube/config
+    #[argh(option)]
+    pub kubeconfig: Option<String>,
+    /// context, defaults to the current context of the kube config
+    #[argh(option)]
+    pub context: Option<String>,
+    /// namespace, defaults to the namespace of the context
+    #[argh(option, short = 'n')]
+    pub namespace: Option<String>,
+    /// tail the pods matching the label selector instead of named pods
+    #[argh(option, short = 'l')]
+    pub selector: Option<String>,
+    /// how far back the logs are loaded, e.g. 30s, 10m, 1h or 1h30m
+    #[argh(option, from_str_fn(parse_since))]
+    pub since: Option<u32>,
+    /// highlight the lines matching the search
+    #[argh(option)]
+    pub search: Option<String>,
+    /// open a local log file instead of pods, - reads stdin
+    #[argh(option, short = 'f')]
+    pub file: Vec<String>,
+    /// follow the local log files as they grow
+    #[argh(switch)]
+    pub follow: bool,
+    /// open a saved workspace
+    #[argh(option, short = 'w')]
+    pub workspace: Option<String>,
+    /// names of the pods to tail
+    #[argh(positional)]
+    pub pods: Vec<String>,
+    #[argh(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand)]
+pub enum Command {
+    Tail(TailCommand),
+}
+
+/// Print the merged lines of the pods to the terminal, colored by the configured highlighters.
+#[derive(FromArgs)]
+#[argh(subcommand, name = "tail")]
+pub struct TailCommand {
+    /// kube config path list, defaults to KUBECONFIG or ~/.kube/config
+    #[argh(option)]
+    pub kubeconfig: Option<String>,
+    /// context, defaults to the current context of the kube config
+    #[argh(option)]
+    pub context: Option<String>,
+    /// namespace, defaults to the namespace of the context
+    #[argh(option, short = 'n')]
+    pub namespace: Option<String>,
+    /// tail the pods matching the label selector instead of named pods
+    #[argh(option, short = 'l')]
+    pub selector: Option<String>,
+    /// how far back the logs are loaded, e.g. 30s, 10m, 1h or 1h30m
+    #[argh(option, from_str_fn(parse_since))]
+    pub since: Option<u32>,
+    /// highlight the matches of the search
+    #[argh(option)]
+    pub search: Option<String>,
+    /// print the timestamps of the lines
+    #[argh(switch, short = 't')]
+    pub timestamps: bool,
+    /// print without colors, also disabled by the NO_COLOR environment variable
+    #[argh(switch)]
+    pub no_color: bool,
+    /// tail the pods of a saved workspace
+    #[argh(option, short = 'w')]
+    pub workspace: Option<String>,
+    /// names of the pods to tail
+    #[argh(positional)]
+    pub pods: Vec<String>,
+}
+
+/// Pods given on the command line along with the cluster they are running in.
+#[derive(Clone)]
+pub struct TailTarget {
+    pub kubeconfig: String,
+    pub context: String,
+    pub namespace: String,
+    pub pods: Vec<String>,
+    pub selector: Option<String>,
+    pub since_seconds: Option<u32>,
+    pub search: Option<String>,
+}
+
+impl TailTarget {
+    pub fn for_workspace(workspace: &Workspace) -> Self {
+        Self {
+            kubeconfig: workspace.config_path.clone(),
+            context: workspace.context.clone(),
+            namespace: workspace.namespace.clone(),
+            pods: workspace.pods.clone(),
+            selector: workspace.label_selector.clone(),
+            since_seconds: workspace.since_seconds,
+            search: workspace.search.clone(),
+        }
+    }
+
+    pub fn namespace_view_data(&self) -> NamespaceViewData {
+        NamespaceViewData {
+            name: self.namespace.clone(),
+            context: self.context.clone(),
+            config_path: self.kubeconfig.clone(),
+        }
+    }
+}
+
+/// What the gui opens on start.
+#[derive(Clone)]
+pub enum Startup {
+    Tail(TailTarget),
+    Local(Vec<LocalSource>, bool),
+    Workspace(Workspace),
+}
+
+fn saved_workspace(name: &str) -> anyhow::Result<Workspace> {
+    CONFIG.lock()
+        .map_err(|e| anyhow!("{}", e))?
+        .workspace(name)
+        .cloned()
+        .ok_or(anyhow!("Unknown workspace {}", name))
+}
+
+impl Args {
+    /// None if neither a workspace, files, a context, a namespace nor pods are given.
+    pub fn startup(&self) -> anyhow::Result<Option<Startup>> {
+        if let Some(name) = &self.workspace {
+            return Ok(Some(Startup::Workspace(saved_workspace(name)?)));
+        }
+        if !self.file.is_empty() {
+            let sources = self.file.iter().map(|f| LocalSource::from_arg(f)).collect();
+            return Ok(Some(Startup::Local(sources, self.follow)));
+        }
+        if self.context.is_none() && self.namespace.is_none() && self.selector.is_none() && self.pods.is_empty() {
+            return Ok(None);
+        }
+        let (kubeconfig, context, namespace) = resolve_cluster(self.kubeconfig.as_deref(), self.context.as_deref(), self.namespace.as_deref())?;
+        Ok(Some(Startup::Tail(TailTarget {
+            kubeconfig,
+            context,
+            namespace,
+            pods: self.pods.clone(),
+            selector: self.selector.clone(),
+            since_seconds: self.since,
+            search: self.search.clone(),
+        })))
+    }
+}
+
+impl TailCommand {
+    pub fn target(&self) -> anyhow::Result<TailTarget> {
+        if let Some(name) = &self.workspace {
+            let mut target = TailTarget::for_workspace(&saved_workspace(name)?);
+            target.since_seconds = self.since.or(target.since_seconds);
+            target.search = self.search.clone().or(target.search);
+            if target.selector.is_none() && target.pods.is_empty() {
+                bail!("Workspace {} has no pods", name);
+            }
+            return Ok(target);
+        }
+        if self.selector.is_none() && self.pods.is_empty() {
+            bail!("No pods given, name the pods or use --selector");
+        }
+        let (kubeconfig, context, namespace) = resolve_cluster(self.kubeconfig.as_deref(), self.context.as_deref(), self.namespace.as_deref())?;
+        Ok(TailTarget {
+            kubeconfig,
+            context,
+            namespace,
+            pods: self.pods.clone(),
+            selector: self.selector.clone(),
+            since_seconds: self.since,
+            search: self.search.clone(),
+        })
+    }
+}
+
+/// Fills in the kube config, context and namespace kubectl would use.
+fn resolve_cluster(kubeconfig: Option<&str>, context: Option<&str>, namespace: Option<&str>) -> anyhow::Result<(String, String, String)> {
+    let kubeconfig = match kubeconfig {
+        Some(path) => path.to_string(),
+        None => KubeConfig::discover().into_iter().next().ok_or(anyhow!("No kube config found, use --kubeconfig"))?,
+    };
+    let cfg = KubeConfig::load_paths(&kubeconfig)?;
+    let context = match context {
+        Some(context) => context.to_string(),
+        None if !cfg.current_context.is_empty() => cfg.current_context.clone(),
+        None => bail!("No current context in {}, use --context", kubeconfig),
+    };
+    let namespace = match namespace {
+        Some(namespace) => namespace.to_string(),
+        None => cfg.context(&context)?.namespace.unwrap_or("default".to_string()),
+    };
+    Ok((kubeconfig, context, namespace))
+}
+
+/// Parses durations like 90s, 10m, 1h30m or 2d, plain numbers are seconds.
+pub fn parse_since(value: &str) -> Result<u32, String> {
+    let mut seconds: u64 = 0;
+    let mut number = String::new();
+    for c in value.trim().chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 60 * 60 * 24,
+            _ => return Err(format!("Unknown unit '{}' in {}", c, value)),
+        };
+        let count = number.parse::<u64>().map_err(|_| format!("Missing number before '{}' in {}", c, value))?;
+        seconds = seconds.saturating_add(count.saturating_mul(unit));
+        number.clear();
+    }
+    if !number.is_empty() {
+        seconds = seconds.saturating_add(number.parse::<u64>().map_err(|e| e.to_string())?);
+    }
+    match u32::try_from(seconds) {
+        Ok(0) => Err(format!("Invalid duration {}", value)),
+        Ok(seconds) => Ok(seconds),
+        Err(_) => Err(format!("Duration {} is too long", value)),
+    }
+}
+
+/// Looks up the pods by name, containers excluded in the namespace are skipped.
+pub async fn resolve_pods(client: &KubeClient, context: &str, namespace: &str, names: &[String]) -> AppResult<Vec<PodViewData>> {
+    let excluded = CONFIG.lock()
+        .map(|cfg| cfg.excluded_containers(context, namespace))
+        .unwrap_or_default();
+    let pods = futures::future::try_join_all(names.iter().map(|name| async move {
+        client.pod(namespace, name).await.map_err(|e| {
+            match e.downcast_ref::<reqwest::Error>().and_then(|e| e.status()) {
+                Some(StatusCode::NOT_FOUND) => AppError::Msg(format!("Pod {} not found in namespace {}", name, namespace)),
+                _ => AppError::for_context(context, e),
+            }
+        })
+    })).await?;
+    let now = Utc::now();
+    Ok(pods.into_iter()
+        .map(|pod| {
+            let mut pod = pod_view_data(pod, now);
+            pod.exclude_containers(&excluded);
+            pod
+        })
+        .collect())
+}
+
+/// Highlighter of the config, lines are colored by the last matching one like in the log view.
+struct TerminalHighlighter {
+    search: Regex,
+    color: (u8, u8, u8),
+}
+
+struct LinePrinter {
+    highlighters: Vec<TerminalHighlighter>,
+    search: Option<Regex>,
+    timestamps: bool,
+    color: bool,
+}
+
+impl LinePrinter {
+    fn format(&self, datum: &LogData) -> String {
+        let plain = parse_ansi(&datum.text).map(|(text, _)| text);
+        let text = plain.as_deref().unwrap_or(&datum.text).trim_end_matches(|c| c == '\r' || c == '\n');
+        let mut line = String::new();
+        if self.color {
+            line.push_str("\x1b[2m");
+        }
+        if self.timestamps {
+            line.push_str(&datum.timestamp.to_rfc3339_opts(SecondsFormat::Millis, true));
+            line.push(' ');
+        }
+        line.push_str(&format!("{} {}", datum.pod, datum.container));
+        if self.color {
+            line.push_str("\x1b[22m");
+        }
+        line.push(' ');
+
+        if !self.color {
+            line.push_str(text);
+            return line;
+        }
+        let highlighter = self.highlighters.iter().filter(|h| h.search.is_match(text)).last();
+        let matches: Vec<(usize, usize)> = self.search.iter()
+            .flat_map(|search| search.find_iter(text).map(|m| (m.start(), m.end())))
+            .filter(|(start, end)| end > start)
+            .collect();
+        if highlighter.is_none() && matches.is_empty() {
+            // Keeps the colors of the application
+            line.push_str(datum.text.trim_end_matches(|c| c == '\r' || c == '\n'));
+            return line;
+        }
+
+        if let Some(h) = highlighter {
+            let (r, g, b) = h.color;
+            line.push_str(&format!("\x1b[38;2;{};{};{}m", r, g, b));
+        }
+        let mut pos = 0;
+        for (start, end) in matches {
+            line.push_str(&text[pos..start]);
+            line.push_str("\x1b[7m");
+            line.push_str(&text[start..end]);
+            line.push_str("\x1b[27m");
+            pos = end;
+        }
+        line.push_str(&text[pos..]);
+        line.push_str("\x1b[0m");
+        line
+    }
+}
+
+/// Parses the colors of the highlighter settings, e.g. rgb(244,94,94).
+fn parse_rgb(color: &str) -> Option<(u8, u8, u8)> {
+    let color = color.trim();
+    let values = color.strip_prefix("rgba(").or(color.strip_prefix("rgb("))?.strip_suffix(')')?;
+    let mut values = values.split(',').map(|v| v.trim().parse::<f64>().ok().map(|v| v.clamp(0.0, 255.0) as u8));
+    Some((values.next()??, values.next()??, values.next()??))
+}
+
+/// The highlighters of the workspace if it has some, the configured ones otherwise.
+fn terminal_highlighters(workspace: Option<&str>) -> Vec<TerminalHighlighter> {
+    CONFIG.lock()
+        .map(|cfg| workspace.and_then(|name| cfg.workspace(name)).and_then(|w| w.highlighters.as_ref())
+            .unwrap_or(&cfg.highlighters)
+            .iter()
+            .filter_map(|h| Some(TerminalHighlighter {
+                search: Regex::new(&h.search).ok()?,
+                color: parse_rgb(&h.color)?,
+            }))
+            .collect())
+        .unwrap_or_default()
+}
+
+/// Prints the lines of the pods until the streams end.
+pub async fn run_tail(cmd: TailCommand) -> anyhow::Result<()> {
+    let target = cmd.target()?;
+    let client = crate::log_stream::k8s_client(&target.kubeconfig, &target.context)?;
+    let since_seconds = target.since_seconds.unwrap_or(SINCE_10M);
+    let (stream, _exit) = match &target.selector {
+        Some(selector) => {
+            let excluded_containers = CONFIG.lock()
+                .map(|cfg| cfg.excluded_containers(&target.context, &target.namespace))
+                .unwrap_or_default();
+            let (stream, exit) = crate::log_stream::workload_log_stream(&client, &target.namespace, selector, since_seconds, excluded_containers).await;
+            (stream.boxed(), exit)
+        }
+        None => {
+            let pods = resolve_pods(&client, &target.context, &target.namespace, &target.pods).await?;
+            let (stream, exit) = crate::log_stream::log_stream(&client, &target.namespace, pods, since_seconds, PreviousLogs::Exclude).await;
+            (stream.boxed(), exit)
+        }
+    };
+
+    let printer = LinePrinter {
+        highlighters: terminal_highlighters(cmd.workspace.as_deref()),
+        search: match target.search.as_ref() {
+            Some(search) => Some(Regex::new(&format!("(?i){}", search))?),
+            None => None,
+        },
+        timestamps: cmd.timestamps,
+        color: !cmd.no_color && std::env::var_os("NO_COLOR").is_none(),
+    };
+
+    let mut out = std::io::BufWriter::new(std::io::stdout());
+    let mut chunks = StreamExt::ready_chunks(stream, 1000);
+    let mut pending: Vec<LogData> = vec![];
+    let mut last_print = Instant::now();
+    loop {
+        let events = match tokio::time::timeout(MERGE_DELAY, chunks.next()).await {
+            Ok(Some(events)) => events,
+            Ok(None) => break,
+            Err(_) => vec![],
+        };
+        for event in events {
+            match event {
+                LogStreamEvent::Data(datum) => pending.push(datum),
+                LogStreamEvent::Status(status) => {
+                    if let StreamState::Reconnecting(reason) = status.state {
+                        eprintln!("{} {}: {}", status.pod, status.container, reason);
+                    }
+                }
+            }
+        }
+        if last_print.elapsed() >= MERGE_DELAY {
+            print_lines(&mut out, &printer, &mut pending)?;
+            last_print = Instant::now();
+        }
+    }
+    print_lines(&mut out, &printer, &mut pending)
+}
+
+fn print_lines<W: Write>(out: &mut W, printer: &LinePrinter, pending: &mut Vec<LogData>) -> anyhow::Result<()> {
+    // Stable, so lines with the same timestamp keep their order
+    pending.sort_by_key(|datum| datum.timestamp);
+    for datum in pending.drain(..) {
+        writeln!(out, "{}", printer.format(&datum))?;
+    }
+    Ok(out.flush()?)
+}
+
+#[test]
+pub fn test_parse_since() {
+    assert_eq!(parse_since("90"), Ok(90));
+    assert_eq!(parse_since("30s"), Ok(30));
+    assert_eq!(parse_since("10m"), Ok(600));
+    assert_eq!(parse_since("1h30m"), Ok(5400));
+    assert_eq!(parse_since("2d"), Ok(172800));
+    assert!(parse_since("0m").is_err());
+    assert!(parse_since("h").is_err());
+    assert!(parse_since("1w").is_err());
+    assert!(parse_since("").is_err());
+}
+
+#[test]
+pub fn test_format_line() {
+    let mut printer = LinePrinter {
+        highlighters: vec![TerminalHighlighter {
+            search: Regex::new("(?i)error").unwrap(),
+            color: parse_rgb("rgb(244,94,94)").unwrap(),
+        }],
+        search: Some(Regex::new("(?i)db").unwrap()),
+        timestamps: true,
+        color: false,
+    };
+    assert_eq!(printer.format(&LogData::test("\x1b[31mERROR\x1b[0m db down\n", 1)), "2022-01-01T10:00:01.000Z pod app ERROR db down");
+
+    printer.timestamps = false;
+    printer.color = true;
+    assert_eq!(printer.format(&LogData::test("ERROR db down\n", 1)), "\x1b[2mpod app\x1b[22m \x1b[38;2;244;94;94mERROR \x1b[7mdb\x1b[27m down\x1b[0m");
+    // Lines without matches keep their colors
+    assert_eq!(printer.format(&LogData::test("\x1b[32mok\x1b[0m\n", 1)), "\x1b[2mpod app\x1b[22m \x1b[32mok\x1b[0m");
+    assert_eq!(parse_rgb("rgba(1,2,3,0.5)"), Some((1, 2, 3)));
+    assert_eq!(parse_rgb("blue"), None);
+}
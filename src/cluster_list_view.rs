@@ -1,5 +1,6 @@
 use std::path::PathBuf;
 use std::rc::Rc;
+use std::sync::Arc;
 use gtk4_helper::prelude::{Command, MsgHandler};
 
 use gtk4_helper::{
@@ -11,12 +12,15 @@ use gtk4_helper::{
 };
 use gtk4_helper::gio::ListStore;
 use gtk4_helper::gtk::{ColumnView, Orientation, ScrolledWindow};
+use futures::StreamExt;
 use itertools::Itertools;
+use stream_cancel::{StreamExt as StreamCancelStreamExt, Trigger, Tripwire};
 use crate::{ApplicationWindow, column_view_helper};
 use crate::column_view_helper::ButtonOptions;
 use crate::config::{CONFIG};
-use crate::k8s_client::KubeConfig;
-use crate::result::AppResult;
+use crate::k8s_client::{KubeConfig, ListWatchEvent, Namespace};
+use crate::result::AppError;
+use crate::tokio;
 use crate::util::{WidgetLoadingWrapper, show_and_log_error};
 
 
@@ -27,6 +31,11 @@ pub struct ClusterListView {
     context_list_data: gio::ListStore,
     namespace_list_data: gio::ListStore,
     namespace_list_view: WidgetLoadingWrapper<ScrolledWindow>,
+    // Changes of previous watches are ignored
+    watch_generation: u64,
+    watch_trigger: Option<Arc<Trigger>>,
+    is_loading: bool,
+    sender: Arc<dyn MsgHandler<ClusterListViewMsg>>,
     app_wnd: Rc<ApplicationWindow>,
 }
 
@@ -40,7 +49,9 @@ pub enum ClusterListViewMsg {
     AddConfig,
     RemoveConfig(u32),
     ConfigAdded(Option<PathBuf>),
-    NamespacesLoaded(AppResult<Vec<NamespaceViewData>>),
+    NamespacesWatching(u64, Arc<Trigger>),
+    NamespacesChanged(u64, ListWatchEvent<NamespaceViewData>),
+    NamespacesWatchFailed(u64, AppError),
 }
 
 #[model]
@@ -149,6 +160,10 @@ impl Component for ClusterListView {
             context_list_data,
             namespace_list_data,
             namespace_list_view: namespace_wnd,
+            watch_generation: 0,
+            watch_trigger: None,
+            is_loading: false,
+            sender: Arc::new(sender.clone()),
             app_wnd: input.app_wnd
         }
     }
@@ -181,37 +196,58 @@ impl Component for ClusterListView {
             }
             ClusterListViewMsg::ContextSelected(ctx) => {
                 self.namespace_list_view.set_is_loading(true);
-                return self.run_async(load_namespaces(ctx.config_path, ctx.name));
+                self.namespace_list_data.remove_all();
+                self.watch_trigger.take();
+                self.watch_generation += 1;
+                self.is_loading = true;
+                return self.run_async(watch_namespaces(ctx.config_path, ctx.name, self.watch_generation, self.sender.clone()));
             }
             ClusterListViewMsg::ConfigSelected(cfg) => {
                 let path = cfg.path;
-                if let Ok(cfg) = KubeConfig::load(&path) {
-                    self.context_list_data.remove_all();
-                    for context in cfg.contexts {
-                        let ctx = ContextViewData {
-                            name: context.name.clone(),
-                            config_path: path.clone(),
-                        };
-                        let ctx = ContextViewData::to_object(&ctx);
-                        self.context_list_data.append(&ctx);
-                    }
-                }
-            }
-            ClusterListViewMsg::NamespacesLoaded(data) => {
-                self.namespace_list_view.set_is_loading(false);
-                match data {
-                    Ok(data) => {
-                        self.namespace_list_data.remove_all();
-                        for namespace in data {
-                            let obj = NamespaceViewData::to_object(&namespace);
-                            self.namespace_list_data.append(&obj);
+                match KubeConfig::load_paths(&path) {
+                    Ok(cfg) => {
+                        self.context_list_data.remove_all();
+                        for context in cfg.contexts {
+                            let ctx = ContextViewData {
+                                name: context.name.clone(),
+                                config_path: path.clone(),
+                            };
+                            let ctx = ContextViewData::to_object(&ctx);
+                            self.context_list_data.append(&ctx);
                         }
                     }
                     Err(e) => {
-                        show_and_log_error("Failed to load namespaces", &e.to_string(), Some(&*self.app_wnd.clone()));
+                        show_and_log_error("Failed to load config", &e.to_string(), Some(&*self.app_wnd.clone()));
                     }
                 }
             }
+            ClusterListViewMsg::NamespacesWatching(generation, trigger) => {
+                if generation == self.watch_generation {
+                    self.watch_trigger = Some(trigger);
+                }
+            }
+            ClusterListViewMsg::NamespacesChanged(generation, change) => {
+                if generation != self.watch_generation {
+                    return Command::None;
+                }
+                if self.is_loading {
+                    self.is_loading = false;
+                    self.namespace_list_view.set_is_loading(false);
+                }
+                column_view_helper::apply_list_change(&self.namespace_list_data, change, NamespaceViewData::to_object, |obj| NamespaceViewData::from_object(obj).name, &[]);
+            }
+            ClusterListViewMsg::NamespacesWatchFailed(generation, e) => {
+                if generation != self.watch_generation {
+                    return Command::None;
+                }
+                if self.is_loading {
+                    self.is_loading = false;
+                    self.namespace_list_view.set_is_loading(false);
+                    show_and_log_error("Failed to load namespaces", &e.to_string(), Some(&*self.app_wnd.clone()));
+                } else {
+                    log::warn!("Namespace watch failed: {}", e);
+                }
+            }
             ClusterListViewMsg::RemoveConfig(pos) => {
                 if let Some(item) = self.config_list_data.item(pos) {
                     let cfg_item: KubeConfigViewData = KubeConfigViewData::from_object(&item);
@@ -233,16 +269,39 @@ impl Component for ClusterListView {
     }
 }
 
-async fn load_namespaces(config_path: String, context: String) -> ClusterListViewMsg {
-    let client = crate::log_stream::k8s_client_with_timeout(&config_path, &context);
-    let res = client.namespaces().await.and_then(|data| {
-        Ok(data.iter().map(|data| NamespaceViewData {
-            name: data.metadata.name.as_ref().unwrap().clone(),
-            config_path: config_path.clone(),
-            context: context.clone()
-        }).collect())
-    }).map_err(|e| e.into());
-    ClusterListViewMsg::NamespacesLoaded(res)
+/// Keeps the namespace list up to date until the returned trigger is dropped.
+/// The watch stops if the namespaces could not be loaded at all.
+async fn watch_namespaces(config_path: String, context: String, generation: u64, tx: Arc<dyn MsgHandler<ClusterListViewMsg>>) -> ClusterListViewMsg {
+    let client = match crate::log_stream::k8s_client(&config_path, &context) {
+        Ok(client) => client,
+        Err(e) => return ClusterListViewMsg::NamespacesWatchFailed(generation, e),
+    };
+
+    let (trigger, tripwire) = Tripwire::new();
+    let mut events = client.watch_namespaces().take_until_if(tripwire);
+    tokio::task::spawn(async move {
+        let mut loaded = false;
+        while let Some(event) = events.next().await {
+            match event {
+                Ok(change) => {
+                    loaded = true;
+                    let change = change.map(|namespace: Namespace| NamespaceViewData {
+                        name: namespace.metadata.name.unwrap_or_default(),
+                        config_path: config_path.clone(),
+                        context: context.clone()
+                    });
+                    tx(ClusterListViewMsg::NamespacesChanged(generation, change));
+                }
+                Err(e) => {
+                    tx(ClusterListViewMsg::NamespacesWatchFailed(generation, AppError::for_context(&context, e)));
+                    if !loaded {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+    ClusterListViewMsg::NamespacesWatching(generation, Arc::new(trigger))
 }
 
 fn kube_config_list<T: MsgHandler<ClusterListViewMsg> + Clone>(sender: T) -> (ColumnView, ListStore) {
@@ -1,3 +1,4 @@
+use std::collections::{HashMap, HashSet};
 use gtk4_helper::{
     gtk,
     glib,
@@ -8,6 +9,7 @@ use gtk4_helper::gio::ListStore;
 
 use gtk4_helper::gtk::{ColumnView, Align, Sorter, SelectionModel, SortListModel, Expression};
 use crate::gtk::PropertyExpression;
+use crate::k8s_client::ListWatchEvent;
 
 pub fn string_sorter(pe: &PropertyExpression) -> gtk::StringSorter {
     gtk::StringSorter::new(Some(&pe))
@@ -39,7 +41,15 @@ pub fn create_column_view<P, T>(item_type: glib::types::Type, sel_model_factory:
     (column_view, list_store)
 }
 
-fn create_item_label(item: &gtk::ListItem, property: &str) {
+/// Sorts by a key extracted from the items, e.g. for numeric values stored as strings.
+pub fn custom_sorter<K, F>(key: F) -> gtk::CustomSorter
+    where K: Ord,
+          F: 'static + Fn(&glib::Object) -> K
+{
+    gtk::CustomSorter::new(move |a, b| key(a).cmp(&key(b)).into())
+}
+
+pub fn create_item_label(item: &gtk::ListItem, property: &str) {
     if let Some(obj) = item.item() {
         let lbl = gtk::builders::LabelBuilder::new()
             .halign(Align::Start)
@@ -113,4 +123,61 @@ pub fn create_button_column<I>(column_view: &ColumnView, title: &str, click_hand
         .factory(&column_factory);
 
     column_view.append_column(&col_builder.expand(true).build());
-}
\ No newline at end of file
+}
+
+/// Applies the changes of a watched list to the list store. Known items are updated in place
+/// so that the selection of the list is kept, the `keep` properties are not overwritten.
+pub fn apply_list_change<T, O, K>(list_store: &ListStore, change: ListWatchEvent<T>, to_object: O, key: K, keep: &[&str])
+    where O: Fn(&T) -> glib::Object,
+          K: Fn(&glib::Object) -> String
+{
+    match change {
+        ListWatchEvent::Restarted(items) => {
+            let objects: Vec<glib::Object> = items.iter().map(|item| to_object(item)).collect();
+            let keys: HashSet<String> = objects.iter().map(|obj| key(obj)).collect();
+            let mut pos = 0;
+            while pos < list_store.n_items() {
+                match list_store.item(pos) {
+                    Some(obj) if !keys.contains(&key(&obj)) => list_store.remove(pos),
+                    _ => pos += 1,
+                }
+            }
+
+            let mut positions: HashMap<String, u32> = (0..list_store.n_items())
+                .filter_map(|pos| list_store.item(pos).map(|obj| (key(&obj), pos)))
+                .collect();
+            for obj in objects {
+                let obj_key = key(&obj);
+                match positions.get(&obj_key).and_then(|pos| list_store.item(*pos)) {
+                    Some(existing) => update_object(&existing, &obj, keep),
+                    None => {
+                        positions.insert(obj_key, list_store.n_items());
+                        list_store.append(&obj);
+                    }
+                }
+            }
+        }
+        ListWatchEvent::Applied(item) => {
+            let obj = to_object(&item);
+            match find_position(list_store, &key(&obj), &key).and_then(|pos| list_store.item(pos)) {
+                Some(existing) => update_object(&existing, &obj, keep),
+                None => list_store.append(&obj),
+            }
+        }
+        ListWatchEvent::Deleted(item) => {
+            if let Some(pos) = find_position(list_store, &key(&to_object(&item)), &key) {
+                list_store.remove(pos);
+            }
+        }
+    }
+}
+
+fn find_position<K: Fn(&glib::Object) -> String>(list_store: &ListStore, item_key: &str, key: &K) -> Option<u32> {
+    (0..list_store.n_items()).find(|pos| list_store.item(*pos).map(|obj| key(&obj) == item_key).unwrap_or(false))
+}
+
+fn update_object(existing: &glib::Object, update: &glib::Object, keep: &[&str]) {
+    for property in update.list_properties().iter().filter(|p| !keep.contains(&p.name())) {
+        existing.set_property_from_value(property.name(), &update.property_value(property.name()));
+    }
+}
@@ -1,10 +1,16 @@
+use std::collections::HashMap;
 use std::sync::Mutex;
 use once_cell::sync::Lazy;
 use anyhow::{Result, anyhow};
 use std::fs;
 use serde::{Serialize, Deserialize};
+use crate::k8s_client::KubeConfig;
+use crate::log_fields::{LEVEL, LOGGER};
+use crate::log_stream::ParserKind;
 
 const CONFIG_NAME: &'static str = "config.json";
+const DEFAULT_MAX_LINES: usize = 500_000;
+const DEFAULT_MAX_BYTES: usize = 256 * 1024 * 1024;
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Highlighter {
@@ -13,12 +19,127 @@ pub struct Highlighter {
    pub color: String,
 }
 
+/// Container which is never tailed in the namespace, e.g. a service mesh sidecar.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ContainerExclusion {
+   pub context: String,
+   pub namespace: String,
+   pub container: String,
+}
+
+/// Parser of the lines of containers with this name, see `ParserKind::id`.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ContainerParser {
+   pub container: String,
+   pub parser: String,
+}
+
+/// Named set of line filters which can be applied to the log view at once.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FilterSet {
+   pub name: String,
+   pub include: Vec<String>,
+   pub exclude: Vec<String>,
+   #[serde(default)]
+   pub fields: Vec<String>,
+}
+
+/// Log view tab which is restored on start.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LogTab {
+   pub config_path: String,
+   pub context: String,
+   pub namespace: String,
+   #[serde(default)]
+   pub pods: Vec<String>,
+   #[serde(default)]
+   pub label_selector: Option<String>,
+   #[serde(default)]
+   pub since_seconds: Option<u32>,
+   #[serde(default)]
+   pub search: Option<String>,
+   /// Shown in the second pane
+   #[serde(default)]
+   pub split: bool,
+}
+
+/// Display toggles of the log view stored with a workspace.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DisplaySettings {
+   pub wrap_text: bool,
+   pub show_pod_names: bool,
+   pub show_container_names: bool,
+   pub show_timestamps: bool,
+   pub show_fields: bool,
+   pub fold_events: bool,
+   pub render_ansi: bool,
+}
+
+impl Default for DisplaySettings {
+   fn default() -> Self {
+      DisplaySettings {
+         wrap_text: false,
+         show_pod_names: false,
+         show_container_names: false,
+         show_timestamps: false,
+         show_fields: false,
+         fold_events: false,
+         render_ansi: default_render_ansi(),
+      }
+   }
+}
+
+/// Named view of a namespace which can be opened at once, e.g. via `--workspace`.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Workspace {
+   pub name: String,
+   pub config_path: String,
+   pub context: String,
+   pub namespace: String,
+   /// Names of the tailed pods, unless a label selector is followed
+   #[serde(default)]
+   pub pods: Vec<String>,
+   #[serde(default)]
+   pub label_selector: Option<String>,
+   #[serde(default)]
+   pub since_seconds: Option<u32>,
+   #[serde(default)]
+   pub search: Option<String>,
+   #[serde(default)]
+   pub display: DisplaySettings,
+   #[serde(default)]
+   pub include: Vec<String>,
+   #[serde(default)]
+   pub exclude: Vec<String>,
+   #[serde(default)]
+   pub fields: Vec<String>,
+   /// None keeps the configured highlighters
+   #[serde(default)]
+   pub highlighters: Option<Vec<Highlighter>>,
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Config {
    pub k8s_configs: Vec<String>,
    pub highlighters: Vec<Highlighter>,
    pub log_view_settings: LogViewSettings,
+   #[serde(default)]
+   pub excluded_containers: Vec<ContainerExclusion>,
+   #[serde(default)]
+   pub filter_sets: Vec<FilterSet>,
+   #[serde(default)]
+   pub container_parsers: Vec<ContainerParser>,
+   #[serde(default)]
+   pub log_tabs: Vec<LogTab>,
+   #[serde(default)]
+   pub workspaces: Vec<Workspace>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -29,6 +150,59 @@ pub struct LogViewSettings {
    pub show_container_names: bool,
    pub show_timestamps: bool,
    pub font: String,
+   /// Budget of the log view, the oldest lines are dropped once it is exceeded
+   #[serde(default = "default_max_lines")]
+   pub max_lines: usize,
+   #[serde(default = "default_max_bytes")]
+   pub max_bytes: usize,
+   #[serde(default)]
+   pub show_fields: bool,
+   /// Name of the minimum level of shown lines
+   #[serde(default)]
+   pub min_level: Option<String>,
+   /// Fields of structured log lines shown in front of the message
+   #[serde(default = "default_field_columns")]
+   pub field_columns: Vec<String>,
+   /// Fields extracted from structured log lines besides level, msg, logger and trace_id
+   #[serde(default)]
+   pub additional_fields: Vec<String>,
+   /// Log4j / Logback pattern layouts used to parse the lines of JVM services
+   #[serde(default = "default_log4j_patterns")]
+   pub log4j_patterns: Vec<String>,
+   /// Fold the continuation lines of multi-line events like stack traces
+   #[serde(default)]
+   pub fold_events: bool,
+   /// Regexes of lines continuing the previous line, besides indented lines and stack trace frames
+   #[serde(default)]
+   pub continuation_patterns: Vec<String>,
+   /// Render ANSI colors, otherwise the escape sequences are only stripped
+   #[serde(default = "default_render_ansi")]
+   pub render_ansi: bool,
+}
+
+fn default_field_columns() -> Vec<String> {
+   vec![LEVEL.to_string(), LOGGER.to_string()]
+}
+
+fn default_log4j_patterns() -> Vec<String> {
+   vec![
+      // Logback default
+      "%d{HH:mm:ss.SSS} [%thread] %-5level %logger{36} - %msg%n".to_string(),
+      // Spring Boot default
+      "%d{yyyy-MM-dd HH:mm:ss.SSS} %5p %pid --- [%t] %-40.40logger{39} : %m%n".to_string(),
+   ]
+}
+
+fn default_render_ansi() -> bool {
+   true
+}
+
+fn default_max_lines() -> usize {
+   DEFAULT_MAX_LINES
+}
+
+fn default_max_bytes() -> usize {
+   DEFAULT_MAX_BYTES
 }
 
 impl Default for Config {
@@ -41,7 +215,7 @@ impl Default for Config {
       let font = "14px Courier";
 
       Config {
-         k8s_configs: vec![],
+         k8s_configs: KubeConfig::discover(),
          highlighters: vec![
             Highlighter {
                name: "Warnings".to_string(),
@@ -60,13 +234,83 @@ impl Default for Config {
             show_timestamps: false,
             wrap_text: false,
             font: font.to_string(),
-         }
+            max_lines: DEFAULT_MAX_LINES,
+            max_bytes: DEFAULT_MAX_BYTES,
+            show_fields: false,
+            min_level: None,
+            field_columns: default_field_columns(),
+            additional_fields: vec![],
+            log4j_patterns: default_log4j_patterns(),
+            fold_events: false,
+            continuation_patterns: vec![],
+            render_ansi: true,
+         },
+         excluded_containers: vec![],
+         filter_sets: vec![],
+         container_parsers: vec![],
+         log_tabs: vec![],
+         workspaces: vec![],
       }
    }
 }
 
 
 impl Config {
+   pub fn excluded_containers(&self, context: &str, namespace: &str) -> Vec<String> {
+      self.excluded_containers.iter()
+         .filter(|e| e.context == context && e.namespace == namespace)
+         .map(|e| e.container.clone())
+         .collect()
+   }
+
+   pub fn set_container_excluded(&mut self, context: &str, namespace: &str, container: &str, excluded: bool) {
+      let exclusion = ContainerExclusion {
+         context: context.to_string(),
+         namespace: namespace.to_string(),
+         container: container.to_string(),
+      };
+      self.excluded_containers.retain(|e| e != &exclusion);
+      if excluded {
+         self.excluded_containers.push(exclusion);
+      }
+   }
+
+   pub fn container_parsers(&self) -> HashMap<String, ParserKind> {
+      self.container_parsers.iter()
+         .filter_map(|p| ParserKind::from_id(&p.parser).map(|kind| (p.container.clone(), kind)))
+         .collect()
+   }
+
+   pub fn set_container_parser(&mut self, container: &str, kind: ParserKind) {
+      self.container_parsers.retain(|p| p.container != container);
+      if kind != ParserKind::Auto {
+         self.container_parsers.push(ContainerParser {
+            container: container.to_string(),
+            parser: kind.id().to_string(),
+         });
+      }
+   }
+
+   pub fn filter_set(&self, name: &str) -> Option<&FilterSet> {
+      self.filter_sets.iter().find(|s| s.name == name)
+   }
+
+   /// Adds the filter set or replaces the one with the same name.
+   pub fn save_filter_set(&mut self, filter_set: FilterSet) {
+      self.filter_sets.retain(|s| s.name != filter_set.name);
+      self.filter_sets.push(filter_set);
+   }
+
+   pub fn workspace(&self, name: &str) -> Option<&Workspace> {
+      self.workspaces.iter().find(|w| w.name == name)
+   }
+
+   /// Adds the workspace or replaces the one with the same name.
+   pub fn save_workspace(&mut self, workspace: Workspace) {
+      self.workspaces.retain(|w| w.name != workspace.name);
+      self.workspaces.push(workspace);
+   }
+
    pub fn save(&self) -> Result<()> {
       let json = serde_json::to_string_pretty(self)?;
       let path = crate::dirs::config_dir().ok_or(anyhow!("No config path!"))?;
@@ -11,4 +11,8 @@ pub fn log_dir() -> PathBuf {
 
 pub fn config_dir() -> Option<PathBuf> {
     root_dir().as_ref().map(|pd| pd.config_dir().to_path_buf())
-}
\ No newline at end of file
+}
+
+pub fn recordings_dir() -> PathBuf {
+    root_dir().as_ref().map(|r| r.data_dir().join("recordings")).unwrap_or(PathBuf::from("."))
+}
@@ -0,0 +1,25 @@
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone)]
+pub enum KubeConfigError {
+    #[error("Could not read file {path}: {msg}")]
+    MissingFile { path: String, msg: String },
+    #[error("Invalid kubeconfig {path}: {msg}")]
+    InvalidConfig { path: String, msg: String },
+    #[error("No kubeconfig found")]
+    NoConfig,
+    #[error("Invalid base64 data for {0}")]
+    InvalidBase64(&'static str),
+    #[error("Invalid kubeconfig - {0} is not PEM-encoded")]
+    InvalidPem(&'static str),
+    #[error("Unknown context {0}")]
+    UnknownContext(String),
+    #[error("Ambiguous context {0}")]
+    AmbiguousContext(String),
+    #[error("Unknown {kind} {name}")]
+    UnknownReference { kind: &'static str, name: String },
+    #[error("Ambiguous {kind} {name}")]
+    AmbiguousReference { kind: &'static str, name: String },
+}
+
+pub type KubeConfigResult<T> = Result<T, KubeConfigError>;
@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Duration, Utc};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use crate::k8s_client::{Cluster, ExecConfig};
+use crate::tokio;
+
+// Refresh tokens slightly before they expire so in flight requests don't fail
+const EXPIRATION_SKEW_SECONDS: i64 = 30;
+
+static CREDENTIAL_CACHE: Lazy<Mutex<HashMap<String, ExecCredentialStatus>>> = Lazy::new(|| {
+    Mutex::new(HashMap::new())
+});
+
+// Held while a plugin runs, so concurrent requests wait for its credential instead of running it again
+static PLUGIN_LOCKS: Lazy<Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>> = Lazy::new(|| {
+    Mutex::new(HashMap::new())
+});
+
+/// client.authentication.k8s.io ExecCredential as passed to and returned by the plugin.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecCredential {
+    pub api_version: String,
+    pub kind: String,
+    pub spec: Option<ExecCredentialSpec>,
+    pub status: Option<ExecCredentialStatus>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecCredentialSpec {
+    pub interactive: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cluster: Option<ExecCluster>,
+}
+
+/// Cluster passed to plugins which set `provideClusterInfo`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ExecCluster {
+    pub server: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_server_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub insecure_skip_tls_verify: Option<bool>,
+    /// Base64 encoded PEM
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub certificate_authority_data: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy_url: Option<String>,
+}
+
+impl ExecCluster {
+    pub fn new(cluster: &Cluster) -> Result<Self> {
+        Ok(Self {
+            server: cluster.server.to_string(),
+            tls_server_name: cluster.tls_server_name.clone(),
+            insecure_skip_tls_verify: cluster.insecure_tls,
+            certificate_authority_data: cluster.ca_pem()?.map(|pem| base64::encode(&pem)),
+            proxy_url: cluster.proxy_url.clone(),
+        })
+    }
+}
+
+/// Credential plugin of a client. The credential is looked up for each request,
+/// the plugin runs again once the cached credential expired.
+pub struct ExecPlugin {
+    exec: ExecConfig,
+    cluster: Option<ExecCluster>,
+}
+
+impl ExecPlugin {
+    pub fn new(exec: &ExecConfig, cluster: &Cluster) -> Result<Self> {
+        let cluster = if exec.provide_cluster_info.unwrap_or(false) {
+            Some(ExecCluster::new(cluster)?)
+        } else { None };
+        Ok(Self { exec: exec.clone(), cluster })
+    }
+
+    /// Returns the cached credential or runs the plugin on a blocking thread,
+    /// at most once at a time for the same plugin configuration.
+    pub async fn load_credential(&self) -> Result<ExecCredentialStatus> {
+        let key = cache_key(&self.exec, self.cluster.as_ref());
+        if let Some(status) = cached_credential(&key)? {
+            return Ok(status);
+        }
+
+        let lock = PLUGIN_LOCKS.lock()
+            .map_err(|_| anyhow!("Could not get credential plugin lock"))?
+            .entry(key).or_default().clone();
+        let _running = lock.lock().await;
+        // The credential is looked up again, it may have been loaded while waiting
+        let exec = self.exec.clone();
+        let cluster = self.cluster.clone();
+        tokio::task::spawn_blocking(move || exec_credential(&exec, cluster.as_ref())).await?
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecCredentialStatus {
+    pub expiration_timestamp: Option<String>,
+    pub token: Option<String>,
+    pub client_certificate_data: Option<String>,
+    pub client_key_data: Option<String>,
+}
+
+impl ExecCredentialStatus {
+    fn is_expired(&self) -> bool {
+        match self.expiration_timestamp.as_ref()
+            .and_then(|ts| DateTime::parse_from_rfc3339(ts).ok())
+        {
+            Some(expiration) => Utc::now() + Duration::seconds(EXPIRATION_SKEW_SECONDS) >= expiration.with_timezone(&Utc),
+            None => false,
+        }
+    }
+}
+
+fn cache_key(exec: &ExecConfig, cluster: Option<&ExecCluster>) -> String {
+    let mut key = exec.command.clone();
+    if let Some(cluster) = cluster {
+        key.push_str(&format!(" [{}]", cluster.server));
+    }
+    for arg in exec.args.iter().flatten() {
+        key.push(' ');
+        key.push_str(arg);
+    }
+    for env in exec.env.iter().flatten() {
+        key.push_str(&format!(" {}={}", env.name, env.value));
+    }
+    key
+}
+
+fn cached_credential(key: &str) -> Result<Option<ExecCredentialStatus>> {
+    let cache = CREDENTIAL_CACHE.lock()
+        .map_err(|_| anyhow!("Could not get credential cache lock"))?;
+    Ok(cache.get(key).filter(|status| !status.is_expired()).cloned())
+}
+
+/// Returns the cached credential of the plugin or runs it if there
+/// is no credential yet or the cached one is expired.
+pub fn exec_credential(exec: &ExecConfig, cluster: Option<&ExecCluster>) -> Result<ExecCredentialStatus> {
+    let key = cache_key(exec, cluster);
+    if let Some(status) = cached_credential(&key)? {
+        return Ok(status);
+    }
+
+    let status = run_plugin(exec, cluster)?;
+    if let Ok(mut cache) = CREDENTIAL_CACHE.lock() {
+        cache.insert(key, status.clone());
+    }
+    Ok(status)
+}
+
+fn run_plugin(exec: &ExecConfig, cluster: Option<&ExecCluster>) -> Result<ExecCredentialStatus> {
+    log::info!("Running credential plugin: {}", exec.command);
+    let exec_info = ExecCredential {
+        api_version: exec.api_version.clone(),
+        kind: "ExecCredential".to_string(),
+        spec: Some(ExecCredentialSpec {
+            interactive: false,
+            cluster: cluster.cloned(),
+        }),
+        status: None,
+    };
+
+    let mut cmd = Command::new(&exec.command);
+    if let Some(args) = &exec.args {
+        cmd.args(args);
+    }
+    if let Some(env) = &exec.env {
+        cmd.envs(env.iter().map(|e| (&e.name, &e.value)));
+    }
+    cmd.env("KUBERNETES_EXEC_INFO", serde_json::to_string(&exec_info)?);
+
+    let output = match cmd.output() {
+        Ok(output) => output,
+        Err(e) => {
+            if let Some(hint) = &exec.install_hint {
+                bail!("Could not run credential plugin {}: {}\n{}", exec.command, e, hint)
+            }
+            bail!("Could not run credential plugin {}: {}", exec.command, e)
+        }
+    };
+
+    if !output.status.success() {
+        bail!("Credential plugin {} failed with {}: {}", exec.command, output.status, String::from_utf8_lossy(&output.stderr))
+    }
+
+    let credential = serde_json::from_slice::<ExecCredential>(&output.stdout)?;
+    if credential.api_version != exec.api_version {
+        log::warn!("Credential plugin returned api version {}, expected {}", credential.api_version, exec.api_version);
+    }
+    credential.status.ok_or(anyhow!("Credential plugin {} returned no status", exec.command))
+}
+
+/// Writes a plugin to the directory which returns the token `$TOKEN_PREFIX-<number of runs>` expiring at `$EXPIRES`.
+#[cfg(all(test, unix))]
+pub(crate) fn write_test_plugin(dir: &std::path::Path) -> anyhow::Result<std::path::PathBuf> {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::create_dir_all(dir)?;
+    let counter = dir.join("calls");
+    let script = dir.join("plugin.sh");
+    std::fs::write(&script, format!(r#"#!/bin/sh
+echo x >> {counter}
+cat <<EOF
+{{"apiVersion":"client.authentication.k8s.io/v1","kind":"ExecCredential","status":{{"token":"$TOKEN_PREFIX-$(wc -l < {counter} | tr -d ' ')","expirationTimestamp":"$EXPIRES"}}}}
+EOF
+"#, counter = counter.display()))?;
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755))?;
+    Ok(script)
+}
+
+#[cfg(unix)]
+#[test]
+pub fn test_exec_credential_is_cached_until_expiration() -> anyhow::Result<()> {
+    use crate::k8s_client::ExecEnvVar;
+
+    let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+    let script = write_test_plugin(&dir)?;
+
+    let plugin = |expires: &str| ExecConfig {
+        api_version: "client.authentication.k8s.io/v1".to_string(),
+        command: script.to_str().unwrap().to_string(),
+        args: None,
+        env: Some(vec![
+            ExecEnvVar { name: "TOKEN_PREFIX".to_string(), value: "token".to_string() },
+            ExecEnvVar { name: "EXPIRES".to_string(), value: expires.to_string() },
+        ]),
+        install_hint: None,
+        provide_cluster_info: None,
+        interactive_mode: None,
+    };
+
+    let valid = plugin("2999-01-01T00:00:00Z");
+    assert_eq!(exec_credential(&valid, None)?.token.as_deref(), Some("token-1"));
+    assert_eq!(exec_credential(&valid, None)?.token.as_deref(), Some("token-1"));
+
+    let expired = plugin("2000-01-01T00:00:00Z");
+    assert_eq!(exec_credential(&expired, None)?.token.as_deref(), Some("token-2"));
+    assert_eq!(exec_credential(&expired, None)?.token.as_deref(), Some("token-3"));
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
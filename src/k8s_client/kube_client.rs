@@ -1,36 +1,194 @@
-use std::time::Duration;
-use reqwest::{self, header, ClientBuilder};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use reqwest::{self, header, ClientBuilder, RequestBuilder, StatusCode};
 use openssl::pkcs12::Pkcs12;
-use url::Url;
-use anyhow::{bail, Result};
-use crate::k8s_client::{ClusterContext};
+use url::{Host, Url};
+use chrono::{DateTime, SecondsFormat, Utc};
+use anyhow::{anyhow, bail, Result};
+use openssl::pkey::{PKey, Private};
+use openssl::x509::X509;
+use crate::k8s_client::{ClusterContext, ExecCredentialStatus, ExecPlugin};
 use serde::{Deserialize, Serialize};
-use k8s_openapi::api::core::v1::{PodSpec, PodStatus, NamespaceSpec};
-use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use k8s_openapi::api::core::v1::{ContainerStatus, PodSpec, PodStatus, NamespaceSpec};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{LabelSelector, ListMeta, ObjectMeta, Status};
+use futures::stream::{BoxStream, StreamExt};
 use log::LevelFilter;
 use serde::de::DeserializeOwned;
 
 use tokio_stream::{Stream};
+use crate::tokio;
+
+const LIST_PAGE_SIZE: u32 = 500;
+const LIST_WATCH_DELAY_MIN: Duration = Duration::from_secs(1);
+const LIST_WATCH_DELAY_MAX: Duration = Duration::from_secs(30);
+const SERVER_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
 
 // Problems with empty password on MacOs
 const PKCS12_PWD: &'static str = "a8c51701-bc96-44a4-a3bc-9b6034d1f8bd";
 
 #[derive(Clone)]
 pub struct KubeClient {
-    pub(crate) client: reqwest::Client,
     pub(crate) base_url: Url,
+    settings: Arc<ClientSettings>,
+    // Created by the first request, plugin credentials and server addresses are loaded asynchronously.
+    // Connection failures drop the client, so the server address is resolved again.
+    client: Arc<tokio::sync::Mutex<Option<CachedClient>>>,
+    token_file: Option<Arc<TokenFile>>,
+    exec_plugin: Option<Arc<ExecPlugin>>,
+    // Default headers keep only one value per name, so the groups are added to each request
+    impersonate_groups: Vec<header::HeaderValue>,
+}
+
+/// Settings of the reqwest clients of a `KubeClient`.
+struct ClientSettings {
+    timeout: Option<Duration>,
+    insecure_tls: bool,
+    ca_cert: Option<reqwest::Certificate>,
+    proxy: Option<reqwest::Proxy>,
+    // Tls server name and the server its requests are sent to
+    pinned_server: Option<(String, Url)>,
+    identity: Option<(X509, PKey<Private>)>,
+    headers: header::HeaderMap,
+}
+
+impl ClientSettings {
+    /// Creates a client, the client certificate of the kubeconfig takes precedence over the one of the plugin.
+    async fn build(&self, certificate: Option<&(String, String)>) -> Result<reqwest::Client> {
+        let client = ClientBuilder::new().default_headers(self.headers.clone());
+        let client = if let Some(timeout) = self.timeout {
+            client.timeout(timeout)
+        } else { client };
+
+        let client = if self.insecure_tls {
+            client.danger_accept_invalid_certs(true)
+        } else if let Some(ca_cert) = &self.ca_cert {
+            client.add_root_certificate(ca_cert.clone())
+        } else { client };
+
+        let client = if let Some(proxy) = &self.proxy {
+            client.proxy(proxy.clone())
+        } else { client };
+
+        let client = if let Some((server_name, server)) = &self.pinned_server {
+            client.resolve(server_name, server_addr(server).await?)
+        } else { client };
+
+        let client = if let Some((crt, key)) = &self.identity {
+            client.identity(identity(crt, key)?)
+        } else if let Some((crt, key)) = certificate {
+            let crt = X509::from_pem(crt.as_bytes())?;
+            let key = PKey::private_key_from_pem(key.as_bytes())?;
+            client.identity(identity(&crt, &key)?)
+        } else { client };
+
+        Ok(client.build()?)
+    }
+}
+
+struct CachedClient {
+    client: reqwest::Client,
+    // Certificate and key of the plugin credential the client was created with
+    certificate: Option<(String, String)>,
 }
 
+/// Bearer token read from a file which may be rotated while the client is in use
+/// (e.g. projected service account tokens). The file is read again when it was modified.
+struct TokenFile {
+    path: String,
+    token: Mutex<Option<(SystemTime, String)>>,
+}
+
+impl TokenFile {
+    fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            token: Mutex::new(None),
+        }
+    }
+
+    async fn token(&self) -> Result<String> {
+        let modified = tokio::fs::metadata(&self.path).await?.modified()?;
+        if let Some((_, token)) = self.token.lock().map_err(|_| anyhow!("Could not get token file lock"))?
+            .as_ref().filter(|(last_modified, _)| *last_modified == modified)
+        {
+            return Ok(token.clone());
+        }
+
+        log::info!("Reading token file: {}", self.path);
+        let data = tokio::fs::read_to_string(&self.path).await?.trim().to_string();
+        let mut token = self.token.lock().map_err(|_| anyhow!("Could not get token file lock"))?;
+        token.replace((modified, data.clone()));
+        Ok(data)
+    }
+}
+
+#[derive(Default)]
 pub struct LogOptions {
     pub follow: Option<bool>,
     pub since_seconds: Option<u32>,
+    pub since_time: Option<DateTime<Utc>>,
+    /// Logs of the previous instance of the container, e.g. before a crash
+    pub previous: Option<bool>,
+    pub tail_lines: Option<u32>,
+    pub limit_bytes: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(from = "PodData")]
 pub struct Pod {
     pub spec: PodSpec,
     pub metadata: ObjectMeta,
     pub status: Option<PodStatus>,
+    // Ephemeral containers are not part of the api version of k8s-openapi
+    pub ephemeral_containers: Vec<String>,
+    pub ephemeral_container_statuses: Vec<ContainerStatus>,
+}
+
+#[derive(Deserialize)]
+struct PodData {
+    spec: PodSpecData,
+    metadata: ObjectMeta,
+    status: Option<PodStatusData>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PodSpecData {
+    #[serde(flatten)]
+    spec: PodSpec,
+    #[serde(default)]
+    ephemeral_containers: Vec<NamedContainer>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PodStatusData {
+    #[serde(flatten)]
+    status: PodStatus,
+    #[serde(default)]
+    ephemeral_container_statuses: Vec<ContainerStatus>,
+}
+
+#[derive(Deserialize)]
+struct NamedContainer {
+    name: String,
+}
+
+impl From<PodData> for Pod {
+    fn from(pod: PodData) -> Self {
+        let (status, ephemeral_container_statuses) = match pod.status {
+            Some(status) => (Some(status.status), status.ephemeral_container_statuses),
+            None => (None, vec![]),
+        };
+        Pod {
+            spec: pod.spec.spec,
+            metadata: pod.metadata,
+            status,
+            ephemeral_containers: pod.spec.ephemeral_containers.into_iter().map(|c| c.name).collect(),
+            ephemeral_container_statuses,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Default)]
@@ -45,14 +203,340 @@ pub struct Namespace {
 pub struct ListResult<T> {
     kind: String,
     api_version: String,
+    pub metadata: Option<ListMeta>,
     pub items: Vec<T>,
 }
 
+/// Deployment, StatefulSet, DaemonSet or Job, only the parts needed to find its pods.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Workload {
+    pub metadata: ObjectMeta,
+    pub spec: Option<WorkloadSpec>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct WorkloadSpec {
+    pub selector: Option<LabelSelector>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WorkloadKind {
+    Deployment,
+    StatefulSet,
+    DaemonSet,
+    Job,
+}
+
+impl WorkloadKind {
+    pub const ALL: [WorkloadKind; 4] = [WorkloadKind::Deployment, WorkloadKind::StatefulSet, WorkloadKind::DaemonSet, WorkloadKind::Job];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WorkloadKind::Deployment => "Deployment",
+            WorkloadKind::StatefulSet => "StatefulSet",
+            WorkloadKind::DaemonSet => "DaemonSet",
+            WorkloadKind::Job => "Job",
+        }
+    }
+
+    fn path(&self) -> (&'static str, &'static str) {
+        match self {
+            WorkloadKind::Deployment => ("apis/apps/v1", "deployments"),
+            WorkloadKind::StatefulSet => ("apis/apps/v1", "statefulsets"),
+            WorkloadKind::DaemonSet => ("apis/apps/v1", "daemonsets"),
+            WorkloadKind::Job => ("apis/batch/v1", "jobs"),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", content = "object")]
+pub enum WatchEvent<T> {
+    #[serde(rename = "ADDED")]
+    Added(T),
+    #[serde(rename = "MODIFIED")]
+    Modified(T),
+    #[serde(rename = "DELETED")]
+    Deleted(T),
+    #[serde(rename = "BOOKMARK")]
+    Bookmark(Bookmark),
+    #[serde(rename = "ERROR")]
+    Error(Status),
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Bookmark {
+    pub metadata: ObjectMeta,
+}
+
+/// Resources which can be kept up to date with `KubeClient::list_watch`.
+pub trait Resource: DeserializeOwned + Send + 'static {
+    fn metadata(&self) -> &ObjectMeta;
+}
+
+impl Resource for Pod {
+    fn metadata(&self) -> &ObjectMeta {
+        &self.metadata
+    }
+}
+
+impl Resource for Namespace {
+    fn metadata(&self) -> &ObjectMeta {
+        &self.metadata
+    }
+}
+
+impl Resource for Workload {
+    fn metadata(&self) -> &ObjectMeta {
+        &self.metadata
+    }
+}
+
+/// Change of a watched list, `Restarted` replaces all known items e.g. after a relist.
+#[derive(Clone, Debug)]
+pub enum ListWatchEvent<T> {
+    Restarted(Vec<T>),
+    Applied(T),
+    Deleted(T),
+}
+
+impl<T> ListWatchEvent<T> {
+    pub fn map<U, F: Fn(T) -> U>(self, f: F) -> ListWatchEvent<U> {
+        match self {
+            ListWatchEvent::Restarted(items) => ListWatchEvent::Restarted(items.into_iter().map(f).collect()),
+            ListWatchEvent::Applied(item) => ListWatchEvent::Applied(f(item)),
+            ListWatchEvent::Deleted(item) => ListWatchEvent::Deleted(f(item)),
+        }
+    }
+}
+
+struct ListWatch<T> {
+    client: KubeClient,
+    url: String,
+    query: Vec<(&'static str, String)>,
+    // None if the list has to be (re)loaded
+    resource_version: Option<String>,
+    events: Option<BoxStream<'static, Result<WatchEvent<T>>>>,
+    delay: Option<Duration>,
+    // Whether the open watch delivered an event, watches ending right away are resumed with a backoff
+    watch_delivered: bool,
+    watch_delay: Option<Duration>,
+}
+
+fn next_delay(delay: Option<Duration>) -> Duration {
+    delay.map(|d| std::cmp::min(d * 2, LIST_WATCH_DELAY_MAX)).unwrap_or(LIST_WATCH_DELAY_MIN)
+}
+
+impl<T: Resource> ListWatch<T> {
+    async fn next(&mut self) -> Result<ListWatchEvent<T>> {
+        if let Some(delay) = self.delay {
+            tokio::time::sleep(delay).await;
+        }
+        match self.next_event().await {
+            Ok(event) => {
+                self.delay = None;
+                Ok(event)
+            }
+            Err(e) => {
+                self.events = None;
+                self.delay = Some(next_delay(self.delay));
+                Err(e)
+            }
+        }
+    }
+
+    async fn next_event(&mut self) -> Result<ListWatchEvent<T>> {
+        loop {
+            let resource_version = match &self.resource_version {
+                Some(resource_version) => resource_version.clone(),
+                None => {
+                    let (items, resource_version) = list_all::<T>(&self.client, &self.url, &self.query).await?;
+                    self.resource_version = Some(resource_version);
+                    self.events = None;
+                    return Ok(ListWatchEvent::Restarted(items));
+                }
+            };
+
+            if self.events.is_none() {
+                match self.client.watch::<T>(&self.url, &self.query, &resource_version).await {
+                    Ok(events) => {
+                        self.events = Some(events);
+                        self.watch_delivered = false;
+                    }
+                    Err(e) if is_gone(&e) => {
+                        log::info!("Resource version {} is too old, reloading {}", resource_version, self.url);
+                        self.resource_version = None;
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+
+            let event = match self.events.as_mut().and_then(|events| Some(events.next())) {
+                Some(next) => next.await,
+                None => None,
+            };
+            let event = match event {
+                Some(event) => event?,
+                None => {
+                    // The server closes watches after a while, resume from the last seen version
+                    self.events = None;
+                    if !self.watch_delivered {
+                        let delay = next_delay(self.watch_delay);
+                        log::warn!("Watch of {} ended without events, resuming in {:?}", self.url, delay);
+                        self.watch_delay = Some(delay);
+                        tokio::time::sleep(delay).await;
+                    }
+                    continue;
+                }
+            };
+            self.watch_delivered = true;
+            self.watch_delay = None;
+
+            match event {
+                WatchEvent::Added(item) | WatchEvent::Modified(item) => {
+                    self.update_resource_version(item.metadata());
+                    return Ok(ListWatchEvent::Applied(item));
+                }
+                WatchEvent::Deleted(item) => {
+                    self.update_resource_version(item.metadata());
+                    return Ok(ListWatchEvent::Deleted(item));
+                }
+                WatchEvent::Bookmark(bookmark) => self.update_resource_version(&bookmark.metadata),
+                WatchEvent::Error(status) => {
+                    self.events = None;
+                    if status.code == Some(StatusCode::GONE.as_u16() as i32) {
+                        log::info!("Watch of {} expired, reloading", self.url);
+                        self.resource_version = None;
+                        continue;
+                    }
+                    bail!("Watch of {} failed: {}", self.url, status.message.unwrap_or_default())
+                }
+            }
+        }
+    }
+
+    fn update_resource_version(&mut self, metadata: &ObjectMeta) {
+        if let Some(resource_version) = &metadata.resource_version {
+            self.resource_version = Some(resource_version.clone());
+        }
+    }
+}
+
+/// Loads all pages of the list.
+async fn list_all<T: Resource>(client: &KubeClient, url: &str, query: &[(&'static str, String)]) -> Result<(Vec<T>, String)> {
+    let mut items = vec![];
+    let mut continue_token: Option<String> = None;
+    loop {
+        let mut query = query.to_vec();
+        query.push(("limit", LIST_PAGE_SIZE.to_string()));
+        if let Some(token) = &continue_token {
+            query.push(("continue", token.clone()));
+        }
+
+        let list = match client.load_data::<T>(url, &query).await {
+            Ok(list) => list,
+            Err(e) if is_gone(&e) && continue_token.is_some() => {
+                log::info!("List continue token of {} expired, reloading", url);
+                items.clear();
+                continue_token = None;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+
+        items.extend(list.items);
+        let metadata = list.metadata.unwrap_or_default();
+        match metadata.continue_.filter(|token| !token.is_empty()) {
+            Some(token) => continue_token = Some(token),
+            None => return Ok((items, metadata.resource_version.unwrap_or_default())),
+        }
+    }
+}
+
+fn is_gone(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<reqwest::Error>().and_then(|e| e.status()) == Some(StatusCode::GONE)
+}
+
+/// Converts a label selector to the `labelSelector` query parameter syntax.
+pub fn label_selector_query(selector: &LabelSelector) -> String {
+    let mut requirements = vec![];
+    for (key, value) in selector.match_labels.iter().flatten() {
+        requirements.push(format!("{}={}", key, value));
+    }
+    for expression in selector.match_expressions.iter().flatten() {
+        let values = expression.values.as_ref().map(|v| v.join(",")).unwrap_or_default();
+        match expression.operator.as_str() {
+            "In" => requirements.push(format!("{} in ({})", expression.key, values)),
+            "NotIn" => requirements.push(format!("{} notin ({})", expression.key, values)),
+            "Exists" => requirements.push(expression.key.clone()),
+            "DoesNotExist" => requirements.push(format!("!{}", expression.key)),
+            op => log::warn!("Unknown label selector operator: {}", op),
+        }
+    }
+    requirements.join(",")
+}
+
 pub struct ClientOptions {
     pub timeout: Option<std::time::Duration>,
 }
 
+/// Address of the server. Of several addresses of a host name the first one accepting connections
+/// is used, e.g. localhost resolves to ::1 and 127.0.0.1 while servers often only listen on one of them.
+async fn server_addr(server: &Url) -> Result<SocketAddr> {
+    let port = server.port_or_known_default().ok_or(anyhow!("No port in server url: {}", server))?;
+    let host = match server.host() {
+        Some(Host::Ipv4(ip)) => return Ok(SocketAddr::new(ip.into(), port)),
+        Some(Host::Ipv6(ip)) => return Ok(SocketAddr::new(ip.into(), port)),
+        Some(Host::Domain(host)) => host,
+        None => bail!("No host in server url: {}", server),
+    };
+
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port)).await?.collect();
+    if addrs.len() > 1 {
+        for addr in &addrs {
+            if let Ok(Ok(_)) = tokio::time::timeout(SERVER_PROBE_TIMEOUT, tokio::net::TcpStream::connect(addr)).await {
+                return Ok(*addr);
+            }
+        }
+    }
+    addrs.first().copied().ok_or(anyhow!("Could not resolve server address: {}", server))
+}
+
+fn host_header(server: &Url) -> Result<String> {
+    let host = server.host_str().ok_or(anyhow!("No host in server url: {}", server))?;
+    Ok(match server.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_string(),
+    })
+}
+
+fn identity(crt: &X509, key: &PKey<Private>) -> Result<reqwest::Identity> {
+    let pkcs_cert = Pkcs12::builder().build(PKCS12_PWD, "admin", key, crt)?;
+    let req_pkcs_cert = reqwest::Identity::from_pkcs12_der(&pkcs_cert.to_der()?, PKCS12_PWD)?;
+    Ok(req_pkcs_cert)
+}
+
 impl KubeClient {
+    async fn get(&self, url: &str) -> Result<RequestBuilder> {
+        // Plugin credentials expire, e.g. tokens after 15 minutes on EKS
+        let credential = match &self.exec_plugin {
+            Some(plugin) => Some(plugin.load_credential().await?),
+            None => None,
+        };
+        let mut request = self.client(credential.as_ref()).await?.get(url);
+        for group in &self.impersonate_groups {
+            request = request.header("Impersonate-Group", group.clone());
+        }
+        if let Some(token_file) = &self.token_file {
+            return Ok(request.bearer_auth(token_file.token().await?));
+        }
+        if let Some(token) = credential.and_then(|c| c.token) {
+            return Ok(request.bearer_auth(token));
+        }
+        Ok(request)
+    }
+
     pub fn new(context: &ClusterContext) -> Result<KubeClient> {
         KubeClient::with_options(context, None)
     }
@@ -67,84 +551,230 @@ impl KubeClient {
         let auth_info = &context.user;
         let cluster = &context.cluster;
 
-        let mut client = ClientBuilder::new();
-        if let Some(options) = options {
-            if let Some(timeout) = options.timeout {
-                client = client.timeout(timeout);
-            }
-        }
-
         let mut headers = header::HeaderMap::new();
 
-        let client = if let Some(ca_cert) = cluster.ca_cert() {
-            let req_ca_cert = reqwest::Certificate::from_der(&ca_cert.to_der().unwrap()).unwrap();
-            client.add_root_certificate(req_ca_cert)
-        } else { client };
+        let ca_cert = if let Some(ca_cert) = cluster.ca_cert()? {
+            Some(reqwest::Certificate::from_der(&ca_cert.to_der()?)?)
+        } else { None };
 
-        let client = if auth_info.client_certificate().is_some() && auth_info.client_key().is_some() {
-            let crt = auth_info.client_certificate().unwrap();
-            let key = auth_info.client_key().unwrap();
-            let pkcs_cert = Pkcs12::builder().build(PKCS12_PWD, "admin", &key, &crt)?;
-            let req_pkcs_cert = reqwest::Identity::from_pkcs12_der(&pkcs_cert.to_der().expect("Could not convert pkcs to der cert"), PKCS12_PWD).unwrap();
-            client.identity(req_pkcs_cert)
-        } else { client };
+        let proxy = if let Some(proxy_url) = &cluster.proxy_url {
+            Some(reqwest::Proxy::all(proxy_url.as_str())?)
+        } else { None };
 
-        // if let (Some(username), Some(password)) = (auth_info.username, auth_info.password) {
-        //     headers.typed_insert(headers_ext::Authorization::basic(
-        //         &username, &password
-        //     ));
-        // } else if let Some(token) = auth_info.token {
-        //     headers.typed_insert(headers_ext::Authorization::bearer(&token)
-        //                          .map_err(|_| Error::from("Invalid bearer token"))?);
-        // }
+        // The proxy would connect to the tls server name, the address of the server can't be pinned for it
+        if proxy.is_some() && cluster.tls_server_name.is_some() {
+            bail!("The tls-server-name of the cluster is not supported together with a proxy-url");
+        }
+
+        // The certificate is verified against the host of the url, so we send the requests to the tls server name
+        // and pin it to the address of the actual server. The Host header still names the server.
+        let mut base_url = cluster.server.clone();
+        let pinned_server = if let Some(server_name) = &cluster.tls_server_name {
+            headers.insert(header::HOST, host_header(&cluster.server)?.parse()?);
+            base_url.set_host(Some(server_name))?;
+            Some((server_name.clone(), cluster.server.clone()))
+        } else { None };
 
-        if let Some(token) = &auth_info.token {
-            headers.insert("Authorization", format!("Bearer {}", token).parse().unwrap());
+        let identity = match (auth_info.client_certificate()?, auth_info.client_key()?) {
+            (Some(crt), Some(key)) => Some((crt, key)),
+            _ => None,
+        };
+
+        // Plugins are run by the requests, their tokens are set for each request
+        // and their client certificates replace the client once they changed
+        let (token_file, exec_plugin) = if let Some(token) = auth_info.token.as_ref() {
+            headers.insert(header::AUTHORIZATION, format!("Bearer {}", token).parse()?);
+            (None, None)
+        } else if let Some(path) = &auth_info.token_file {
+            (Some(Arc::new(TokenFile::new(path))), None)
+        } else {
+            // Plugin tokens take precedence over the default header
+            if let (Some(username), Some(password)) = (&auth_info.username, &auth_info.password) {
+                let credentials = base64::encode(&format!("{}:{}", username, password));
+                headers.insert(header::AUTHORIZATION, format!("Basic {}", credentials).parse()?);
+            }
+            let exec_plugin = if let Some(exec) = &auth_info.exec {
+                Some(Arc::new(ExecPlugin::new(exec, cluster)?))
+            } else { None };
+            (None, exec_plugin)
+        };
+
+        let mut impersonate_groups = vec![];
+        if let Some(user) = &auth_info.impersonate {
+            headers.insert("Impersonate-User", user.parse()?);
+            for group in auth_info.impersonate_groups.iter().flatten() {
+                impersonate_groups.push(group.parse()?);
+            }
+            if let Some(uid) = &auth_info.impersonate_uid {
+                headers.insert("Impersonate-Uid", uid.parse()?);
+            }
         }
 
-        let client = client.default_headers(headers)
-            .build()?;
+        let settings = ClientSettings {
+            timeout: options.and_then(|o| o.timeout),
+            insecure_tls: cluster.insecure_tls.unwrap_or(false),
+            ca_cert,
+            proxy,
+            pinned_server,
+            identity,
+            headers,
+        };
+
+        Ok(KubeClient {
+            base_url,
+            settings: Arc::new(settings),
+            client: Arc::new(tokio::sync::Mutex::new(None)),
+            token_file,
+            exec_plugin,
+            impersonate_groups,
+        })
+    }
+
+    /// Client with the certificate of the plugin credential, a new one is created when the certificate changed.
+    async fn client(&self, credential: Option<&ExecCredentialStatus>) -> Result<reqwest::Client> {
+        let certificate = credential
+            .and_then(|c| c.client_certificate_data.clone().zip(c.client_key_data.clone()));
+        let mut cached = self.client.lock().await;
+        if let Some(cached) = cached.as_ref().filter(|cached| cached.certificate == certificate) {
+            return Ok(cached.client.clone());
+        }
+        let client = self.settings.build(certificate.as_ref()).await?;
+        cached.replace(CachedClient { client: client.clone(), certificate });
+        Ok(client)
+    }
 
-        Ok(KubeClient { client, base_url: cluster.server.clone() })
+    async fn send(&self, request: RequestBuilder) -> Result<reqwest::Response> {
+        match request.send().await {
+            Ok(response) => Ok(response),
+            Err(e) => {
+                if e.is_connect() {
+                    self.client.lock().await.take();
+                }
+                Err(e.into())
+            }
+        }
     }
 
+    #[allow(dead_code)]
     pub async fn pods(&self, namespace: &str) -> Result<Vec<Pod>> {
         let url = format!("{}api/v1/namespaces/{}/pods", self.base_url, namespace);
         log::info!("Loading pods");
-        self.load_data::<Pod>(&url).await.map(|r| r.items)
+        self.load_data::<Pod>(&url, &[]).await.map(|r| r.items)
+    }
+
+    pub async fn pod(&self, namespace: &str, name: &str) -> Result<Pod> {
+        let url = format!("{}api/v1/namespaces/{}/pods/{}", self.base_url, namespace, name);
+        let pod = self.send(self.get(&url).await?).await?.error_for_status()?.json::<Pod>().await?;
+        Ok(pod)
+    }
+
+    /// Keeps track of the pods of the namespace, optionally restricted by a label selector.
+    pub fn watch_pods(&self, namespace: &str, label_selector: Option<&str>) -> BoxStream<'static, Result<ListWatchEvent<Pod>>> {
+        let url = format!("{}api/v1/namespaces/{}/pods", self.base_url, namespace);
+        let query = label_selector.map(|selector| vec![("labelSelector", selector.to_string())]).unwrap_or_default();
+        self.list_watch::<Pod>(url, query)
+    }
+
+    pub fn watch_namespaces(&self) -> BoxStream<'static, Result<ListWatchEvent<Namespace>>> {
+        let url = format!("{}api/v1/namespaces", self.base_url);
+        self.list_watch::<Namespace>(url, vec![])
+    }
+
+    /// Lists the resources and watches them for changes afterwards. Relists are done
+    /// transparently when the watch expired, failed requests are retried with a backoff.
+    pub fn list_watch<T: Resource>(&self, url: String, query: Vec<(&'static str, String)>) -> BoxStream<'static, Result<ListWatchEvent<T>>> {
+        log::info!("Watching {}", url);
+        let state = ListWatch {
+            client: self.clone(),
+            url,
+            query,
+            resource_version: None,
+            events: None,
+            delay: None,
+            watch_delivered: false,
+            watch_delay: None,
+        };
+        futures::stream::unfold(state, |mut state| async move {
+            let event = state.next().await;
+            Some((event, state))
+        }).boxed()
+    }
+
+    pub async fn workloads(&self, namespace: &str, kind: WorkloadKind) -> Result<Vec<Workload>> {
+        let (api, resource) = kind.path();
+        let url = format!("{}{}/namespaces/{}/{}", self.base_url, api, namespace, resource);
+        log::info!("Loading {}", resource);
+        self.load_data::<Workload>(&url, &[]).await.map(|r| r.items)
+    }
+
+    async fn watch<T: DeserializeOwned + Send + 'static>(&self, url: &str, query: &[(&str, String)], resource_version: &str) -> Result<BoxStream<'static, Result<WatchEvent<T>>>> {
+        let request = self.get(url).await?
+            .query(query)
+            .query(&[("watch", "1"), ("resourceVersion", resource_version), ("allowWatchBookmarks", "true")]);
+        let response = self.send(request).await?.error_for_status()?;
+
+        // Every event is a json object on a separate line
+        let events = futures::stream::unfold((Box::pin(response.bytes_stream()), Vec::new()), |(mut stream, mut buffer)| async move {
+            loop {
+                if let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+                    let line: Vec<u8> = buffer.drain(..=pos).collect();
+                    let event = serde_json::from_slice::<WatchEvent<T>>(&line).map_err(|e| anyhow!(e));
+                    return Some((event, (stream, buffer)));
+                }
+                match stream.next().await {
+                    Some(Ok(bytes)) => buffer.extend_from_slice(&bytes),
+                    Some(Err(e)) => return Some((Err(anyhow!(e)), (stream, buffer))),
+                    None => return None,
+                }
+            }
+        });
+        Ok(events.boxed())
     }
 
     pub async fn logs(&self, namespace: &str, pod: &str, container: Option<&str>, options: Option<LogOptions>) -> Result<impl Stream<Item=reqwest::Result<bytes::Bytes>>> {
         let url = format!("{}api/v1/namespaces/{}/pods/{}/log", self.base_url, namespace, pod);
-        let mut request = self.client.get(&url);
+        let mut request = self.get(&url).await?;
         request = request.query(&[("timestamps", "true")]);
         if let Some(container) = container {
             request = request.query(&[("container", container)]);
         }
 
-        let response = if let Some(opt) = options {
+        let request = if let Some(opt) = options {
             if opt.follow.is_some() && opt.follow.unwrap() {
                 request = request.query(&[("follow", "true")])
             }
-            if let Some(since) = opt.since_seconds {
+            if opt.previous.is_some() && opt.previous.unwrap() {
+                request = request.query(&[("previous", "true")])
+            }
+            if let Some(since) = opt.since_time {
+                request = request.query(&[("sinceTime", since.to_rfc3339_opts(SecondsFormat::Secs, true))])
+            } else if let Some(since) = opt.since_seconds {
                 request = request.query(&[("sinceSeconds", since.to_string())])
             }
+            if let Some(tail_lines) = opt.tail_lines {
+                request = request.query(&[("tailLines", tail_lines.to_string())])
+            }
+            if let Some(limit_bytes) = opt.limit_bytes {
+                request = request.query(&[("limitBytes", limit_bytes.to_string())])
+            }
             request
         } else {
             request
-        }.send().await?.bytes_stream();
+        };
+        let response = self.send(request).await?.error_for_status()?.bytes_stream();
         Ok(response)
     }
 
-    async fn load_data<T: DeserializeOwned>(&self, url: &str) -> Result<ListResult<T>> {
-        let response = self.client.get(url).send().await?;
-        if !response.status().is_success() {
+    async fn load_data<T: DeserializeOwned>(&self, url: &str, query: &[(&str, String)]) -> Result<ListResult<T>> {
+        let response = self.send(self.get(url).await?.query(query)).await?;
+        if let Err(e) = response.error_for_status_ref() {
             let msg = format!("Loading data failed with response code: {}", response.status());
             log::error!("{}", msg);
             if let Some(body) = response.text().await.ok() {
                 log::error!("Response body: {}", body);
             }
-            bail!(msg)
+            // Keep the status available for callers which handle specific codes
+            return Err(anyhow!(e).context(msg))
         } else {
             if log::max_level() >= LevelFilter::Trace {
                 log::trace!("Request url: {}", url);
@@ -163,7 +793,7 @@ impl KubeClient {
     pub async fn namespaces(&self) -> Result<Vec<Namespace>> {
         let url = format!("{}api/v1/namespaces", self.base_url);
         log::info!("Loading namespaces");
-        self.load_data::<Namespace>(&url).await.map(|r| r.items)
+        self.load_data::<Namespace>(&url, &[]).await.map(|r| r.items)
     }
 }
 
@@ -196,3 +826,382 @@ pub fn test_load_namespaces() -> anyhow::Result<()> {
     Ok(())
 }
 
+
+#[cfg(test)]
+fn stand_in_server(tls: Option<openssl::ssl::SslAcceptor>) -> u16 {
+    stand_in_server_with(tls, |_| namespace_list_response())
+}
+
+#[cfg(test)]
+fn namespace_list_response() -> String {
+    let body = r#"{"kind":"NamespaceList","apiVersion":"v1","items":[{"spec":{},"metadata":{"name":"default"}}]}"#;
+    format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body)
+}
+
+/// Answers every request with the response returned for its request line and headers.
+#[cfg(test)]
+fn stand_in_server_with<F: 'static + Send + Fn(&str) -> String>(tls: Option<openssl::ssl::SslAcceptor>, respond: F) -> u16 {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let mut stream: Box<dyn ReadWrite> = match &tls {
+                Some(acceptor) => match acceptor.accept(stream) {
+                    Ok(stream) => Box::new(stream),
+                    Err(_) => continue,
+                },
+                None => Box::new(stream),
+            };
+
+            let mut request = vec![];
+            let mut buf = [0u8; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                match stream.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => request.extend_from_slice(&buf[..n]),
+                }
+            }
+
+            let request = String::from_utf8_lossy(&request);
+            let response = respond(&request);
+            let _ = stream.write_all(response.as_bytes());
+        }
+    });
+    port
+}
+
+#[cfg(test)]
+trait ReadWrite: std::io::Read + std::io::Write {}
+#[cfg(test)]
+impl<T: std::io::Read + std::io::Write> ReadWrite for T {}
+
+#[cfg(test)]
+fn test_context(cluster: &str) -> ClusterContext {
+    test_user_context(cluster, "{}")
+}
+
+/// Context of the cluster fragment with the user given as flow mapping.
+#[cfg(test)]
+fn test_user_context(cluster: &str, user: &str) -> ClusterContext {
+    use crate::k8s_client::KubeConfig;
+
+    let cfg: KubeConfig = serde_yaml::from_str(&format!(r#"
+clusters:
+- name: test
+  cluster:
+{}
+users:
+- name: test
+  user: {}
+contexts:
+- name: test
+  context:
+    cluster: test
+    user: test
+current-context: test
+"#, cluster, user)).unwrap();
+    cfg.context("test").unwrap()
+}
+
+#[test]
+pub fn test_cluster_tls_settings() -> anyhow::Result<()> {
+    use openssl::asn1::Asn1Time;
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::nid::Nid;
+    use openssl::ssl::{SslAcceptor, SslMethod};
+    use openssl::x509::extension::SubjectAlternativeName;
+    use gtk4_helper::tokio;
+
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+    let key = PKey::from_ec_key(EcKey::generate(&group)?)?;
+    let mut name = openssl::x509::X509NameBuilder::new()?;
+    name.append_entry_by_text("CN", "kubernetes.test")?;
+    let name = name.build();
+    let mut crt = X509::builder()?;
+    crt.set_version(2)?;
+    crt.set_subject_name(&name)?;
+    crt.set_issuer_name(&name)?;
+    crt.set_pubkey(&key)?;
+    crt.set_not_before(&*Asn1Time::days_from_now(0)?)?;
+    crt.set_not_after(&*Asn1Time::days_from_now(1)?)?;
+    crt.append_extension(SubjectAlternativeName::new().dns("kubernetes.test").build(&crt.x509v3_context(None, None))?)?;
+    crt.sign(&key, openssl::hash::MessageDigest::sha256())?;
+    let crt = crt.build();
+
+    let mut acceptor = SslAcceptor::mozilla_intermediate(SslMethod::tls())?;
+    acceptor.set_private_key(&key)?;
+    acceptor.set_certificate(&crt)?;
+    // The Host header names the server, not the tls server name
+    let tls_port = stand_in_server_with(Some(acceptor.build()), |request| {
+        let request = request.to_lowercase();
+        if request.contains("\r\nhost: 127.0.0.1:") || request.contains("\r\nhost: localhost:") {
+            namespace_list_response()
+        } else {
+            "HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+        }
+    });
+    let http_port = stand_in_server(None);
+    let ca_data = base64::encode(&crt.to_pem()?);
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build().unwrap();
+
+    rt.block_on(async move {
+        let untrusted = test_context(&format!("    server: https://127.0.0.1:{}", tls_port));
+        assert!(KubeClient::with_timeout(&untrusted)?.namespaces().await.is_err());
+
+        let insecure = test_context(&format!("    server: https://127.0.0.1:{}\n    insecure-skip-tls-verify: true", tls_port));
+        assert_eq!(KubeClient::with_timeout(&insecure)?.namespaces().await?.len(), 1);
+
+        let wrong_name = test_context(&format!("    server: https://127.0.0.1:{}\n    certificate-authority-data: {}", tls_port, ca_data));
+        assert!(KubeClient::with_timeout(&wrong_name)?.namespaces().await.is_err());
+
+        let server_name = test_context(&format!("    server: https://127.0.0.1:{}\n    certificate-authority-data: {}\n    tls-server-name: kubernetes.test", tls_port, ca_data));
+        assert_eq!(KubeClient::with_timeout(&server_name)?.namespaces().await?.len(), 1);
+
+        let host_name = test_context(&format!("    server: https://localhost:{}\n    certificate-authority-data: {}\n    tls-server-name: kubernetes.test", tls_port, ca_data));
+        assert_eq!(KubeClient::with_timeout(&host_name)?.namespaces().await?.len(), 1);
+
+        let proxied = test_context(&format!("    server: http://kubernetes.invalid\n    proxy-url: http://127.0.0.1:{}", http_port));
+        assert_eq!(KubeClient::with_timeout(&proxied)?.namespaces().await?.len(), 1);
+
+        let proxied_server_name = test_context(&format!("    server: https://127.0.0.1:{}\n    tls-server-name: kubernetes.test\n    proxy-url: http://127.0.0.1:{}", tls_port, http_port));
+        assert!(KubeClient::with_timeout(&proxied_server_name).is_err());
+        Ok(())
+    })
+}
+
+/// Server which answers with the namespace list and keeps the request heads, header names are lowercase.
+#[cfg(test)]
+fn recording_server() -> (u16, Arc<Mutex<Vec<String>>>) {
+    let requests = Arc::new(Mutex::new(vec![]));
+    let recorded = requests.clone();
+    let port = stand_in_server_with(None, move |request| {
+        recorded.lock().unwrap().push(request.to_string());
+        namespace_list_response()
+    });
+    (port, requests)
+}
+
+#[test]
+pub fn test_basic_auth_and_impersonation_headers() -> anyhow::Result<()> {
+    use gtk4_helper::tokio;
+
+    let (port, requests) = recording_server();
+    let server = format!("    server: http://127.0.0.1:{}", port);
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build().unwrap();
+
+    rt.block_on(async move {
+        let basic = test_user_context(&server, "{username: admin, password: secret}");
+        KubeClient::new(&basic)?.namespaces().await?;
+        let impersonated = test_user_context(&server, "{token: abc, as: jane, as-groups: [dev, ops], as-uid: '42'}");
+        KubeClient::new(&impersonated)?.namespaces().await?;
+
+        let requests = requests.lock().unwrap();
+        assert!(requests[0].contains(&format!("\r\nauthorization: Basic {}\r\n", base64::encode("admin:secret"))));
+        assert!(!requests[0].contains("impersonate-"));
+        assert!(requests[1].contains("\r\nauthorization: Bearer abc\r\n"));
+        assert!(requests[1].contains("\r\nimpersonate-user: jane\r\n"));
+        assert!(requests[1].contains("\r\nimpersonate-group: dev\r\n"));
+        assert!(requests[1].contains("\r\nimpersonate-group: ops\r\n"));
+        assert!(requests[1].contains("\r\nimpersonate-uid: 42\r\n"));
+        Ok(())
+    })
+}
+
+#[test]
+pub fn test_token_file_is_read_again_when_rewritten() -> anyhow::Result<()> {
+    use gtk4_helper::tokio;
+
+    let (port, requests) = recording_server();
+    let path = std::env::temp_dir().join(format!("{}.token", uuid::Uuid::new_v4()));
+    std::fs::write(&path, "first\n")?;
+    let context = test_user_context(&format!("    server: http://127.0.0.1:{}", port), &format!("{{tokenFile: '{}'}}", path.display()));
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build().unwrap();
+
+    let result = rt.block_on(async {
+        let client = KubeClient::new(&context)?;
+        client.namespaces().await?;
+        client.namespaces().await?;
+        // Modification times are only as precise as the clock ticks of the kernel
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        std::fs::write(&path, "second\n")?;
+        client.namespaces().await?;
+
+        let requests = requests.lock().unwrap();
+        assert!(requests[0].contains("\r\nauthorization: Bearer first\r\n"));
+        assert!(requests[1].contains("\r\nauthorization: Bearer first\r\n"));
+        assert!(requests[2].contains("\r\nauthorization: Bearer second\r\n"));
+        anyhow::Ok(())
+    });
+    std::fs::remove_file(&path)?;
+    result
+}
+
+#[cfg(unix)]
+#[test]
+pub fn test_exec_plugin_token_is_refreshed_after_expiration() -> anyhow::Result<()> {
+    use gtk4_helper::tokio;
+
+    let (port, requests) = recording_server();
+    let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+    let script = crate::k8s_client::write_test_plugin(&dir)?;
+    // Expired 2s from now, credentials are refreshed 30s before they expire
+    let expires = (Utc::now() + chrono::Duration::seconds(32)).to_rfc3339_opts(SecondsFormat::Millis, true);
+    let context = test_user_context(&format!("    server: http://127.0.0.1:{}", port), &format!(
+        "{{exec: {{apiVersion: client.authentication.k8s.io/v1, command: '{}', env: [{{name: TOKEN_PREFIX, value: token}}, {{name: EXPIRES, value: '{}'}}]}}}}",
+        script.display(), expires,
+    ));
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build().unwrap();
+
+    let result = rt.block_on(async {
+        let client = KubeClient::new(&context)?;
+        // Concurrent requests share the run of the plugin
+        futures::future::try_join(client.namespaces(), client.namespaces()).await?;
+        tokio::time::sleep(Duration::from_millis(2500)).await;
+        client.namespaces().await?;
+
+        let requests = requests.lock().unwrap();
+        assert!(requests[0].contains("\r\nauthorization: Bearer token-1\r\n"));
+        assert!(requests[1].contains("\r\nauthorization: Bearer token-1\r\n"));
+        assert!(requests[2].contains("\r\nauthorization: Bearer token-2\r\n"));
+        anyhow::Ok(())
+    });
+    std::fs::remove_dir_all(&dir)?;
+    result
+}
+
+#[test]
+pub fn test_label_selector_query() {
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelectorRequirement;
+
+    let selector = LabelSelector {
+        match_labels: Some(vec![("app".to_string(), "nginx".to_string())].into_iter().collect()),
+        match_expressions: Some(vec![
+            LabelSelectorRequirement { key: "tier".to_string(), operator: "In".to_string(), values: Some(vec!["web".to_string(), "api".to_string()]) },
+            LabelSelectorRequirement { key: "canary".to_string(), operator: "DoesNotExist".to_string(), values: None },
+        ]),
+    };
+    assert_eq!(label_selector_query(&selector), "app=nginx,tier in (web,api),!canary");
+}
+
+#[test]
+pub fn test_list_watch_pagination_bookmarks_and_relist() -> anyhow::Result<()> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use gtk4_helper::tokio;
+
+    let namespace = |name: &str, version: u32| format!(r#"{{"spec":{{}},"metadata":{{"name":"{}","resourceVersion":"{}"}}}}"#, name, version);
+    let lists = AtomicUsize::new(0);
+    let port = stand_in_server_with(None, move |request| {
+        let body = if request.contains("watch=1") && request.contains("resourceVersion=10&") {
+            format!("{{\"type\":\"ADDED\",\"object\":{}}}\n{{\"type\":\"BOOKMARK\",\"object\":{{\"metadata\":{{\"resourceVersion\":\"12\"}}}}}}\n", namespace("c", 11))
+        } else if request.contains("watch=1") && request.contains("resourceVersion=12&") {
+            "{\"type\":\"ERROR\",\"object\":{\"code\":410,\"message\":\"too old resource version\"}}\n".to_string()
+        } else if request.contains("watch=1") && request.contains("resourceVersion=20&") {
+            format!("{{\"type\":\"DELETED\",\"object\":{}}}\n", namespace("a", 21))
+        } else if request.contains("continue=page2") {
+            format!(r#"{{"kind":"NamespaceList","apiVersion":"v1","metadata":{{"resourceVersion":"10"}},"items":[{}]}}"#, namespace("b", 2))
+        } else if request.contains("limit=500") && lists.fetch_add(1, Ordering::SeqCst) == 0 {
+            format!(r#"{{"kind":"NamespaceList","apiVersion":"v1","metadata":{{"resourceVersion":"10","continue":"page2"}},"items":[{}]}}"#, namespace("a", 1))
+        } else if request.contains("limit=500") {
+            format!(r#"{{"kind":"NamespaceList","apiVersion":"v1","metadata":{{"resourceVersion":"20"}},"items":[{},{}]}}"#, namespace("a", 1), namespace("c", 11))
+        } else {
+            return "HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string();
+        };
+        format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nConnection: close\r\n\r\n{}", body)
+    });
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build().unwrap();
+
+    rt.block_on(async move {
+        let client = KubeClient::new(&test_context(&format!("    server: http://127.0.0.1:{}", port)))?;
+        let names = |items: &Vec<Namespace>| items.iter().map(|n| n.metadata.name.clone().unwrap_or_default()).collect::<Vec<String>>();
+        let mut events = client.watch_namespaces();
+
+        match events.next().await.unwrap()? {
+            ListWatchEvent::Restarted(items) => assert_eq!(names(&items), vec!["a", "b"]),
+            e => panic!("Unexpected event: {:?}", e.map(|n| n.metadata.name)),
+        }
+        match events.next().await.unwrap()? {
+            ListWatchEvent::Applied(item) => assert_eq!(item.metadata.name.as_deref(), Some("c")),
+            e => panic!("Unexpected event: {:?}", e.map(|n| n.metadata.name)),
+        }
+        match events.next().await.unwrap()? {
+            ListWatchEvent::Restarted(items) => assert_eq!(names(&items), vec!["a", "c"]),
+            e => panic!("Unexpected event: {:?}", e.map(|n| n.metadata.name)),
+        }
+        match events.next().await.unwrap()? {
+            ListWatchEvent::Deleted(item) => assert_eq!(item.metadata.name.as_deref(), Some("a")),
+            e => panic!("Unexpected event: {:?}", e.map(|n| n.metadata.name)),
+        }
+        Ok(())
+    })
+}
+
+#[test]
+pub fn test_list_watch_backoff_for_empty_watches() -> anyhow::Result<()> {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use gtk4_helper::tokio;
+
+    let watches = Arc::new(AtomicUsize::new(0));
+    let watch_count = watches.clone();
+    let port = stand_in_server_with(None, move |request| {
+        let body = if request.contains("watch=1") {
+            watch_count.fetch_add(1, Ordering::SeqCst);
+            String::new()
+        } else {
+            r#"{"kind":"NamespaceList","apiVersion":"v1","metadata":{"resourceVersion":"5"},"items":[]}"#.to_string()
+        };
+        format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nConnection: close\r\n\r\n{}", body)
+    });
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build().unwrap();
+
+    rt.block_on(async move {
+        let client = KubeClient::new(&test_context(&format!("    server: http://127.0.0.1:{}", port)))?;
+        let mut events = client.watch_namespaces();
+        assert!(matches!(events.next().await.unwrap()?, ListWatchEvent::Restarted(_)));
+        assert!(tokio::time::timeout(Duration::from_millis(1500), events.next()).await.is_err());
+        // Resumed after 1s, the next one would follow after 2s
+        assert_eq!(watches.load(Ordering::SeqCst), 2);
+        Ok(())
+    })
+}
+
+#[test]
+pub fn test_deserialize_pod_with_ephemeral_containers() -> anyhow::Result<()> {
+    let pod: Pod = serde_json::from_str(r#"{
+        "metadata": {"name": "api"},
+        "spec": {
+            "initContainers": [{"name": "migrate"}],
+            "containers": [{"name": "api"}, {"name": "istio-proxy"}],
+            "ephemeralContainers": [{"name": "debugger"}]
+        },
+        "status": {
+            "phase": "Running",
+            "ephemeralContainerStatuses": [{"name": "debugger", "ready": false, "restartCount": 0, "image": "busybox", "imageID": ""}]
+        }
+    }"#)?;
+
+    assert_eq!(pod.spec.containers.len(), 2);
+    assert_eq!(pod.spec.init_containers.as_ref().map(|c| c.len()), Some(1));
+    assert_eq!(pod.status.as_ref().and_then(|s| s.phase.as_deref()), Some("Running"));
+    assert_eq!(pod.ephemeral_containers, vec!["debugger"]);
+    assert_eq!(pod.ephemeral_container_statuses[0].name, "debugger");
+    Ok(())
+}
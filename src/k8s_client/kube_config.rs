@@ -1,13 +1,13 @@
 use std::fs::File;
 use std::io::prelude::*;
-use std::path::{Path};
+use std::path::{Path, PathBuf};
 use serde_yaml;
 use openssl::x509::X509;
 use openssl::pkey::{PKey, Private};
 use url::Url;
 use base64;
 use serde::{Deserialize, Serialize};
-use anyhow::{Result, anyhow};
+use crate::k8s_client::{KubeConfigError, KubeConfigResult};
 
 /// Configuration to build a Kubernetes client.
 #[derive(Debug, Serialize, Deserialize)]
@@ -16,10 +16,13 @@ pub struct KubeConfig {
     #[serde(rename = "apiVersion")]
     pub api_version: Option<String>,
     pub preferences: Option<Preferences>,
+    #[serde(default)]
     pub clusters: Vec<NamedCluster>,
+    #[serde(default)]
     pub users: Vec<NamedAuthInfo>,
+    #[serde(default)]
     pub contexts: Vec<NamedContext>,
-    #[serde(rename = "current-context")]
+    #[serde(rename = "current-context", default)]
     pub current_context: String,
     pub extensions: Option<Vec<NamedExtension>>,
 }
@@ -46,32 +49,41 @@ pub struct Cluster {
     ca_file: Option<String>,
     #[serde(rename = "certificate-authority-data")]
     ca_data: Option<String>,
+    #[serde(rename = "proxy-url")]
+    pub proxy_url: Option<String>,
+    #[serde(rename = "tls-server-name")]
+    pub tls_server_name: Option<String>,
     pub extensions: Option<Vec<NamedExtension>>,
 }
 
 /// Given two Option<String> parameters representing
 /// base64 encoded data, or file name with the data
 /// return the data form the first found in the specified order.
-fn get_from_b64data_or_file(data: &Option<String>, file: &Option<String>) -> Option<String> {
+fn get_from_b64data_or_file(data: &Option<String>, file: &Option<String>, what: &'static str) -> KubeConfigResult<Option<String>> {
     if let &Some(ref data) = data {
-        let decoded = base64::decode(&data).expect("Unable to decode base64.");
-        Some(String::from_utf8(decoded).expect("Unable to convert to string."))
+        let decoded = base64::decode(&data).map_err(|_| KubeConfigError::InvalidBase64(what))?;
+        let data = String::from_utf8(decoded).map_err(|_| KubeConfigError::InvalidBase64(what))?;
+        Ok(Some(data))
     } else if let &Some(ref file) = file {
         let mut data = String::new();
-        let mut f = File::open(file).expect("Unable to open file.");
-        f.read_to_string(&mut data)
-            .expect("File data is not UTF-8.");
-        Some(data)
+        File::open(file)
+            .and_then(|mut f| f.read_to_string(&mut data))
+            .map_err(|e| KubeConfigError::MissingFile { path: file.clone(), msg: e.to_string() })?;
+        Ok(Some(data))
     } else {
-        None
+        Ok(None)
     }
 }
 
 impl Cluster {
-    pub fn ca_cert(&self) -> Option<X509> {
-        get_from_b64data_or_file(&self.ca_data, &self.ca_file).map(|k| {
-            X509::from_pem(k.as_ref()).expect("Invalid kubeconfig - ca cert is not PEM-encoded")
-        })
+    pub fn ca_cert(&self) -> KubeConfigResult<Option<X509>> {
+        self.ca_pem()?.map(|k| {
+            X509::from_pem(k.as_ref()).map_err(|_| KubeConfigError::InvalidPem("ca cert"))
+        }).transpose()
+    }
+
+    pub fn ca_pem(&self) -> KubeConfigResult<Option<String>> {
+        get_from_b64data_or_file(&self.ca_data, &self.ca_file, "ca cert")
     }
 }
 
@@ -96,19 +108,46 @@ pub struct AuthInfo {
     pub client_key_file: Option<String>,
     #[serde(rename = "client-key-data")]
     pub client_key_data: Option<String>,
+    #[serde(rename = "as", alias = "impersonate")]
     pub impersonate: Option<String>,
+    #[serde(rename = "as-groups")]
+    pub impersonate_groups: Option<Vec<String>>,
+    #[serde(rename = "as-uid")]
+    pub impersonate_uid: Option<String>,
+    pub exec: Option<ExecConfig>,
+}
+
+/// Credential plugin (client.authentication.k8s.io) used to obtain a token or client certificate.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecConfig {
+    pub api_version: String,
+    pub command: String,
+    pub args: Option<Vec<String>>,
+    pub env: Option<Vec<ExecEnvVar>>,
+    pub install_hint: Option<String>,
+    pub provide_cluster_info: Option<bool>,
+    pub interactive_mode: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ExecEnvVar {
+    pub name: String,
+    pub value: String,
 }
 
 impl AuthInfo {
-    pub fn client_certificate(&self) -> Option<X509> {
-        get_from_b64data_or_file(&self.client_certificate_data, &self.client_certificate_file)
+    pub fn client_certificate(&self) -> KubeConfigResult<Option<X509>> {
+        get_from_b64data_or_file(&self.client_certificate_data, &self.client_certificate_file, "client cert")?
             .map(|k| X509::from_pem(k.as_ref())
-                .expect("Invalid kubeconfig - client cert is not PEM-encoded"))
+                .map_err(|_| KubeConfigError::InvalidPem("client cert")))
+            .transpose()
     }
-    pub fn client_key(&self) -> Option<PKey<Private>> {
-        get_from_b64data_or_file(&self.client_key_data, &self.client_key_file)
+    pub fn client_key(&self) -> KubeConfigResult<Option<PKey<Private>>> {
+        get_from_b64data_or_file(&self.client_key_data, &self.client_key_file, "client key")?
             .map(|k| PKey::private_key_from_pem(k.as_ref())
-                .expect("Invalid kubeconfig - client key is not PEM-encoded"))
+                .map_err(|_| KubeConfigError::InvalidPem("client key")))
+            .transpose()
     }
 }
 
@@ -146,19 +185,107 @@ pub struct ClusterContext {
     pub extensions: Option<Vec<Extension>>,
 }
 
+fn home_config_path() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".kube").join("config"))
+}
+
 impl KubeConfig {
-    pub fn load<P: AsRef<Path>>(path: P) -> Result<KubeConfig> {
-        let f = File::open(path.as_ref())?;
-        let r = serde_yaml::from_reader(f)?;
+    pub fn load<P: AsRef<Path>>(path: P) -> KubeConfigResult<KubeConfig> {
+        let path_name = path.as_ref().display().to_string();
+        let f = File::open(path.as_ref())
+            .map_err(|e| KubeConfigError::MissingFile { path: path_name.clone(), msg: e.to_string() })?;
+        let r = serde_yaml::from_reader(f)
+            .map_err(|e| KubeConfigError::InvalidConfig { path: path_name, msg: e.to_string() })?;
         Ok(r)
     }
 
-    pub fn context(&self, name: &str) -> Result<ClusterContext> {
+    /// Loads and merges the files of a path list like the KUBECONFIG environment variable.
+    pub fn load_paths(paths: &str) -> KubeConfigResult<KubeConfig> {
+        let paths: Vec<PathBuf> = std::env::split_paths(paths).collect();
+        KubeConfig::load_merged(&paths)
+    }
+
+    /// Merges the given files the way kubectl does: The first occurrence of a cluster,
+    /// user or context wins and the current context is taken from the first file that sets it.
+    /// Files that don't exist are skipped.
+    pub fn load_merged<P: AsRef<Path>>(paths: &[P]) -> KubeConfigResult<KubeConfig> {
+        let mut merged: Option<KubeConfig> = None;
+        for path in paths {
+            let path = path.as_ref();
+            if path.as_os_str().is_empty() {
+                continue;
+            }
+            if !path.exists() {
+                log::warn!("Kube config does not exist: {}", path.display());
+                continue;
+            }
+
+            let cfg = KubeConfig::load(path)?;
+            merged = Some(match merged {
+                Some(merged) => merged.merge(cfg),
+                None => cfg,
+            });
+        }
+        merged.ok_or(KubeConfigError::NoConfig)
+    }
+
+    /// Loads the configuration kubectl would use: the files listed in KUBECONFIG or ~/.kube/config.
+    pub fn load_default() -> KubeConfigResult<KubeConfig> {
+        match std::env::var("KUBECONFIG") {
+            Ok(paths) if !paths.is_empty() => KubeConfig::load_paths(&paths),
+            _ => {
+                let path = home_config_path().ok_or(KubeConfigError::NoConfig)?;
+                KubeConfig::load(path)
+            }
+        }
+    }
+
+    /// Config entries which should be available without adding them manually,
+    /// the KUBECONFIG path list and ~/.kube/config.
+    pub fn discover() -> Vec<String> {
+        let mut discovered = vec![];
+        let kube_config = std::env::var("KUBECONFIG").ok().filter(|paths| !paths.is_empty());
+        if let Some(paths) = &kube_config {
+            discovered.push(paths.clone());
+        }
+
+        if let Some(path) = home_config_path().filter(|p| p.exists()) {
+            let listed = kube_config.map(|paths| std::env::split_paths(&paths).any(|p| p == path)).unwrap_or(false);
+            if let (false, Some(path)) = (listed, path.to_str()) {
+                discovered.push(path.to_string());
+            }
+        }
+        discovered
+    }
+
+    fn merge(mut self, other: KubeConfig) -> KubeConfig {
+        if self.current_context.is_empty() {
+            self.current_context = other.current_context;
+        }
+        for cluster in other.clusters {
+            if !self.clusters.iter().any(|c| c.name == cluster.name) {
+                self.clusters.push(cluster);
+            }
+        }
+        for user in other.users {
+            if !self.users.iter().any(|u| u.name == user.name) {
+                self.users.push(user);
+            }
+        }
+        for context in other.contexts {
+            if !self.contexts.iter().any(|c| c.name == context.name) {
+                self.contexts.push(context);
+            }
+        }
+        self
+    }
+
+    pub fn context(&self, name: &str) -> KubeConfigResult<ClusterContext> {
         let ctxs: Vec<&NamedContext> = self.contexts.iter().filter(|c| c.name == name).collect();
         let ctx = match ctxs.len() {
-            0 => Err(anyhow!("unknown context {}", name)),
+            0 => Err(KubeConfigError::UnknownContext(name.to_string())),
             1 => Ok(&ctxs[0].context),
-            _ => Err(anyhow!("ambiguous context {}", name)),
+            _ => Err(KubeConfigError::AmbiguousContext(name.to_string())),
         }?;
 
         let clus: Vec<&NamedCluster> = self.clusters
@@ -167,9 +294,9 @@ impl KubeConfig {
             .collect();
 
         let clu = match clus.len() {
-            0 => Err(anyhow!("unknown cluster {}", name)),
+            0 => Err(KubeConfigError::UnknownReference { kind: "cluster", name: ctx.cluster.clone() }),
             1 => Ok(&clus[0].cluster),
-            _ => Err(anyhow!("ambiguous cluster {}", name)),
+            _ => Err(KubeConfigError::AmbiguousReference { kind: "cluster", name: ctx.cluster.clone() }),
         }?;
 
         let auths: Vec<&NamedAuthInfo> = self.users
@@ -178,9 +305,9 @@ impl KubeConfig {
             .collect();
 
         let auth = match auths.len() {
-            0 => Err(anyhow!("unknown auth-info {}", name)),
+            0 => Err(KubeConfigError::UnknownReference { kind: "auth-info", name: ctx.user.clone() }),
             1 => Ok(&auths[0].user),
-            _ => Err(anyhow!("ambiguous auth-info {}", name)),
+            _ => Err(KubeConfigError::AmbiguousReference { kind: "auth-info", name: ctx.user.clone() }),
         }?;
         let rc = ClusterContext {
             name: name.to_string(),
@@ -192,3 +319,44 @@ impl KubeConfig {
         Ok(rc)
     }
 }
+
+#[test]
+pub fn test_load_merged() -> anyhow::Result<()> {
+    let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+    std::fs::create_dir_all(&dir)?;
+    let config = |server: &str, token: &str, current_context: &str| format!(r#"
+clusters:
+- name: shared
+  cluster:
+    server: {}
+users:
+- name: shared
+  user:
+    token: {}
+contexts:
+- name: shared
+  context:
+    cluster: shared
+    user: shared
+{}
+"#, server, token, current_context);
+
+    let first = dir.join("first");
+    let second = dir.join("second");
+    std::fs::write(&first, config("https://first", "first-token", ""))?;
+    std::fs::write(&second, config("https://second", "second-token", "current-context: shared")
+        .replace("name: shared\n  context", "name: other\n  context"))?;
+
+    let paths = std::env::join_paths(vec![first, dir.join("missing"), second])?;
+    let cfg = KubeConfig::load_paths(paths.to_str().unwrap())?;
+    assert_eq!(cfg.current_context, "shared");
+    assert_eq!(cfg.clusters.len(), 1);
+    assert_eq!(cfg.contexts.len(), 2);
+
+    let ctx = cfg.context("other")?;
+    assert_eq!(ctx.cluster.server.as_str(), "https://first/");
+    assert_eq!(ctx.user.token.as_deref(), Some("first-token"));
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
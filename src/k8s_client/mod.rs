@@ -1,6 +1,10 @@
 mod kube_config;
 mod kube_client;
+mod exec_credential;
+mod error;
 
 pub use kube_client::*;
 pub use kube_config::*;
+pub use exec_credential::*;
+pub use error::*;
 pub use reqwest::Response;
\ No newline at end of file
@@ -0,0 +1,397 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::time::Duration;
+use chrono::{Datelike, DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use futures::Stream;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_json::Value;
+use stream_cancel::{StreamExt as StreamCancelStreamExt, Trigger, Tripwire};
+use tokio_stream::wrappers::ReceiverStream;
+use crate::log_line_decoder::MAX_LINE_LENGTH;
+use crate::log_stream::{LogData, LogStreamEvent};
+use crate::tokio;
+
+/// How often a followed file is checked for new lines.
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Log file or stdin, the name is shown as pod.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LocalSource {
+    File(PathBuf),
+    Stdin,
+}
+
+impl LocalSource {
+    /// `-` reads from stdin.
+    pub fn from_arg(arg: &str) -> Self {
+        if arg == "-" {
+            LocalSource::Stdin
+        } else {
+            LocalSource::File(PathBuf::from(arg))
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            LocalSource::File(path) => path.file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or(path.display().to_string()),
+            LocalSource::Stdin => "stdin".to_string(),
+        }
+    }
+
+    fn container(&self) -> &'static str {
+        match self {
+            LocalSource::File(_) => "file",
+            LocalSource::Stdin => "stdin",
+        }
+    }
+}
+
+/// Format of a local log, detected from its first line.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineFormat {
+    /// `kubectl logs --timestamps` output
+    Timestamped,
+    /// Lines of the docker json-file log driver
+    DockerJson,
+    /// The time is inferred from the line or the previous lines
+    Plain,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LocalLine {
+    pub timestamp: DateTime<Utc>,
+    pub text: String,
+    /// stdout / stderr of docker logs
+    pub stream: Option<String>,
+}
+
+/// Parses the lines of one local source.
+/// Lines without a time get the time of the previous line, the first ones the fallback time.
+pub struct LocalLineParser {
+    format: Option<LineFormat>,
+    last_timestamp: DateTime<Utc>,
+    /// Plain lines without a time which are appended to a followed file get the current time
+    following: bool,
+}
+
+impl LocalLineParser {
+    pub fn new(fallback: DateTime<Utc>) -> Self {
+        Self {
+            format: None,
+            last_timestamp: fallback,
+            following: false,
+        }
+    }
+
+    pub fn set_following(&mut self, following: bool) {
+        self.following = following;
+    }
+
+    pub fn format(&self) -> Option<LineFormat> {
+        self.format
+    }
+
+    pub fn parse(&mut self, line: &str) -> LocalLine {
+        let format = match self.format {
+            Some(format) => format,
+            None if line.trim().is_empty() => LineFormat::Plain,
+            None => {
+                let format = detect_format(line);
+                self.format = Some(format);
+                format
+            }
+        };
+        let parsed = match format {
+            LineFormat::Timestamped => parse_timestamped(line),
+            LineFormat::DockerJson => parse_docker_json(line),
+            LineFormat::Plain => infer_timestamp(line, &self.last_timestamp).map(|timestamp| LocalLine {
+                timestamp,
+                text: line.to_string(),
+                stream: None,
+            }),
+        };
+        let mut line = parsed.unwrap_or_else(|| LocalLine {
+            timestamp: if format == LineFormat::Plain && self.following { Utc::now() } else { self.last_timestamp },
+            text: line.to_string(),
+            stream: None,
+        });
+        if !line.text.ends_with('\n') {
+            line.text.push('\n');
+        }
+        self.last_timestamp = line.timestamp;
+        line
+    }
+}
+
+fn detect_format(line: &str) -> LineFormat {
+    if parse_docker_json(line).is_some() {
+        LineFormat::DockerJson
+    } else if parse_timestamped(line).is_some() {
+        LineFormat::Timestamped
+    } else {
+        LineFormat::Plain
+    }
+}
+
+fn parse_timestamped(line: &str) -> Option<LocalLine> {
+    let (timestamp, text) = line.split_once(' ')?;
+    let timestamp = DateTime::parse_from_rfc3339(timestamp).ok()?.with_timezone(&Utc);
+    Some(LocalLine {
+        timestamp,
+        text: text.to_string(),
+        stream: None,
+    })
+}
+
+fn parse_docker_json(line: &str) -> Option<LocalLine> {
+    let line = line.trim();
+    if !line.starts_with('{') {
+        return None;
+    }
+    let value: Value = serde_json::from_str(line).ok()?;
+    let timestamp = DateTime::parse_from_rfc3339(value.get("time")?.as_str()?).ok()?.with_timezone(&Utc);
+    Some(LocalLine {
+        timestamp,
+        text: value.get("log")?.as_str()?.to_string(),
+        stream: value.get("stream").and_then(|s| s.as_str()).map(|s| s.to_string()),
+    })
+}
+
+/// Looks for a timestamp at the start of the line, times without a date are taken to be on the date of the previous line.
+/// Times without an offset are taken to be UTC.
+fn infer_timestamp(line: &str, previous: &DateTime<Utc>) -> Option<DateTime<Utc>> {
+    static ISO: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(r"^\W{0,2}(\d{4}-\d{2}-\d{2})[T ](\d{2}:\d{2}:\d{2}(?:[.,]\d{1,9})?)(Z|[+-]\d{2}:?\d{2})?")
+            .expect("ISO timestamp regex should be valid")
+    });
+    static KLOG: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(r"^[IWEF](\d{2})(\d{2}) (\d{2}:\d{2}:\d{2}(?:\.\d{1,9})?)").expect("klog timestamp regex should be valid")
+    });
+    static COMBINED: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(r"\[(\d{2}/\w{3}/\d{4}:\d{2}:\d{2}:\d{2} [+-]\d{4})\]").expect("combined timestamp regex should be valid")
+    });
+    static TIME: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(r"^\W{0,2}(\d{2}:\d{2}:\d{2}(?:[.,]\d{1,9})?)\b").expect("time regex should be valid")
+    });
+
+    let time = |s: &str| NaiveTime::parse_from_str(&s.replace(',', "."), "%H:%M:%S%.f").ok();
+    let head = &line[..line.char_indices().nth(120).map(|(idx, _)| idx).unwrap_or(line.len())];
+
+    if let Some(captures) = ISO.captures(head) {
+        let date = NaiveDate::parse_from_str(&captures[1], "%Y-%m-%d").ok()?;
+        let naive = NaiveDateTime::new(date, time(&captures[2])?);
+        return match captures.get(3).map(|m| m.as_str()) {
+            None | Some("Z") => Some(Utc.from_utc_datetime(&naive)),
+            Some(offset) => {
+                let with_offset = format!("{}{}", naive.format("%Y-%m-%dT%H:%M:%S%.f"), offset);
+                DateTime::parse_from_str(&with_offset, "%Y-%m-%dT%H:%M:%S%.f%z").ok().map(|dt| dt.with_timezone(&Utc))
+            }
+        };
+    }
+    if let Some(captures) = KLOG.captures(head) {
+        let date = NaiveDate::from_ymd_opt(previous.year(), captures[1].parse().ok()?, captures[2].parse().ok()?)?;
+        return Some(Utc.from_utc_datetime(&NaiveDateTime::new(date, time(&captures[3])?)));
+    }
+    if let Some(captures) = COMBINED.captures(head) {
+        return DateTime::parse_from_str(&captures[1], "%d/%b/%Y:%H:%M:%S %z").ok().map(|dt| dt.with_timezone(&Utc));
+    }
+    if let Some(captures) = TIME.captures(head) {
+        return Some(Utc.from_utc_datetime(&NaiveDateTime::new(previous.naive_utc().date(), time(&captures[1])?)));
+    }
+    None
+}
+
+/// Streams the lines of local files and stdin, optionally following them as they grow.
+pub fn local_log_stream(sources: Vec<LocalSource>, follow: bool) -> (impl Stream<Item = LogStreamEvent>, Trigger) {
+    let (tx, rx) = tokio::sync::mpsc::channel::<LogStreamEvent>(1000);
+    let (trigger, tripwire) = Tripwire::new();
+    for source in sources {
+        let tx = tx.clone();
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = read_source(&source, follow, &tx) {
+                log::error!("Could not read {}: {}", source.name(), e);
+            }
+        });
+    }
+    (ReceiverStream::new(rx).take_until_if(tripwire), trigger)
+}
+
+fn read_source(source: &LocalSource, follow: bool, tx: &tokio::sync::mpsc::Sender<LogStreamEvent>) -> anyhow::Result<()> {
+    let mut identity = None;
+    let (mut reader, modified): (Box<dyn BufRead>, DateTime<Utc>) = match source {
+        LocalSource::File(path) => {
+            let file = File::open(path)?;
+            let metadata = file.metadata()?;
+            identity = file_identity(&metadata);
+            let modified = metadata.modified().map(DateTime::<Utc>::from).unwrap_or(Utc::now());
+            (Box::new(BufReader::new(file)), modified)
+        }
+        LocalSource::Stdin => (Box::new(BufReader::new(std::io::stdin())), Utc::now()),
+    };
+    let pod = source.name();
+    let mut parser = LocalLineParser::new(modified);
+    let mut position = 0u64;
+    let mut pending = vec![];
+    loop {
+        let read = reader.read_until(b'\n', &mut pending)?;
+        position += read as u64;
+        let complete = pending.ends_with(b"\n");
+        // Incomplete lines of followed files are completed by the next read
+        if complete || (read == 0 && !pending.is_empty() && !follow) {
+            let line = String::from_utf8_lossy(&pending).to_string();
+            pending.clear();
+            let line = parser.parse(&line);
+            let container = line.stream.clone().unwrap_or(source.container().to_string());
+            for text in split_oversized(line.text) {
+                let datum = LogData {
+                    text,
+                    pod: pod.clone(),
+                    container: container.clone(),
+                    timestamp: line.timestamp,
+                    previous: false,
+                    fields: None,
+                    severity: None,
+                    styles: vec![],
+                };
+                if tx.blocking_send(LogStreamEvent::Data(datum)).is_err() {
+                    return Ok(());
+                }
+            }
+            continue;
+        }
+        if read > 0 {
+            continue;
+        }
+
+        // End of input
+        if !follow || *source == LocalSource::Stdin {
+            return Ok(());
+        }
+        parser.set_following(true);
+        std::thread::sleep(FOLLOW_INTERVAL);
+        if tx.is_closed() {
+            return Ok(());
+        }
+        if let LocalSource::File(path) = source {
+            // Files replaced by a rotation are read from the start again, truncated files only if they are
+            // still shorter than the read part. Missing files are waited for, e.g. between rename and create.
+            if let Ok(metadata) = std::fs::metadata(path) {
+                if metadata.len() < position || file_identity(&metadata) != identity {
+                    let mut file = File::open(path)?;
+                    identity = file_identity(&file.metadata()?);
+                    file.seek(SeekFrom::Start(0))?;
+                    reader = Box::new(BufReader::new(file));
+                    position = 0;
+                    pending.clear();
+                }
+            }
+        }
+    }
+}
+
+/// Device and inode of a file, which change when a rotated file is replaced by a new one.
+#[cfg(unix)]
+fn file_identity(metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+/// File indexes are not available on stable Rust, only truncations are detected.
+#[cfg(not(unix))]
+fn file_identity(_metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Splits lines longer than the maximum line length like the lines of a log response,
+/// every part ends with a line break.
+fn split_oversized(text: String) -> Vec<String> {
+    let content_len = |text: &str| text.strip_suffix('\n').unwrap_or(text).len();
+    if content_len(&text) <= MAX_LINE_LENGTH {
+        return vec![text];
+    }
+    let mut parts = vec![];
+    let mut rest = text.as_str();
+    while content_len(rest) > MAX_LINE_LENGTH {
+        let mut split = MAX_LINE_LENGTH;
+        while !rest.is_char_boundary(split) {
+            split -= 1;
+        }
+        parts.push(format!("{}\n", &rest[..split]));
+        rest = &rest[split..];
+    }
+    parts.push(rest.to_string());
+    parts
+}
+
+#[test]
+pub fn test_timestamped_and_docker_lines() {
+    let fallback = Utc.ymd(2022, 1, 1).and_hms(0, 0, 0);
+
+    let mut parser = LocalLineParser::new(fallback);
+    let line = parser.parse("2022-03-01T10:00:00.123456789Z GET / 200\n");
+    assert_eq!(parser.format(), Some(LineFormat::Timestamped));
+    assert_eq!(line.timestamp, Utc.ymd(2022, 3, 1).and_hms_nano(10, 0, 0, 123456789));
+    assert_eq!(line.text, "GET / 200\n");
+    // Lines without a timestamp keep the previous time
+    assert_eq!(parser.parse("\tat a.B.c(B.java:1)\n").timestamp, line.timestamp);
+
+    let mut parser = LocalLineParser::new(fallback);
+    let line = parser.parse(r#"{"log":"started\n","stream":"stderr","time":"2022-03-01T10:00:01.5Z"}"#);
+    assert_eq!(parser.format(), Some(LineFormat::DockerJson));
+    assert_eq!(line.text, "started\n");
+    assert_eq!(line.stream.as_deref(), Some("stderr"));
+    assert_eq!(line.timestamp, Utc.ymd(2022, 3, 1).and_hms_milli(10, 0, 1, 500));
+}
+
+#[test]
+pub fn test_inferred_timestamps() {
+    let fallback = Utc.ymd(2022, 3, 1).and_hms(0, 0, 0);
+    let mut parser = LocalLineParser::new(fallback);
+    assert_eq!(parser.parse("starting\n").timestamp, fallback);
+    assert_eq!(parser.format(), Some(LineFormat::Plain));
+    assert_eq!(parser.parse("2022-03-01 10:00:00,250 INFO started\n").timestamp, Utc.ymd(2022, 3, 1).and_hms_milli(10, 0, 0, 250));
+    assert_eq!(parser.parse("[2022-03-01T11:00:00+01:00] offset\n").timestamp, Utc.ymd(2022, 3, 1).and_hms(10, 0, 0));
+    assert_eq!(parser.parse("I0301 10:00:02.000001 1 main.go:10] klog\n").timestamp, Utc.ymd(2022, 3, 1).and_hms_micro(10, 0, 2, 1));
+    assert_eq!(parser.parse("10.0.0.1 - - [01/Mar/2022:11:00:03 +0100] \"GET / HTTP/1.1\" 200 1\n").timestamp, Utc.ymd(2022, 3, 1).and_hms(10, 0, 3));
+    assert_eq!(parser.parse("10:00:04.5 [main] INFO app - time only\n").timestamp, Utc.ymd(2022, 3, 1).and_hms_milli(10, 0, 4, 500));
+    assert_eq!(parser.parse("no time").text, "no time\n");
+    assert_eq!(parser.parse("no time").timestamp, Utc.ymd(2022, 3, 1).and_hms_milli(10, 0, 4, 500));
+
+    let parts = split_oversized(format!("{}\n", "ü".repeat(MAX_LINE_LENGTH)));
+    assert_eq!(parts.len(), 2);
+    assert!(parts.iter().all(|part| part.ends_with('\n')));
+    assert_eq!(parts.concat().replace('\n', ""), "ü".repeat(MAX_LINE_LENGTH));
+}
+
+#[cfg(unix)]
+#[test]
+pub fn test_follow_rotated_file() -> anyhow::Result<()> {
+    let path = std::env::temp_dir().join(format!("ktail-{}.log", uuid::Uuid::new_v4()));
+    let rotated = path.with_extension("log.1");
+    std::fs::write(&path, "2022-03-01T10:00:00Z a\n")?;
+
+    let (tx, mut rx) = tokio::sync::mpsc::channel(10);
+    let source = LocalSource::File(path.clone());
+    let reader = std::thread::spawn(move || read_source(&source, true, &tx));
+    let rt = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    let mut next_text = move || match rt.block_on(async { tokio::time::timeout(Duration::from_secs(5), rx.recv()).await }) {
+        Ok(Some(LogStreamEvent::Data(datum))) => datum.text,
+        _ => String::new(),
+    };
+    assert_eq!(next_text(), "a\n");
+
+    // The new file is already longer than the read part of the rotated one
+    std::fs::rename(&path, &rotated)?;
+    std::fs::write(&path, "2022-03-01T10:00:01Z after rotation\n")?;
+    assert_eq!(next_text(), "after rotation\n");
+
+    drop(next_text);
+    reader.join().unwrap()?;
+    std::fs::remove_file(&path)?;
+    std::fs::remove_file(&rotated)?;
+    Ok(())
+}
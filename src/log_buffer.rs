@@ -0,0 +1,307 @@
+use std::collections::VecDeque;
+use chrono::{DateTime, TimeZone, Utc};
+use crate::log_filter::LineFilters;
+use crate::log_stream::LogData;
+
+/// All lines loaded into a log view, ordered by timestamp, along with the
+/// text buffer line offsets of the lines which pass the filters.
+/// The oldest lines are dropped once the line or byte budget is exceeded.
+pub struct LogBuffer {
+    lines: VecDeque<LogData>,
+    line_times: VecDeque<i64>,
+    entry_times: VecDeque<i64>,
+    filters: LineFilters,
+    /// Shown fields put the first line of structured lines into one text line
+    show_fields: bool,
+    shown: usize,
+    bytes: usize,
+    dropped: usize,
+    max_lines: usize,
+    max_bytes: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BufferStats {
+    pub shown: usize,
+    pub hidden: usize,
+    /// Lines dropped to stay within the budget
+    pub dropped: usize,
+}
+
+/// Lines dropped from the start of the buffer.
+pub struct Evicted {
+    pub dropped: usize,
+    /// Number of text buffer lines the dropped lines were shown in
+    pub text_lines: usize,
+    /// Dropped lines which were shown
+    pub data: Vec<LogData>,
+}
+
+impl LogBuffer {
+    pub fn new(max_lines: usize, max_bytes: usize) -> Self {
+        Self {
+            lines: VecDeque::new(),
+            line_times: VecDeque::new(),
+            entry_times: VecDeque::new(),
+            filters: LineFilters::default(),
+            show_fields: false,
+            shown: 0,
+            bytes: 0,
+            dropped: 0,
+            max_lines,
+            max_bytes,
+        }
+    }
+
+    /// Adds the line and returns the text buffer line it has to be inserted at, if it is shown.
+    pub fn push(&mut self, datum: LogData) -> Option<usize> {
+        let timestamp = datum.timestamp.timestamp_nanos();
+        let idx = self.line_times.partition_point(|t| *t <= timestamp);
+        self.line_times.insert(idx, timestamp);
+        self.bytes += datum.text.len();
+        let shown = self.filters.matches(&datum);
+        let offset = if shown {
+            Some(self.add_entry(&datum))
+        } else {
+            None
+        };
+        self.lines.insert(idx, datum);
+        offset
+    }
+
+    /// Takes effect with the next `set_filters`, which the log view calls after toggling the fields.
+    pub fn set_show_fields(&mut self, show_fields: bool) {
+        self.show_fields = show_fields;
+    }
+
+    /// Replaces the filters and returns the shown lines with their offsets in an empty text buffer.
+    pub fn set_filters(&mut self, filters: LineFilters) -> Vec<(i64, LogData)> {
+        self.filters = filters;
+        self.refilter()
+    }
+
+    /// Updates the fields of the lines, e.g. after another parser was selected,
+    /// and returns the shown lines like `set_filters`.
+    pub fn update_fields<F: FnMut(&mut LogData)>(&mut self, mut update: F) -> Vec<(i64, LogData)> {
+        for datum in self.lines.iter_mut() {
+            update(datum);
+        }
+        self.refilter()
+    }
+
+    fn refilter(&mut self) -> Vec<(i64, LogData)> {
+        self.entry_times.clear();
+        self.shown = 0;
+        let mut res = vec![];
+        for idx in 0..self.lines.len() {
+            if self.filters.matches(&self.lines[idx]) {
+                let datum = self.lines[idx].clone();
+                let offset = self.add_entry(&datum);
+                res.push((offset as i64, datum));
+            }
+        }
+        res
+    }
+
+    /// Drops the oldest lines until the buffer is within its budget again.
+    pub fn evict(&mut self) -> Evicted {
+        let mut evicted = Evicted {
+            dropped: 0,
+            text_lines: 0,
+            data: vec![],
+        };
+        while self.lines.len() > self.max_lines || (self.bytes > self.max_bytes && self.lines.len() > 1) {
+            if let Some(datum) = self.lines.pop_front() {
+                self.line_times.pop_front();
+                self.bytes -= datum.text.len();
+                self.dropped += 1;
+                evicted.dropped += 1;
+                if self.filters.matches(&datum) {
+                    let entries = entry_count(&datum, self.show_fields);
+                    self.entry_times.drain(..entries.min(self.entry_times.len()));
+                    self.shown -= 1;
+                    evicted.text_lines += entries;
+                    evicted.data.push(datum);
+                }
+            }
+        }
+        evicted
+    }
+
+    fn add_entry(&mut self, datum: &LogData) -> usize {
+        let timestamp = datum.timestamp.timestamp_nanos();
+        let offset = self.entry_times.partition_point(|t| *t <= timestamp);
+        for _ in 0..entry_count(datum, self.show_fields) {
+            self.entry_times.insert(offset, timestamp);
+        }
+        self.shown += 1;
+        offset
+    }
+
+    /// Lines which pass the filters, ordered by timestamp.
+    pub fn shown_lines(&self) -> impl Iterator<Item = &LogData> {
+        self.lines.iter().filter(move |datum| self.filters.matches(datum))
+    }
+
+    /// Timestamps of the text buffer lines.
+    pub fn entry_times(&self) -> &VecDeque<i64> {
+        &self.entry_times
+    }
+
+    /// Timestamp of the first shown line.
+    pub fn first_entry_time(&self) -> Option<DateTime<Utc>> {
+        self.entry_times.front().map(|t| Utc.timestamp_nanos(*t))
+    }
+
+    /// Text buffer line of the first entry at or after the timestamp.
+    pub fn offset_for_timestamp(&self, timestamp: i64) -> usize {
+        self.entry_times.partition_point(|t| *t < timestamp)
+    }
+
+    pub fn stats(&self) -> BufferStats {
+        BufferStats {
+            shown: self.shown,
+            hidden: self.lines.len() - self.shown,
+            dropped: self.dropped,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+        self.line_times.clear();
+        self.entry_times.clear();
+        self.shown = 0;
+        self.bytes = 0;
+        self.dropped = 0;
+    }
+}
+
+/// Number of text buffer lines a log line takes, multi-line events take one per line.
+fn entry_count(datum: &LogData, show_fields: bool) -> usize {
+    if show_fields && datum.fields.is_some() {
+        // The first line is shown as columns with escaped line breaks, followed by the continuation lines
+        return match datum.text.split_once('\n') {
+            Some((_, continuation)) if !continuation.is_empty() => 1 + text_lines(continuation),
+            _ => 1,
+        };
+    }
+    text_lines(&datum.text)
+}
+
+fn text_lines(text: &str) -> usize {
+    // Sourceview handles \r, \n and \r\n as line breaks
+    let mut count = 0;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\r' => {
+                chars.next_if_eq(&'\n');
+                count += 1;
+            }
+            '\n' => count += 1,
+            _ => {}
+        }
+    }
+    if !text.ends_with('\n') && !text.ends_with('\r') {
+        count += 1;
+    }
+    count
+}
+
+#[test]
+pub fn test_log_buffer_filters() -> anyhow::Result<()> {
+    use crate::log_filter::{FilterKind, LineFilter};
+
+    let mut buffer = LogBuffer::new(100, 1000);
+    assert_eq!(buffer.push(LogData::test("GET /b\n", 2)), Some(0));
+    assert_eq!(buffer.push(LogData::test("GET /health\n", 3)), Some(1));
+    assert_eq!(buffer.push(LogData::test("GET /a\n", 1)), Some(0));
+    assert_eq!(buffer.stats(), BufferStats { shown: 3, hidden: 0, dropped: 0 });
+
+    let mut filters = LineFilters::default();
+    filters.push(LineFilter::new(FilterKind::Exclude, "/health")?);
+    let shown = buffer.set_filters(filters);
+    let texts: Vec<(i64, &str)> = shown.iter().map(|(idx, d)| (*idx, d.text.as_str())).collect();
+    assert_eq!(texts, vec![(0, "GET /a\n"), (1, "GET /b\n")]);
+    assert_eq!(buffer.stats(), BufferStats { shown: 2, hidden: 1, dropped: 0 });
+
+    // New lines are filtered as well and get offsets between the shown lines
+    assert_eq!(buffer.push(LogData::test("GET /health\n", 0)), None);
+    assert_eq!(buffer.push(LogData::test("GET /c\n", 1)), Some(1));
+    assert_eq!(buffer.stats(), BufferStats { shown: 3, hidden: 2, dropped: 0 });
+    let texts: Vec<&str> = buffer.shown_lines().map(|d| d.text.as_str()).collect();
+    assert_eq!(texts, vec!["GET /a\n", "GET /c\n", "GET /b\n"]);
+
+    let shown = buffer.set_filters(LineFilters::default());
+    assert_eq!(shown.len(), 5);
+    assert_eq!(shown[0].1.text, "GET /health\n");
+    assert_eq!(buffer.entry_times().len(), 5);
+    Ok(())
+}
+
+#[test]
+pub fn test_log_buffer_eviction() -> anyhow::Result<()> {
+    use crate::log_filter::{FilterKind, LineFilter};
+
+    let mut buffer = LogBuffer::new(3, 1000);
+    let mut filters = LineFilters::default();
+    filters.push(LineFilter::new(FilterKind::Exclude, "debug")?);
+    buffer.set_filters(filters);
+
+    buffer.push(LogData::test("debug 1\n", 1));
+    buffer.push(LogData::test("\rinfo 2\n", 2));
+    buffer.push(LogData::test("info 3\n", 3));
+    assert_eq!(buffer.evict().dropped, 0);
+    assert_eq!(buffer.entry_times().len(), 3);
+
+    // The hidden line goes first and takes no text buffer lines with it
+    assert_eq!(buffer.push(LogData::test("info 4\n", 4)), Some(3));
+    let evicted = buffer.evict();
+    assert_eq!(evicted.dropped, 1);
+    assert_eq!(evicted.text_lines, 0);
+    assert_eq!(evicted.data.len(), 0);
+
+    assert_eq!(buffer.push(LogData::test("info 5\n", 5)), Some(4));
+    let evicted = buffer.evict();
+    assert_eq!(evicted.text_lines, 2);
+    assert_eq!(evicted.data[0].text, "\rinfo 2\n");
+    assert_eq!(buffer.entry_times().len(), 3);
+    assert_eq!(buffer.stats(), BufferStats { shown: 3, hidden: 0, dropped: 2 });
+
+    // Lines older than the buffer are dropped right away
+    assert_eq!(buffer.push(LogData::test("info 0\n", 0)), Some(0));
+    assert_eq!(buffer.evict().text_lines, 1);
+    assert_eq!(buffer.first_entry_time(), Some(LogData::test("", 3).timestamp));
+
+    let mut buffer = LogBuffer::new(100, 10);
+    buffer.push(LogData::test("12345\n", 1));
+    buffer.push(LogData::test("12345\n", 2));
+    assert_eq!(buffer.evict().dropped, 1);
+    assert_eq!(buffer.stats().dropped, 1);
+    Ok(())
+}
+
+#[test]
+pub fn test_entry_count() {
+    assert_eq!(entry_count(&LogData::test("info\n", 0), false), 1);
+    assert_eq!(entry_count(&LogData::test("\n", 0), false), 1);
+    assert_eq!(entry_count(&LogData::test("\r\n", 0), false), 1);
+    assert_eq!(entry_count(&LogData::test("\rinfo\n", 0), false), 2);
+    assert_eq!(entry_count(&LogData::test("info", 0), false), 1);
+    assert_eq!(entry_count(&LogData::test("error\n\tat a.B.c(B.java:1)\r\n\tat a.B.d(B.java:2)\n", 0), false), 3);
+    // Line breaks in the first line are escaped in the columns of shown fields
+    let structured = LogData::test("\r{\"level\":\"info\",\"msg\":\"started\"}\n", 0).with_json_fields();
+    assert_eq!(entry_count(&structured, false), 2);
+    assert_eq!(entry_count(&structured, true), 1);
+    let structured = LogData::test("{\"level\":\"error\",\"msg\":\"failed\"}\n\tat a.B.c(B.java:1)\r\n", 0).with_json_fields();
+    assert_eq!(entry_count(&structured, true), 2);
+    assert_eq!(entry_count(&LogData::test("info", 0), true), 1);
+
+    // Multi-line events are evicted with all of their lines
+    let mut buffer = LogBuffer::new(1, 1000);
+    buffer.push(LogData::test("error\n  frame 1\n  frame 2\n", 1));
+    assert_eq!(buffer.entry_times().len(), 3);
+    assert_eq!(buffer.push(LogData::test("info\n", 2)), Some(3));
+    assert_eq!(buffer.evict().text_lines, 3);
+    assert_eq!(buffer.entry_times().len(), 1);
+}
@@ -0,0 +1,178 @@
+use std::io::Write;
+use chrono::{DateTime, SecondsFormat, Utc};
+use regex::Regex;
+use serde_json::{json, Map, Value};
+use crate::log_stream::LogData;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    Plain,
+    JsonLines,
+    Html,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 3] = [ExportFormat::Plain, ExportFormat::JsonLines, ExportFormat::Html];
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            ExportFormat::Plain => "plain",
+            ExportFormat::JsonLines => "jsonl",
+            ExportFormat::Html => "html",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ExportFormat::Plain => "Plain text",
+            ExportFormat::JsonLines => "JSON Lines",
+            ExportFormat::Html => "HTML",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<ExportFormat> {
+        Self::ALL.iter().find(|f| f.id() == id).cloned()
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Plain => "log",
+            ExportFormat::JsonLines => "jsonl",
+            ExportFormat::Html => "html",
+        }
+    }
+}
+
+/// Highlighter colors kept in HTML exports.
+pub struct ExportHighlighter {
+    pub search: Regex,
+    pub background: String,
+    pub foreground: String,
+}
+
+/// Time range of exported lines, the end is exclusive.
+pub type ExportRange = (DateTime<Utc>, DateTime<Utc>);
+
+pub fn in_range(datum: &LogData, range: Option<&ExportRange>) -> bool {
+    range.map(|(from, to)| datum.timestamp >= *from && datum.timestamp < *to).unwrap_or(true)
+}
+
+/// Writes the lines in the format, returns the number of written lines.
+pub fn export<'a, W: Write, I: Iterator<Item = &'a LogData>>(
+    out: &mut W,
+    format: ExportFormat,
+    lines: I,
+    highlighters: &[ExportHighlighter],
+) -> anyhow::Result<usize> {
+    if format == ExportFormat::Html {
+        writeln!(out, "<!DOCTYPE html>")?;
+        writeln!(out, "<html><head><meta charset=\"utf-8\"><title>KTail export</title>")?;
+        writeln!(out, "<style>body {{ margin: 0; }} pre {{ font-family: monospace; margin: 0; padding: 4px; }} .line {{ white-space: pre-wrap; }}</style>")?;
+        writeln!(out, "</head><body><pre>")?;
+    }
+
+    let mut count = 0;
+    for datum in lines {
+        match format {
+            ExportFormat::Plain => write!(out, "{}", plain_line(datum))?,
+            ExportFormat::JsonLines => writeln!(out, "{}", json_line(datum))?,
+            ExportFormat::Html => {
+                let line = html_escape(plain_line(datum).trim_end_matches(|c| c == '\r' || c == '\n'));
+                // Like in the log view the last matching highlighter wins
+                match highlighters.iter().filter(|h| h.search.is_match(&datum.text)).last() {
+                    Some(h) => writeln!(out, "<div class=\"line\" style=\"background: {}; color: {};\">{}</div>", h.background, h.foreground, line)?,
+                    None => writeln!(out, "<div class=\"line\">{}</div>", line)?,
+                }
+            }
+        }
+        count += 1;
+    }
+
+    if format == ExportFormat::Html {
+        writeln!(out, "</pre></body></html>")?;
+    }
+    out.flush()?;
+    Ok(count)
+}
+
+fn timestamp(datum: &LogData) -> String {
+    datum.timestamp.to_rfc3339_opts(SecondsFormat::Nanos, true)
+}
+
+fn plain_line(datum: &LogData) -> String {
+    let previous = if datum.previous { " [previous]" } else { "" };
+    let mut line = format!("{} {} {}{} {}", timestamp(datum), datum.pod, datum.container, previous, datum.text);
+    if !line.ends_with('\n') {
+        line.push('\n');
+    }
+    line
+}
+
+fn json_line(datum: &LogData) -> Value {
+    let mut line = json!({
+        "timestamp": timestamp(datum),
+        "pod": datum.pod,
+        "container": datum.container,
+        "text": datum.text.trim_end_matches(|c| c == '\r' || c == '\n'),
+    });
+    if datum.previous {
+        line["previous"] = Value::Bool(true);
+    }
+    if let Some(fields) = datum.fields.as_ref() {
+        let fields: Map<String, Value> = fields.iter()
+            .map(|(k, v)| (k.clone(), Value::String(v.clone())))
+            .collect();
+        line["fields"] = Value::Object(fields);
+    }
+    line
+}
+
+fn html_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[test]
+pub fn test_export() -> anyhow::Result<()> {
+    use chrono::TimeZone;
+
+    let lines = vec![
+        LogData::test("started\n", 1),
+        LogData::test("{\"level\":\"error\",\"msg\":\"<failed>\"}\n", 2).with_json_fields(),
+        LogData::test("done\n", 3),
+    ];
+
+    let mut out = vec![];
+    assert_eq!(export(&mut out, ExportFormat::Plain, lines.iter(), &[])?, 3);
+    assert_eq!(String::from_utf8(out)?.lines().next(), Some("2022-01-01T10:00:01.000000000Z pod app started"));
+
+    let mut out = vec![];
+    let range = (Utc.ymd(2022, 1, 1).and_hms(10, 0, 2), Utc.ymd(2022, 1, 1).and_hms(10, 0, 3));
+    export(&mut out, ExportFormat::JsonLines, lines.iter().filter(|d| in_range(d, Some(&range))), &[])?;
+    let exported: Vec<Value> = String::from_utf8(out)?.lines().map(|l| serde_json::from_str(l)).collect::<Result<_, _>>()?;
+    assert_eq!(exported.len(), 1);
+    assert_eq!(exported[0]["timestamp"], "2022-01-01T10:00:02.000000000Z");
+    assert_eq!(exported[0]["fields"]["level"], "error");
+    assert_eq!(exported[0]["text"], "{\"level\":\"error\",\"msg\":\"<failed>\"}");
+
+    let mut out = vec![];
+    let highlighters = vec![ExportHighlighter {
+        search: Regex::new("error")?,
+        background: "rgb(244,94,94)".to_string(),
+        foreground: "rgb(0,0,0)".to_string(),
+    }];
+    export(&mut out, ExportFormat::Html, lines.iter(), &highlighters)?;
+    let html = String::from_utf8(out)?;
+    assert!(html.contains("<div class=\"line\" style=\"background: rgb(244,94,94); color: rgb(0,0,0);\">2022-01-01T10:00:02.000000000Z pod app {&quot;level&quot;:&quot;error&quot;,&quot;msg&quot;:&quot;&lt;failed&gt;&quot;}</div>"));
+    assert!(html.contains("<div class=\"line\">2022-01-01T10:00:03.000000000Z pod app done</div>"));
+    Ok(())
+}
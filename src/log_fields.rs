@@ -0,0 +1,336 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_json::{Map, Value};
+
+pub const LEVEL: &'static str = "level";
+pub const MSG: &'static str = "msg";
+pub const LOGGER: &'static str = "logger";
+pub const TRACE_ID: &'static str = "trace_id";
+
+/// Keys used by common logging libraries for the well-known fields.
+const WELL_KNOWN_FIELDS: [(&str, &[&str]); 4] = [
+    (LEVEL, &["level", "severity", "lvl", "loglevel", "levelname", "log.level"]),
+    (MSG, &["msg", "message"]),
+    (LOGGER, &["logger", "logger_name", "loggerName", "log.logger"]),
+    (TRACE_ID, &["trace_id", "traceId", "traceID", "trace.id", "dd.trace_id"]),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Eq, Ord)]
+pub enum Severity {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+    Fatal,
+}
+
+impl Severity {
+    pub const ALL: [Severity; 6] = [Severity::Trace, Severity::Debug, Severity::Info, Severity::Warn, Severity::Error, Severity::Fatal];
+
+    /// Parses level names like `WARNING` or `err` and numeric bunyan/pino levels.
+    pub fn parse(level: &str) -> Option<Self> {
+        let level = level.trim().to_lowercase();
+        let severity = match level.as_str() {
+            "trace" | "finest" | "10" => Severity::Trace,
+            "debug" | "dbg" | "fine" | "20" => Severity::Debug,
+            "info" | "information" | "notice" | "30" => Severity::Info,
+            "warn" | "warning" | "40" => Severity::Warn,
+            "error" | "err" | "severe" | "50" => Severity::Error,
+            "fatal" | "critical" | "crit" | "panic" | "emerg" | "alert" | "60" => Severity::Fatal,
+            _ => return None,
+        };
+        Some(severity)
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().find(|s| s.name() == name).cloned()
+    }
+
+    /// Color of the level in the overview.
+    pub fn color(&self) -> (u8, u8, u8) {
+        match self {
+            Severity::Trace => (150, 150, 150),
+            Severity::Debug => (110, 150, 200),
+            Severity::Info => (90, 170, 90),
+            Severity::Warn => (207, 111, 57),
+            Severity::Error => (244, 94, 94),
+            Severity::Fatal => (170, 60, 200),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Severity::Trace => "TRACE",
+            Severity::Debug => "DEBUG",
+            Severity::Info => "INFO",
+            Severity::Warn => "WARN",
+            Severity::Error => "ERROR",
+            Severity::Fatal => "FATAL",
+        }
+    }
+}
+
+/// Level words are only taken into account at the start of unstructured lines,
+/// later they are more likely part of the message, e.g. `retrying after error`.
+const SEVERITY_SEARCH_LEN: usize = 80;
+
+/// Detects the level of unstructured lines, e.g. `2022-01-02 10:00:00 ERROR ...`, `[warn] ...` or klog `E0102 ...`.
+pub fn detect_severity(text: &str) -> Option<Severity> {
+    static LEVEL_REGEX: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(r"(?:^|[\s\[(|:])(TRACE|DEBUG|INFO|NOTICE|WARN|WARNING|ERROR|FATAL|CRITICAL|PANIC|(?i:\[(?:trace|debug|info|notice|warn|warning|error|err|fatal|crit|critical)\]))(?:$|[\s\])|:])")
+            .expect("Level regex should be valid")
+    });
+    static KLOG_REGEX: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(r"^([IWEF])\d{4} \d{2}:\d{2}:\d{2}").expect("Klog level regex should be valid")
+    });
+
+    if let Some(level) = KLOG_REGEX.captures(text).and_then(|c| c.get(1)) {
+        return match level.as_str() {
+            "I" => Some(Severity::Info),
+            "W" => Some(Severity::Warn),
+            "E" => Some(Severity::Error),
+            _ => Some(Severity::Fatal),
+        };
+    }
+    let end = text.char_indices().nth(SEVERITY_SEARCH_LEN).map(|(idx, _)| idx).unwrap_or(text.len());
+    let level = LEVEL_REGEX.captures(&text[..end])?.get(1)?.as_str();
+    Severity::parse(level.trim_matches(|c| c == '[' || c == ']'))
+}
+
+/// Structured fields extracted from a log line.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LogFields {
+    fields: BTreeMap<String, String>,
+}
+
+impl LogFields {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields.get(name).map(|v| v.as_str())
+    }
+
+    pub fn insert(&mut self, name: &str, value: String) {
+        self.fields.insert(name.to_string(), value);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.fields.iter()
+    }
+
+    pub fn message(&self) -> Option<&str> {
+        self.get(MSG)
+    }
+
+    pub fn severity(&self) -> Option<Severity> {
+        self.get(LEVEL).and_then(Severity::parse)
+    }
+}
+
+const FIELD_COLUMN_WIDTH: usize = 20;
+
+/// Formats the chosen fields as fixed width columns followed by the message,
+/// line breaks within the message are escaped to keep one line per entry.
+pub fn format_columns(fields: &LogFields, columns: &[String], text: &str) -> String {
+    let mut line = String::new();
+    for column in columns {
+        if column == LEVEL {
+            let level = fields.severity().map(|s| s.name()).or(fields.get(LEVEL)).unwrap_or("-");
+            line.push_str(&format!("{:<5} ", level));
+        } else {
+            let value = fields.get(column).unwrap_or("-");
+            let value = if value.chars().count() > FIELD_COLUMN_WIDTH {
+                format!("{}…", value.chars().take(FIELD_COLUMN_WIDTH - 1).collect::<String>())
+            } else {
+                value.to_string()
+            };
+            line.push_str(&format!("{:<width$} ", value, width = FIELD_COLUMN_WIDTH));
+        }
+    }
+    let message = fields.message().unwrap_or(text.trim_end());
+    line.push_str(&message.replace('\n', "\\n").replace('\r', "\\r"));
+    line.push('\n');
+    line
+}
+
+/// Extracts the well-known and the additional fields if the line is a JSON object.
+pub fn parse_json_fields(text: &str, additional_fields: &[String]) -> Option<LogFields> {
+    let text = text.trim();
+    if !text.starts_with('{') {
+        return None;
+    }
+    let object: Map<String, Value> = serde_json::from_str(text).ok()?;
+    Some(extract_fields(|key| lookup(&object, key), additional_fields))
+}
+
+/// Collects the well-known fields by their common keys and the additional fields.
+pub fn extract_fields<F: Fn(&str) -> Option<String>>(lookup: F, additional_fields: &[String]) -> LogFields {
+    let mut fields = LogFields::default();
+    for (name, keys) in WELL_KNOWN_FIELDS {
+        if let Some(value) = keys.iter().find_map(|key| lookup(key)) {
+            fields.insert(name, value);
+        }
+    }
+    for name in additional_fields {
+        if let Some(value) = lookup(name) {
+            fields.insert(name, value);
+        }
+    }
+    fields
+}
+
+/// Looks up the key, dotted keys are looked up in nested objects if there is no such key.
+fn lookup(object: &Map<String, Value>, key: &str) -> Option<String> {
+    let value = object.get(key).or_else(|| {
+        let mut parts = key.split('.');
+        let mut value = object.get(parts.next()?)?;
+        for part in parts {
+            value = value.as_object()?.get(part)?;
+        }
+        Some(value)
+    })?;
+    match value {
+        Value::Null => None,
+        Value::String(s) => Some(s.clone()),
+        v => Some(v.to_string()),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FieldOp {
+    Eq,
+    NotEq,
+    Ge,
+    Le,
+    Gt,
+    Lt,
+    Matches,
+}
+
+/// Filter on a structured field like `level>=warn`, `trace_id=abc` or `logger~^com\.acme`.
+#[derive(Clone, Debug)]
+pub struct FieldFilter {
+    name: String,
+    op: FieldOp,
+    value: String,
+    regex: Option<Regex>,
+}
+
+impl FromStr for FieldFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Two character operators first
+        let ops = [(">=", FieldOp::Ge), ("<=", FieldOp::Le), ("!=", FieldOp::NotEq), ("=", FieldOp::Eq), (">", FieldOp::Gt), ("<", FieldOp::Lt), ("~", FieldOp::Matches)];
+        let (pos, op_str, op) = ops.iter()
+            .filter_map(|(op_str, op)| s.find(op_str).map(|pos| (pos, *op_str, *op)))
+            .min_by_key(|(pos, op_str, _)| (*pos, -(op_str.len() as i32)))
+            .ok_or(anyhow::anyhow!("Expected a field filter like level>=warn or trace_id=abc"))?;
+        let name = s[..pos].trim().to_string();
+        let value = s[pos + op_str.len()..].trim().to_string();
+        if name.is_empty() {
+            return Err(anyhow::anyhow!("Missing field name in {}", s));
+        }
+        let regex = if op == FieldOp::Matches {
+            Some(Regex::new(&value)?)
+        } else {
+            None
+        };
+        if name == LEVEL && op != FieldOp::Matches && Severity::parse(&value).is_none() {
+            return Err(anyhow::anyhow!("Unknown level {}", value));
+        }
+        Ok(Self { name, op, value, regex })
+    }
+}
+
+impl FieldFilter {
+    /// Lines without the field never match.
+    pub fn matches(&self, fields: Option<&LogFields>) -> bool {
+        let value = match fields.and_then(|f| f.get(&self.name)) {
+            Some(value) => value,
+            None => return false,
+        };
+        if let Some(regex) = &self.regex {
+            return regex.is_match(value);
+        }
+        let ordering = if self.name == LEVEL {
+            Severity::parse(value).zip(Severity::parse(&self.value)).map(|(a, b)| a.cmp(&b))
+        } else if let (Ok(a), Ok(b)) = (value.parse::<f64>(), self.value.parse::<f64>()) {
+            a.partial_cmp(&b)
+        } else {
+            Some(value.cmp(self.value.as_str()))
+        };
+        match (self.op, ordering) {
+            (FieldOp::Eq, Some(o)) => o == Ordering::Equal,
+            (FieldOp::NotEq, Some(o)) => o != Ordering::Equal,
+            (FieldOp::Ge, Some(o)) => o != Ordering::Less,
+            (FieldOp::Le, Some(o)) => o != Ordering::Greater,
+            (FieldOp::Gt, Some(o)) => o == Ordering::Greater,
+            (FieldOp::Lt, Some(o)) => o == Ordering::Less,
+            _ => false,
+        }
+    }
+}
+
+#[test]
+pub fn test_parse_json_fields() {
+    let line = r#"{"level":"WARNING","message":"disk almost full","logger":"io.disk","trace":{"id":"abc"},"free_mb":12,"ctx":{"user":"bob"}}"#;
+    let fields = parse_json_fields(line, &vec!["free_mb".to_string(), "ctx.user".to_string()]).unwrap();
+    assert_eq!(fields.get(LEVEL), Some("WARNING"));
+    assert_eq!(fields.severity(), Some(Severity::Warn));
+    assert_eq!(fields.message(), Some("disk almost full"));
+    assert_eq!(fields.get(LOGGER), Some("io.disk"));
+    assert_eq!(fields.get(TRACE_ID), Some("abc"));
+    assert_eq!(fields.get("free_mb"), Some("12"));
+    assert_eq!(fields.get("ctx.user"), Some("bob"));
+
+    assert!(parse_json_fields("plain text {\"level\":\"info\"}", &[]).is_none());
+    assert!(parse_json_fields("{not json", &[]).is_none());
+}
+
+#[test]
+pub fn test_detect_severity() {
+    assert_eq!(detect_severity("2022-01-02 10:00:00.123 ERROR 1 --- [main] Failed\n"), Some(Severity::Error));
+    assert_eq!(detect_severity("[warn] disk almost full\n"), Some(Severity::Warn));
+    assert_eq!(detect_severity("10:00:00 [Info] started\n"), Some(Severity::Info));
+    assert_eq!(detect_severity("W0102 15:04:05.123456 1 main.go:1] slow\n"), Some(Severity::Warn));
+    assert_eq!(detect_severity("retrying after error\n"), None);
+    assert_eq!(detect_severity("ERRORS: none\n"), None);
+    assert_eq!(detect_severity(&format!("{} ERROR late\n", "x".repeat(100))), None);
+}
+
+#[test]
+pub fn test_format_columns() {
+    let fields = parse_json_fields(r#"{"level":"warning","msg":"line 1\nline 2","logger":"com.example.service.Accounts"}"#, &[]).unwrap();
+    let columns = vec![LEVEL.to_string(), LOGGER.to_string(), TRACE_ID.to_string()];
+    assert_eq!(
+        format_columns(&fields, &columns, ""),
+        "WARN  com.example.service… -                    line 1\\nline 2\n"
+    );
+}
+
+#[test]
+pub fn test_field_filters() -> anyhow::Result<()> {
+    let fields = parse_json_fields(r#"{"level":"error","trace_id":"abc","status":404,"logger":"com.acme.Api"}"#, &vec!["status".to_string()]);
+    let fields = fields.as_ref();
+    assert!(FieldFilter::from_str("level>=warn")?.matches(fields));
+    assert!(!FieldFilter::from_str("level<warn")?.matches(fields));
+    assert!(FieldFilter::from_str("trace_id=abc")?.matches(fields));
+    assert!(!FieldFilter::from_str("trace_id!=abc")?.matches(fields));
+    assert!(FieldFilter::from_str("status>=400")?.matches(fields));
+    assert!(!FieldFilter::from_str("status>1000")?.matches(fields));
+    assert!(FieldFilter::from_str(r"logger~^com\.acme")?.matches(fields));
+    assert!(!FieldFilter::from_str("user=bob")?.matches(fields));
+    assert!(!FieldFilter::from_str("level>=warn")?.matches(None));
+
+    assert!(FieldFilter::from_str("level>=loud").is_err());
+    assert!(FieldFilter::from_str("no operator").is_err());
+    assert!(FieldFilter::from_str("=abc").is_err());
+    Ok(())
+}
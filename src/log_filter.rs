@@ -0,0 +1,160 @@
+use std::str::FromStr;
+use regex::Regex;
+use serde::{Serialize, Deserialize};
+use crate::log_fields::{FieldFilter, Severity};
+use crate::log_stream::LogData;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FilterKind {
+    Include,
+    Exclude,
+    /// Filter on a structured field, e.g. `level>=warn`
+    Field,
+}
+
+impl FilterKind {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            FilterKind::Include => "+",
+            FilterKind::Exclude => "−",
+            FilterKind::Field => "⊃",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Matcher {
+    Text(Regex),
+    Field(FieldFilter),
+}
+
+#[derive(Clone, Debug)]
+pub struct LineFilter {
+    pub kind: FilterKind,
+    pub pattern: String,
+    matcher: Matcher,
+}
+
+impl LineFilter {
+    pub fn new(kind: FilterKind, pattern: &str) -> anyhow::Result<Self> {
+        let matcher = match kind {
+            FilterKind::Field => Matcher::Field(FieldFilter::from_str(pattern)?),
+            _ => Matcher::Text(Regex::new(pattern)?),
+        };
+        Ok(Self {
+            kind,
+            pattern: pattern.to_string(),
+            matcher,
+        })
+    }
+
+    fn is_match(&self, datum: &LogData) -> bool {
+        match &self.matcher {
+            Matcher::Text(regex) => regex.is_match(&datum.text),
+            Matcher::Field(filter) => filter.matches(datum.fields.as_ref()),
+        }
+    }
+}
+
+/// Stacked line filters, a line is shown if it matches every include and field filter and none of the exclude filters.
+/// With a minimum level lines without a detected level are hidden.
+#[derive(Clone, Debug, Default)]
+pub struct LineFilters {
+    filters: Vec<LineFilter>,
+    min_severity: Option<Severity>,
+}
+
+impl LineFilters {
+    pub fn push(&mut self, filter: LineFilter) {
+        self.filters.push(filter);
+    }
+
+    pub fn remove(&mut self, idx: usize) {
+        if idx < self.filters.len() {
+            self.filters.remove(idx);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.filters.clear();
+    }
+
+    pub fn set_min_severity(&mut self, min_severity: Option<Severity>) {
+        self.min_severity = min_severity;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty() && self.min_severity.is_none()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &LineFilter> {
+        self.filters.iter()
+    }
+
+    pub fn patterns(&self, kind: FilterKind) -> Vec<String> {
+        self.filters.iter()
+            .filter(|f| f.kind == kind)
+            .map(|f| f.pattern.clone())
+            .collect()
+    }
+
+    pub fn matches(&self, datum: &LogData) -> bool {
+        if let Some(min_severity) = self.min_severity {
+            if !datum.severity.map(|s| s >= min_severity).unwrap_or(false) {
+                return false;
+            }
+        }
+        self.filters.iter().all(|f| match f.kind {
+            FilterKind::Exclude => !f.is_match(datum),
+            _ => f.is_match(datum),
+        })
+    }
+}
+
+#[test]
+pub fn test_line_filters() -> anyhow::Result<()> {
+    let mut filters = LineFilters::default();
+    assert!(filters.matches(&LogData::test("anything", 0)));
+
+    filters.push(LineFilter::new(FilterKind::Include, "GET|POST")?);
+    filters.push(LineFilter::new(FilterKind::Include, "/api/")?);
+    filters.push(LineFilter::new(FilterKind::Exclude, "/api/health")?);
+    assert!(filters.matches(&LogData::test("GET /api/users 200", 0)));
+    assert!(filters.matches(&LogData::test("POST /api/orders 201", 0)));
+    assert!(!filters.matches(&LogData::test("GET /index.html 200", 0)));
+    assert!(!filters.matches(&LogData::test("GET /api/health 200", 0)));
+    assert!(!filters.matches(&LogData::test("DELETE /api/users 204", 0)));
+    assert_eq!(filters.patterns(FilterKind::Exclude), vec!["/api/health".to_string()]);
+
+    filters.remove(2);
+    assert!(filters.matches(&LogData::test("GET /api/health 200", 0)));
+    assert!(LineFilter::new(FilterKind::Include, "(").is_err());
+    Ok(())
+}
+
+#[test]
+pub fn test_field_line_filters() -> anyhow::Result<()> {
+    let mut filters = LineFilters::default();
+    filters.push(LineFilter::new(FilterKind::Field, "level>=warn")?);
+    filters.push(LineFilter::new(FilterKind::Exclude, "retry")?);
+    assert!(filters.matches(&LogData::test(r#"{"level":"error","msg":"failed"}"#, 0).with_json_fields()));
+    assert!(!filters.matches(&LogData::test(r#"{"level":"info","msg":"started"}"#, 0).with_json_fields()));
+    assert!(!filters.matches(&LogData::test(r#"{"level":"warn","msg":"retry"}"#, 0).with_json_fields()));
+    assert!(!filters.matches(&LogData::test("plain error line", 0)));
+    assert!(LineFilter::new(FilterKind::Field, "level").is_err());
+    Ok(())
+}
+
+#[test]
+pub fn test_min_severity_filter() {
+    let mut filters = LineFilters::default();
+    filters.set_min_severity(Some(Severity::Warn));
+    assert!(!filters.is_empty());
+    assert!(filters.matches(&LogData::test(r#"{"level":"error","msg":"failed"}"#, 0).with_json_fields()));
+    assert!(filters.matches(&LogData::test("10:00:00 WARN slow", 0).with_detected_severity()));
+    assert!(!filters.matches(&LogData::test("10:00:00 INFO started", 0).with_detected_severity()));
+    assert!(!filters.matches(&LogData::test("no level", 0).with_detected_severity()));
+    filters.set_min_severity(None);
+    assert!(filters.is_empty());
+}
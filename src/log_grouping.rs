@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use crate::ansi::StyleSpan;
+use crate::log_stream::LogData;
+
+/// Events are cut off after this many lines to keep runaway output from piling up.
+const MAX_EVENT_LINES: usize = 1000;
+
+const CONTINUATION_PREFIXES: [&str; 6] = [
+    "Caused by:",
+    "Suppressed:",
+    "... ",
+    "Traceback (most recent call last):",
+    "During handling of the above exception",
+    "The above exception was the direct cause",
+];
+
+/// Tells whether a line continues the previous one, e.g. the frames of a stack trace.
+pub struct ContinuationDetector {
+    patterns: Vec<Regex>,
+}
+
+/// Python tracebacks end with an unindented exception line.
+#[derive(Clone, Copy, Default)]
+struct TracebackState {
+    seen: bool,
+    open: bool,
+}
+
+impl TracebackState {
+    fn update(&mut self, line: &str) {
+        if line.trim_start().starts_with("Traceback (most recent call last):") {
+            self.seen = true;
+            self.open = true;
+        } else if self.open && !is_indented(line) {
+            self.open = false;
+        }
+    }
+}
+
+fn is_indented(line: &str) -> bool {
+    line.starts_with(' ') || line.starts_with('\t')
+}
+
+impl ContinuationDetector {
+    /// Lines matching one of the patterns are continuations as well.
+    pub fn new(patterns: &[String]) -> Self {
+        let patterns = patterns.iter().filter_map(|pattern| match Regex::new(pattern) {
+            Ok(regex) => Some(regex),
+            Err(e) => {
+                log::warn!("Invalid continuation pattern {}: {}", pattern, e);
+                None
+            }
+        }).collect();
+        Self { patterns }
+    }
+
+    fn is_continuation(&self, text: &str, traceback: TracebackState) -> bool {
+        static JAVA_FRAME: Lazy<Regex> = Lazy::new(|| {
+            Regex::new(r"^at [\w$./<>]+\(").expect("Java frame regex should be valid")
+        });
+
+        let line = text.trim_end_matches(|c| c == '\r' || c == '\n');
+        if line.trim().is_empty() {
+            // Python separates chained exceptions by empty lines
+            return traceback.seen;
+        }
+        if is_indented(line) || traceback.open {
+            return true;
+        }
+        CONTINUATION_PREFIXES.iter().any(|prefix| line.starts_with(prefix))
+            || JAVA_FRAME.is_match(line)
+            || self.patterns.iter().any(|p| p.is_match(line))
+    }
+}
+
+struct PendingEvent {
+    datum: LogData,
+    lines: usize,
+    traceback: TracebackState,
+    updated: Instant,
+}
+
+/// Groups the lines of each container into events.
+/// An event is complete once the next event of the container starts or no more lines arrived for a while.
+pub struct EventGrouper {
+    detector: ContinuationDetector,
+    pending: HashMap<(String, String, bool), PendingEvent>,
+}
+
+impl EventGrouper {
+    pub fn new(detector: ContinuationDetector) -> Self {
+        Self {
+            detector,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Returns the previous event of the container if the line starts a new one.
+    pub fn push(&mut self, datum: LogData, now: Instant) -> Option<LogData> {
+        let key = (datum.pod.clone(), datum.container.clone(), datum.previous);
+        if let Some(pending) = self.pending.get_mut(&key) {
+            if pending.lines < MAX_EVENT_LINES && self.detector.is_continuation(&datum.text, pending.traceback) {
+                pending.traceback.update(&datum.text);
+                if !datum.styles.is_empty() {
+                    let offset = pending.datum.text.chars().count();
+                    pending.datum.styles.extend(datum.styles.into_iter().map(|span| StyleSpan {
+                        start: span.start + offset,
+                        end: span.end + offset,
+                        style: span.style,
+                    }));
+                }
+                pending.datum.text.push_str(&datum.text);
+                pending.lines += 1;
+                pending.updated = now;
+                return None;
+            }
+        }
+
+        let mut traceback = TracebackState::default();
+        traceback.update(&datum.text);
+        let event = PendingEvent {
+            datum,
+            lines: 1,
+            traceback,
+            updated: now,
+        };
+        self.pending.insert(key, event).map(|p| p.datum)
+    }
+
+    /// Completes the events which did not get new lines within the timeout.
+    pub fn flush_idle(&mut self, now: Instant, timeout: Duration) -> Vec<LogData> {
+        let idle: Vec<(String, String, bool)> = self.pending.iter()
+            .filter(|(_, p)| now.duration_since(p.updated) >= timeout)
+            .map(|(key, _)| key.clone())
+            .collect();
+        idle.into_iter()
+            .filter_map(|key| self.pending.remove(&key))
+            .map(|p| p.datum)
+            .collect()
+    }
+
+    pub fn flush(&mut self) -> Vec<LogData> {
+        self.pending.drain().map(|(_, p)| p.datum).collect()
+    }
+}
+
+#[test]
+pub fn test_group_java_stack_trace() {
+    let mut grouper = EventGrouper::new(ContinuationDetector::new(&[]));
+    let now = Instant::now();
+    let lines = [
+        "10:00:00 ERROR Request failed",
+        "java.lang.IllegalStateException: boom",
+        "\tat com.acme.Api.handle(Api.java:42)",
+        "at com.acme.Server.run(Server.java:7)",
+        "Caused by: java.io.IOException: closed",
+        "\t... 12 more",
+    ];
+    assert!(grouper.push(LogData::test(&format!("{}\n", lines[0]), 0).with_container("api"), now).is_none());
+    // The exception line starts a new event, the frames belong to it
+    assert_eq!(grouper.push(LogData::test(&format!("{}\n", lines[1]), 0).with_container("api"), now).map(|d| d.text), Some(format!("{}\n", lines[0])));
+    for line in &lines[2..] {
+        assert!(grouper.push(LogData::test(&format!("{}\n", line), 0).with_container("api"), now).is_none());
+    }
+    // Lines of other containers don't interrupt the event
+    assert!(grouper.push(LogData::test("GET / 200\n", 0).with_container("proxy"), now).is_none());
+
+    let event = grouper.push(LogData::test("10:00:01 INFO next\n", 0).with_container("api"), now).unwrap();
+    assert_eq!(event.text, format!("{}\n", lines[1..].join("\n")));
+    assert_eq!(grouper.flush().len(), 2);
+}
+
+#[test]
+pub fn test_group_python_traceback() {
+    let mut grouper = EventGrouper::new(ContinuationDetector::new(&["^\\s*-{3,}$".to_string()]));
+    let now = Instant::now();
+    let lines = [
+        "ERROR:root:Job failed",
+        "Traceback (most recent call last):",
+        "  File \"job.py\", line 3, in <module>",
+        "    run()",
+        "KeyError: 'id'",
+        "",
+        "During handling of the above exception, another exception occurred:",
+        "",
+        "Traceback (most recent call last):",
+        "  File \"job.py\", line 5, in <module>",
+        "ValueError: boom",
+        "---",
+    ];
+    for line in &lines {
+        assert!(grouper.push(LogData::test(&format!("{}\n", line), 0).with_container("job"), now).is_none());
+    }
+    let event = grouper.push(LogData::test("INFO:root:Retrying\n", 0).with_container("job"), now).unwrap();
+    assert_eq!(event.text, format!("{}\n", lines.join("\n")));
+
+    assert!(grouper.flush_idle(now + Duration::from_millis(100), Duration::from_millis(200)).is_empty());
+    let idle = grouper.flush_idle(now + Duration::from_millis(300), Duration::from_millis(200));
+    assert_eq!(idle.len(), 1);
+    assert_eq!(idle[0].text, "INFO:root:Retrying\n");
+
+    // Empty lines outside of tracebacks are events of their own
+    grouper.push(LogData::test("done\n", 0).with_container("job"), now);
+    assert!(grouper.push(LogData::test("\n", 0).with_container("job"), now).is_some());
+}
@@ -0,0 +1,152 @@
+use chrono::{DateTime, Utc};
+
+/// Lines longer than this get split into multiple lines with the same timestamp.
+pub const MAX_LINE_LENGTH: usize = 64 * 1024;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecodedLine {
+    pub timestamp: DateTime<Utc>,
+    pub text: String,
+}
+
+/// Splits the chunks of a log response (`timestamps=true`) into `timestamp data\n` records.
+/// Incomplete records are kept until the rest of the record arrives with one of the next chunks.
+pub struct LogLineDecoder {
+    buffer: Vec<u8>,
+    max_line_length: usize,
+    // Timestamp of an oversized record which was split, the remaining data has no timestamp prefix.
+    continuation: Option<DateTime<Utc>>,
+}
+
+impl LogLineDecoder {
+    pub fn new(max_line_length: usize) -> Self {
+        Self {
+            buffer: vec![],
+            max_line_length,
+            continuation: None,
+        }
+    }
+
+    pub fn decode(&mut self, chunk: &[u8]) -> Vec<DecodedLine> {
+        let mut lines = vec![];
+        let mut search_from = self.buffer.len();
+        self.buffer.extend_from_slice(chunk);
+
+        let mut start = 0;
+        loop {
+            let line_break = self.buffer[search_from..].iter().position(|b| *b == b'\n')
+                .map(|pos| search_from + pos);
+
+            // Split records which exceed the maximum length, the line break is not counted
+            let end = line_break.unwrap_or(self.buffer.len());
+            while end - start > self.max_line_length {
+                let split = char_boundary(&self.buffer, start + self.max_line_length);
+                if let Some(line) = self.decode_record(start, split, false) {
+                    lines.push(line);
+                }
+                start = split;
+            }
+
+            match line_break {
+                Some(pos) => {
+                    if let Some(line) = self.decode_record(start, pos + 1, true) {
+                        lines.push(line);
+                    }
+                    start = pos + 1;
+                    search_from = start;
+                }
+                None => break,
+            }
+        }
+
+        self.buffer.drain(..start);
+        lines
+    }
+
+    fn decode_record(&mut self, start: usize, end: usize, complete: bool) -> Option<DecodedLine> {
+        let record = &self.buffer[start..end];
+        let (timestamp, data) = if let Some(timestamp) = self.continuation {
+            (timestamp, record)
+        } else {
+            let separator = record.iter().position(|b| *b == b' ');
+            let timestamp = separator
+                .and_then(|pos| std::str::from_utf8(&record[..pos]).ok())
+                .and_then(|ts| DateTime::parse_from_rfc3339(ts).ok())
+                .map(|dt| dt.with_timezone(&Utc));
+
+            match (timestamp, separator) {
+                (Some(timestamp), Some(pos)) => (timestamp, &record[pos + 1..]),
+                _ => {
+                    log::error!("Invalid log data without timestamp");
+                    return None;
+                }
+            }
+        };
+
+        let mut text = String::from_utf8_lossy(data).to_string();
+        if complete {
+            self.continuation.take();
+        } else {
+            self.continuation = Some(timestamp);
+            text.push('\n');
+        }
+        Some(DecodedLine { timestamp, text })
+    }
+}
+
+/// Moves the position back to the start of a UTF-8 sequence.
+fn char_boundary(data: &[u8], mut pos: usize) -> usize {
+    let min = pos.saturating_sub(3);
+    while pos > min && (data[pos] & 0xC0) == 0x80 {
+        pos -= 1;
+    }
+    pos
+}
+
+#[cfg(test)]
+fn decode_chunks(decoder: &mut LogLineDecoder, data: &[u8], splits: &[usize]) -> Vec<DecodedLine> {
+    let mut lines = vec![];
+    let mut start = 0;
+    for split in splits.iter().chain(std::iter::once(&data.len())) {
+        lines.append(&mut decoder.decode(&data[start..*split]));
+        start = *split;
+    }
+    lines
+}
+
+#[test]
+pub fn test_decode_arbitrary_chunks() {
+    let data = "2022-01-02T10:00:00.123456789Z first line\n\
+        2022-01-02T10:00:01.000000001Z zweite Zeile mit Ümläuten 🚀\n\
+        2022-01-02T10:00:02.5Z \n\
+        2022-01-02T10:00:03.000000000Z last\r\n";
+    let data = data.as_bytes();
+    let expected = decode_chunks(&mut LogLineDecoder::new(MAX_LINE_LENGTH), data, &[]);
+    assert_eq!(expected.iter().map(|l| l.text.as_str()).collect::<Vec<&str>>(),
+               vec!["first line\n", "zweite Zeile mit Ümläuten 🚀\n", "\n", "last\r\n"]);
+
+    for a in 0..data.len() {
+        for b in a..data.len() {
+            let lines = decode_chunks(&mut LogLineDecoder::new(MAX_LINE_LENGTH), data, &[a, b]);
+            assert_eq!(lines, expected, "split at {} and {}", a, b);
+        }
+    }
+
+    let byte_splits: Vec<usize> = (1..data.len()).collect();
+    assert_eq!(decode_chunks(&mut LogLineDecoder::new(MAX_LINE_LENGTH), data, &byte_splits), expected);
+}
+
+#[test]
+pub fn test_decode_oversized_lines() {
+    let data = "2022-01-02T10:00:00Z ääääää\n2022-01-02T10:00:01Z ok\n".as_bytes();
+    let max_line_length = 26;
+    let expected = decode_chunks(&mut LogLineDecoder::new(max_line_length), data, &[]);
+    assert_eq!(expected.iter().map(|l| l.text.as_str()).collect::<Vec<&str>>(),
+               vec!["ää\n", "ääää\n", "ok\n"]);
+    assert_eq!(expected[0].timestamp, expected[1].timestamp);
+
+    for split in 0..data.len() {
+        let lines = decode_chunks(&mut LogLineDecoder::new(max_line_length), data, &[split]);
+        assert_eq!(lines, expected, "split at {}", split);
+    }
+}
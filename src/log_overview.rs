@@ -9,8 +9,19 @@ use itertools::Itertools;
 use plotters::coord::ReverseCoordTranslate;
 
 enum WorkerData {
-    Timestamp(Vec<DateTime<Utc>>),
-    Highlight(HighlightResultData)
+    Timestamp(Vec<(DateTime<Utc>, Option<Severity>)>),
+    Highlight(HighlightResultData),
+    Evict(Vec<HighlightResultData>, Vec<(DateTime<Utc>, Severity)>, Option<DateTime<Utc>>),
+}
+
+/// Name of the series with the line count of the level.
+fn level_series(severity: Severity) -> String {
+    format!("level:{}", severity.name())
+}
+
+fn minute(timestamp: &DateTime<Utc>) -> DateTime<Utc> {
+    let time = timestamp.time();
+    Utc.ymd(timestamp.year(), timestamp.month(), timestamp.day()).and_hms(time.hour(), time.minute(), 0)
 }
 
 pub struct LogOverview {
@@ -25,6 +36,11 @@ pub struct ChartData {
     data: HashMap<String, HashMap<DateTime<Utc>, u32>>,
     click_pos: Option<(f64, f64)>,
     mouse_pos: Option<(f64, f64)>,
+    /// Start and current x of a dragged time range and whether the drag ended
+    drag: Option<(f64, f64, bool)>,
+    /// Selected time range in seconds
+    selection: Option<(i64, i64)>,
+    selection_changed: bool,
     series_styles: HashMap<String, SeriesStyle>,
 }
 
@@ -33,14 +49,30 @@ pub enum LogOverviewMsg {
     Redraw,
     Clear,
     HighlightResults(HighlightResultData),
-    LogData(Vec<DateTime<Utc>>),
+    LogData(Vec<(DateTime<Utc>, Option<Severity>)>),
+    /// Highlight results and levels of lines dropped from the log view and the timestamp of the oldest remaining line
+    Evicted(Vec<HighlightResultData>, Vec<(DateTime<Utc>, Severity)>, Option<DateTime<Utc>>),
     MouseClick((i64, u32)),
+    /// Time range selected by dragging, in seconds
+    RangeSelected(Option<(i64, i64)>),
+    SetHighlighters(Vec<Highlighter>),
 }
 
 struct SeriesStyle {
     pub color: plotters::style::RGBColor,
 }
 
+fn highlighter_style(highlighter: &Highlighter) -> SeriesStyle {
+    let color = &highlighter.color;
+    let parts = &mut color[4..color.len() - 1].split(",");
+    let r = parts.next().and_then(|p| p.trim().parse::<u8>().ok()).unwrap_or_default();
+    let g = parts.next().and_then(|p| p.trim().parse::<u8>().ok()).unwrap_or_default();
+    let b = parts.next().and_then(|p| p.trim().parse::<u8>().ok()).unwrap_or_default();
+    SeriesStyle {
+        color: plotters::style::RGBColor(r, g, b)
+    }
+}
+
 impl Component for LogOverview {
     type Msg = LogOverviewMsg;
     type View = gtk::DrawingArea;
@@ -48,22 +80,18 @@ impl Component for LogOverview {
 
     fn create<T: MsgHandler<Self::Msg> + Clone>(sender: T, _input: Option<Self::Input>) -> Self {
         let drawing_area = gtk::DrawingArea::new();
-        let series_styles: HashMap<String, SeriesStyle> = if let Ok(cfg) = CONFIG.lock() {
-            cfg.highlighters.iter().map(|h| {
-                let parts = &mut h.color[4..h.color.len() - 1].split(",");
-                let r = parts.next().and_then(|p| p.trim().parse::<u8>().ok()).unwrap_or_default();
-                let g = parts.next().and_then(|p| p.trim().parse::<u8>().ok()).unwrap_or_default();
-                let b = parts.next().and_then(|p| p.trim().parse::<u8>().ok()).unwrap_or_default();
-                let color = plotters::style::RGBColor(r, g, b);
-
-                (h.name.clone(), SeriesStyle {
-                    color
-                })
-            }).collect()
+        let mut series_styles: HashMap<String, SeriesStyle> = if let Ok(cfg) = CONFIG.lock() {
+            cfg.highlighters.iter().map(|h| (h.name.clone(), highlighter_style(h))).collect()
         } else {
             log::warn!("Could not get config lock");
             HashMap::new()
         };
+        for severity in Severity::ALL {
+            let (r, g, b) = severity.color();
+            series_styles.insert(level_series(severity), SeriesStyle {
+                color: plotters::style::RGBColor(r, g, b)
+            });
+        }
 
         let chart_data = Arc::new(Mutex::new(ChartData {
             start_date: None,
@@ -71,6 +99,9 @@ impl Component for LogOverview {
             data: HashMap::new(),
             click_pos: None,
             mouse_pos: None,
+            drag: None,
+            selection: None,
+            selection_changed: false,
             series_styles
         }));
 
@@ -105,19 +136,62 @@ impl Component for LogOverview {
         click.connect_released(move |_gesture, _p,x,y| {
             if let Ok(mut cd) = cd.lock() {
                 cd.click_pos = Some((x, y));
+                // A click clears the selected time range
+                if cd.selection.take().is_some() {
+                    cd.selection_changed = true;
+                }
                 tx(LogOverviewMsg::Redraw);
             }
         });
 
         drawing_area.add_controller(&click);
+
+        let drag = gtk::GestureDrag::new();
+        let cd = chart_data.clone();
+        drag.connect_drag_begin(move |_, x, _| {
+            if let Ok(mut cd) = cd.lock() {
+                cd.drag = Some((x, x, false));
+            }
+        });
+        let cd = chart_data.clone();
+        let tx = sender.clone();
+        drag.connect_drag_update(move |_, offset_x, _| {
+            if let Ok(mut cd) = cd.lock() {
+                if let Some((start, _, _)) = cd.drag {
+                    cd.drag = Some((start, start + offset_x, false));
+                    tx(LogOverviewMsg::Redraw);
+                }
+            }
+        });
+        let cd = chart_data.clone();
+        let tx = sender.clone();
+        drag.connect_drag_end(move |_, offset_x, _| {
+            if let Ok(mut cd) = cd.lock() {
+                if let Some((start, _, _)) = cd.drag {
+                    // Short drags are clicks
+                    cd.drag = if offset_x.abs() < MIN_DRAG_WIDTH {
+                        None
+                    } else {
+                        Some((start, start + offset_x, true))
+                    };
+                    tx(LogOverviewMsg::Redraw);
+                }
+            }
+        });
+        drawing_area.add_controller(&drag);
+
         let cd = chart_data.clone();
         let tx = sender.clone();
         drawing_area.set_draw_func(move |_, ctx, width, height| {
             if let Ok(mut cd) = cd.lock() {
-                if let Some((dt, val)) = draw(&*cd, &ctx, width, height) {
+                if let Some((dt, val)) = draw(&mut *cd, &ctx, width, height) {
                     tx(LogOverviewMsg::MouseClick((dt, val)));
                 }
                 cd.click_pos.take();
+                if cd.selection_changed {
+                    cd.selection_changed = false;
+                    tx(LogOverviewMsg::RangeSelected(cd.selection));
+                }
             }
         });
 
@@ -128,7 +202,7 @@ impl Component for LogOverview {
             while let Ok(data) = r.recv() {
                 match data {
                     WorkerData::Timestamp(data) => {
-                        for timestamp in data {
+                        for (timestamp, severity) in data {
                             if let Ok(mut chart_data) = cd.lock() {
                                 if let Some(ts) = chart_data.start_date {
                                     if timestamp < ts {
@@ -146,13 +220,16 @@ impl Component for LogOverview {
                                     chart_data.end_date.replace(timestamp);
                                 }
 
-                                let time = timestamp.time();
-                                let ts = Utc.ymd(timestamp.year(),timestamp.month(),timestamp.day()).and_hms(time.hour(), time.minute(), 0);
+                                let ts = minute(&timestamp);
                                 for (_, data) in chart_data.data.iter_mut() {
                                     if data.len() > 0 && !data.contains_key(&ts) {
                                         data.insert(ts.clone(), 0);
                                     }
                                 }
+                                if let Some(severity) = severity {
+                                    let series_data = chart_data.data.entry(level_series(severity)).or_insert(HashMap::new());
+                                    *series_data.entry(ts).or_insert(0) += 1;
+                                }
                             }
                         }
                         tx(LogOverviewMsg::Redraw);
@@ -174,6 +251,34 @@ impl Component for LogOverview {
                             tx(LogOverviewMsg::Redraw);
                         }
                     }
+                    WorkerData::Evict(results, levels, oldest) => {
+                        if let Ok(mut chart_data) = cd.lock() {
+                            let series = results.into_iter()
+                                .flat_map(|r| {
+                                    let timestamp = minute(&r.timestamp);
+                                    r.matching_highlighters.into_iter().map(move |name| (name, timestamp))
+                                })
+                                .chain(levels.into_iter().map(|(ts, severity)| (level_series(severity), minute(&ts))));
+                            for (name, timestamp) in series {
+                                if let Some(ts_count) = chart_data.data.get_mut(&name).and_then(|d| d.get_mut(&timestamp)) {
+                                    *ts_count = ts_count.saturating_sub(1);
+                                }
+                            }
+
+                            if let Some(oldest) = oldest {
+                                let first_bucket = minute(&oldest);
+                                for (_, data) in chart_data.data.iter_mut() {
+                                    data.retain(|ts, _| *ts >= first_bucket);
+                                }
+                                chart_data.start_date.replace(oldest);
+                            } else {
+                                chart_data.start_date.take();
+                                chart_data.end_date.take();
+                                chart_data.data.clear();
+                            }
+                            tx(LogOverviewMsg::Redraw);
+                        }
+                    }
                 }
             }
         });
@@ -195,6 +300,7 @@ impl Component for LogOverview {
                     cd.start_date.take();
                     cd.end_date.take();
                     cd.data.clear();
+                    cd.selection.take();
                     self.drawing_area.queue_draw();
                 }
             }
@@ -208,7 +314,20 @@ impl Component for LogOverview {
                     log::error!("Failed to send worker data: {}", e);
                 }
             }
+            LogOverviewMsg::Evicted(results, levels, oldest) => {
+                if let Err(e) = self.worker.send(WorkerData::Evict(results, levels, oldest)) {
+                    log::error!("Failed to send worker data: {}", e);
+                }
+            }
+            LogOverviewMsg::SetHighlighters(highlighters) => {
+                if let Ok(mut cd) = self.chart_data.lock() {
+                    for highlighter in &highlighters {
+                        cd.series_styles.insert(highlighter.name.clone(), highlighter_style(highlighter));
+                    }
+                }
+            }
             LogOverviewMsg::MouseClick(_) => {}
+            LogOverviewMsg::RangeSelected(_) => {}
         }
 
         Command::None
@@ -221,7 +340,8 @@ impl Component for LogOverview {
 
 use plotters::prelude::*;
 use plotters_cairo::CairoBackend;
-use crate::config::{CONFIG};
+use crate::config::{CONFIG, Highlighter};
+use crate::log_fields::Severity;
 use crate::log_view::{HighlightResultData};
 
 
@@ -231,9 +351,17 @@ const MARGIN_TOP: i32 = 15;
 const MARGIN_LEFT: i32 = 10;
 const LINE_HEIGHT: i32 = 2;
 const X_START: f64 = (Y_LABEL_AREA_SIZE + MARGIN_LEFT) as f64;
+const MIN_DRAG_WIDTH: f64 = 3.0;
+
+fn draw_selection(root: &DrawingArea<CairoBackend<'_>, plotters::coord::Shift>, x1: i32, x2: i32, height: i32) {
+    let rect = Rectangle::new([(x1.min(x2), MARGIN_TOP), (x1.max(x2), height - X_LABEL_AREA_SIZE)], BLUE.mix(0.15).filled());
+    if let Err(e) = root.draw(&rect) {
+        log::error!("Could not draw selection: {}", e);
+    }
+}
 
 fn draw(
-    chart_data: &ChartData,
+    chart_data: &mut ChartData,
     ctx: &gtk::cairo::Context, width: i32, height: i32) -> Option<(i64, u32)>
 {
     let root = CairoBackend::new(ctx, (width as u32, height as u32)).unwrap().into_drawing_area();
@@ -284,6 +412,29 @@ fn draw(
             }
         }
 
+        if let Some((x1, x2, done)) = chart_data.drag {
+            if done {
+                // The dragged range is clamped to the plotting area
+                let (x_range, y_range) = chart.plotting_area().get_pixel_range();
+                let clamp = |x: f64| (x as i32).max(x_range.start).min(x_range.end - 1);
+                let from = chart.as_coord_spec().reverse_translate((clamp(x1.min(x2)), y_range.start));
+                let to = chart.as_coord_spec().reverse_translate((clamp(x1.max(x2)), y_range.start));
+                if let (Some((from, _)), Some((to, _))) = (from, to) {
+                    chart_data.selection = Some((from, to));
+                    chart_data.selection_changed = true;
+                }
+                chart_data.drag.take();
+            } else {
+                draw_selection(&root, x1 as i32, x2 as i32, height);
+            }
+        }
+
+        if let Some((from, to)) = chart_data.selection {
+            let (x1, _) = chart.backend_coord(&(from, 0));
+            let (x2, _) = chart.backend_coord(&(to, 0));
+            draw_selection(&root, x1, x2, height);
+        }
+
         if let Err(e) = chart
             .configure_mesh()
             .disable_x_mesh()
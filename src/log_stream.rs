@@ -1,81 +1,957 @@
 use chrono::{DateTime, Utc};
-use futures::{Stream, StreamExt};
-use once_cell::sync::Lazy;
-use regex::Regex;
+use futures::{FutureExt, Stream, StreamExt};
 use stream_cancel::{StreamExt as StreamCancelStreamExt, Trigger, Tripwire};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use futures::future::Either;
+use reqwest::StatusCode;
 use tokio_stream::wrappers::ReceiverStream;
-use crate::k8s_client::{KubeClient, LogOptions, KubeConfig};
-use crate::pod_list_view::PodViewData;
+use k8s_openapi::api::core::v1::ContainerStatus;
+use crate::k8s_client::{KubeClient, LogOptions, KubeConfig, ClusterContext, ListWatchEvent, Pod};
+use crate::result::{AppError, AppResult};
+use regex::Regex;
+use crate::ansi::StyleSpan;
+use crate::log_fields::{detect_severity, extract_fields, parse_json_fields, LogFields, Severity, LEVEL, MSG};
+use crate::log_line_decoder::{LogLineDecoder, MAX_LINE_LENGTH};
+use crate::pod_list_view::{ContainerKind, PodViewData};
 use crate::tokio;
+use crate::tokio::sync::mpsc::Sender;
+use crate::tokio::sync::watch;
 
 
-static LOG_LINE_PATTERN: Lazy<Regex> = Lazy::new(||{
-    Regex::new(r"(?P<timestamp>\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}.\d{9}Z)\s(?P<data>.*\n)").expect("Invalid regex")
-});
-
 #[derive(Clone)]
 pub struct LogData {
     pub text: String,
     pub pod: String,
     pub container: String,
     pub timestamp: DateTime<Utc>,
+    /// Line of the previous container instance
+    pub previous: bool,
+    /// Fields of structured log lines
+    pub fields: Option<LogFields>,
+    pub severity: Option<Severity>,
+    /// Ranges of the text colored by ANSI escape sequences
+    pub styles: Vec<StyleSpan>,
+}
+
+#[cfg(test)]
+impl LogData {
+    /// Line of the container `app` in the pod `pod`, logged at the second of 2022-01-01 10:00 UTC.
+    pub fn test(text: &str, second: u32) -> Self {
+        use chrono::TimeZone;
+        LogData {
+            text: text.to_string(),
+            pod: "pod".to_string(),
+            container: "app".to_string(),
+            timestamp: Utc.ymd(2022, 1, 1).and_hms(10, 0, second),
+            previous: false,
+            fields: None,
+            severity: None,
+            styles: vec![],
+        }
+    }
+
+    /// Sets the fields of a JSON line and the level among them.
+    pub fn with_json_fields(mut self) -> Self {
+        self.fields = parse_json_fields(&self.text, &[]);
+        self.severity = self.fields.as_ref().and_then(|f| f.severity());
+        self
+    }
+
+    /// Sets the level detected in a plain line.
+    pub fn with_detected_severity(mut self) -> Self {
+        self.severity = detect_severity(&self.text);
+        self
+    }
+
+    pub fn with_container(mut self, container: &str) -> Self {
+        self.container = container.to_string();
+        self
+    }
+}
+
+/// Whether the logs of the previous container instances are loaded along with the current ones.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PreviousLogs {
+    Exclude,
+    Include,
+    Only,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum StreamState {
+    Connected,
+    Reconnecting(String),
+    Stopped,
+}
+
+#[derive(Clone, Debug)]
+pub struct StreamStatus {
+    pub pod: String,
+    pub container: String,
+    pub state: StreamState,
+}
+
+#[derive(Clone)]
+pub enum LogStreamEvent {
+    Data(LogData),
+    Status(StreamStatus),
+}
+
+const RECONNECT_DELAY_MIN: Duration = Duration::from_secs(1);
+const RECONNECT_DELAY_MAX: Duration = Duration::from_secs(30);
+const PREVIOUS_LOGS_TAIL_LINES: u32 = 10000;
+const PREVIOUS_LOGS_LIMIT_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Remembers the lines delivered for the latest timestamp so that lines
+/// which are sent again after resuming a stream with `sinceTime` can be skipped.
+/// Identical lines within a connection are all delivered.
+struct DeliveredLines {
+    timestamp: Option<DateTime<Utc>>,
+    lines: Vec<String>,
+    /// Lines of the latest timestamp which the resumed stream sends again
+    replayed: Vec<String>,
+}
+
+impl DeliveredLines {
+    fn new() -> Self {
+        Self {
+            timestamp: None,
+            lines: vec![],
+            replayed: vec![],
+        }
+    }
+
+    /// Called before the stream is resumed from the latest timestamp.
+    fn resume(&mut self) {
+        self.replayed = self.lines.clone();
+    }
+
+    /// Returns false if the line was already delivered.
+    fn deliver(&mut self, timestamp: DateTime<Utc>, text: &str) -> bool {
+        match self.timestamp {
+            Some(last) if timestamp < last => false,
+            Some(last) if timestamp == last => {
+                // Each replayed occurrence is skipped once
+                if let Some(pos) = self.replayed.iter().position(|l| l == text) {
+                    self.replayed.remove(pos);
+                    false
+                } else {
+                    self.lines.push(text.to_string());
+                    true
+                }
+            }
+            _ => {
+                self.timestamp = Some(timestamp);
+                self.lines.clear();
+                self.replayed.clear();
+                self.lines.push(text.to_string());
+                true
+            }
+        }
+    }
 }
 
-pub async fn log_stream(k8s_client: &KubeClient, namespace: &str, pods: Vec<PodViewData>, since_seconds: u32) -> (impl Stream<Item = LogData>, Trigger) {
-    let (tx, rx) = tokio::sync::mpsc::channel::<LogData>(1000);
+pub async fn log_stream(k8s_client: &KubeClient, namespace: &str, pods: Vec<PodViewData>, since_seconds: u32, previous: PreviousLogs) -> (impl Stream<Item = LogStreamEvent>, Trigger) {
+    let (tx, rx) = tokio::sync::mpsc::channel::<LogStreamEvent>(1000);
     let (trigger, tripwire) = Tripwire::new();
     for pod in pods {
-        for container in pod.containers() {
-            let tripwire = tripwire.clone();
-            let k8s_client = k8s_client.clone();
-            let tx = tx.clone();
-            let namespace = namespace.to_string();
-            let pod = pod.clone();
-
-            tokio::task::spawn(async move {
-                log::info!("Start tail for {} ({})", pod.name, container);
-                let res = k8s_client.logs(&namespace, &pod.name, Some(&container), Some(LogOptions {
-                    since_seconds: Some(since_seconds),
-                    follow: Some(true),
-                })).await.unwrap();
-
-                let mut res = res.take_until_if(tripwire);
-                let mut buffer = Vec::new();
-                while let Some(Ok(bytes)) = res.next().await {
-                    buffer.append(&mut bytes.to_vec());
-                    let data = String::from_utf8_lossy(&buffer).to_string();
-                    if let Some(ma) = LOG_LINE_PATTERN.captures(&data) {
-                        if let Some(timestamp) = ma.name("timestamp")
-                            .and_then(|ts| chrono::DateTime::parse_from_rfc3339(ts.as_str()).ok()
-                            .and_then(|dt| Some(dt.with_timezone(&Utc))))
-                        {
-                            let log_data = ma.name("data").unwrap().as_str().to_string();
-                            if let Err(e ) = tx.send(LogData { pod: pod.name.clone(), container: container.clone(), text: log_data, timestamp }).await {
+        for (container, kind) in pod.containers() {
+            if previous != PreviousLogs::Exclude {
+                spawn_logs_once(k8s_client, namespace, &pod.name, &container, previous_log_options(), true, &tx, &tripwire);
+            }
+            if previous == PreviousLogs::Only {
+                continue;
+            }
+            if kind == ContainerKind::Init {
+                // Init containers have usually finished when their pod gets selected
+                let options = LogOptions { since_seconds: Some(since_seconds), ..LogOptions::default() };
+                spawn_logs_once(k8s_client, namespace, &pod.name, &container, options, false, &tx, &tripwire);
+                continue;
+            }
+            // Nobody reports the phase of a static pod list, it's looked up when a stream ends
+            spawn_tail(k8s_client, namespace, &pod.name, &container, since_seconds, &tx, &tripwire, None);
+        }
+    }
+
+    (ReceiverStream::new(rx), trigger)
+}
+
+/// Follows all pods matching the label selector, containers of pods which are created later
+/// get tailed as soon as they are started. Excluded containers are skipped.
+pub async fn workload_log_stream(k8s_client: &KubeClient, namespace: &str, label_selector: &str, since_seconds: u32, excluded_containers: Vec<String>) -> (impl Stream<Item = LogStreamEvent>, Trigger) {
+    let (tx, rx) = tokio::sync::mpsc::channel::<LogStreamEvent>(1000);
+    let (trigger, tripwire) = Tripwire::new();
+    let k8s_client = k8s_client.clone();
+    let namespace = namespace.to_string();
+    let label_selector = label_selector.to_string();
+    tokio::task::spawn(async move {
+        log::info!("Start watching pods for: {}", label_selector);
+        watch_pods(k8s_client, &namespace, &label_selector, since_seconds, &excluded_containers, tx, tripwire).await;
+        log::info!("Stopped watching pods for: {}", label_selector);
+    });
+
+    (ReceiverStream::new(rx), trigger)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ContainerPhase {
+    Running,
+    Terminated,
+    Removed,
+}
+
+fn container_phase(status: &ContainerStatus) -> Option<ContainerPhase> {
+    let state = status.state.as_ref();
+    if state.and_then(|s| s.running.as_ref()).is_some() {
+        Some(ContainerPhase::Running)
+    } else if state.and_then(|s| s.terminated.as_ref()).is_some()
+        || status.last_state.as_ref().and_then(|s| s.terminated.as_ref()).is_some()
+    {
+        Some(ContainerPhase::Terminated)
+    } else {
+        // Still waiting for the first start, there are no logs yet
+        None
+    }
+}
+
+fn container_statuses(pod: &Pod) -> impl Iterator<Item = &ContainerStatus> {
+    let status = pod.status.as_ref();
+    status.and_then(|s| s.init_container_statuses.as_ref()).into_iter().flatten()
+        .chain(status.and_then(|s| s.container_statuses.as_ref()).into_iter().flatten())
+        .chain(pod.ephemeral_container_statuses.iter())
+}
+
+/// Loads the phase of a container whose pod is not watched.
+async fn load_container_phase(k8s_client: &KubeClient, namespace: &str, pod: &str, container: &str) -> ContainerPhase {
+    match k8s_client.pod(namespace, pod).await {
+        Ok(pod) => container_statuses(&pod)
+            .find(|status| status.name == container)
+            .and_then(container_phase)
+            .unwrap_or(ContainerPhase::Running),
+        Err(e) if e.downcast_ref::<reqwest::Error>().and_then(|e| e.status()) == Some(StatusCode::NOT_FOUND) => ContainerPhase::Removed,
+        Err(e) => {
+            // Assume it's still running, the tail is resumed with a backoff
+            log::warn!("Could not load the state of {} ({}): {}", pod, container, e);
+            ContainerPhase::Running
+        }
+    }
+}
+
+type Tails = HashMap<(String, String), watch::Sender<ContainerPhase>>;
+
+async fn watch_pods(k8s_client: KubeClient, namespace: &str, label_selector: &str, since_seconds: u32, excluded_containers: &[String], tx: Sender<LogStreamEvent>, tripwire: Tripwire) {
+    let mut tails: Tails = HashMap::new();
+    let mut events = k8s_client.watch_pods(namespace, Some(label_selector)).take_until_if(tripwire.clone());
+    while let Some(event) = events.next().await {
+        match event {
+            Ok(ListWatchEvent::Restarted(pods)) => {
+                let names: HashSet<String> = pods.iter().filter_map(|p| p.metadata.name.clone()).collect();
+                let removed: HashSet<String> = tails.keys().map(|(pod, _)| pod.clone()).filter(|pod| !names.contains(pod)).collect();
+                for pod in removed {
+                    remove_pod(&mut tails, &pod);
+                }
+                for pod in &pods {
+                    sync_pod(&k8s_client, namespace, pod, since_seconds, excluded_containers, &tx, &tripwire, &mut tails);
+                }
+            }
+            Ok(ListWatchEvent::Applied(pod)) => {
+                sync_pod(&k8s_client, namespace, &pod, since_seconds, excluded_containers, &tx, &tripwire, &mut tails);
+            }
+            Ok(ListWatchEvent::Deleted(pod)) => {
+                if let Some(name) = &pod.metadata.name {
+                    remove_pod(&mut tails, name);
+                }
+            }
+            Err(e) => log::warn!("Pod watch failed: {}", e),
+        }
+
+        if tx.is_closed() {
+            return;
+        }
+    }
+}
+
+/// Starts tails for new containers and passes state changes to the running ones.
+fn sync_pod(k8s_client: &KubeClient, namespace: &str, pod: &Pod, since_seconds: u32, excluded_containers: &[String], tx: &Sender<LogStreamEvent>, tripwire: &Tripwire, tails: &mut Tails) {
+    let name = match &pod.metadata.name {
+        Some(name) => name,
+        None => return,
+    };
+
+    // Terminating pods are tailed until their containers stop or the pod is deleted, to keep the shutdown logs
+    let statuses = container_statuses(pod)
+        .filter(|status| !excluded_containers.contains(&status.name));
+    for status in statuses {
+        if let Some(phase) = container_phase(status) {
+            let key = (name.clone(), status.name.clone());
+            if let Some(tail) = tails.get(&key) {
+                if *tail.borrow() != phase {
+                    let _ = tail.send(phase);
+                }
+            } else {
+                let (phase_tx, phase_rx) = watch::channel(phase);
+                spawn_tail(k8s_client, namespace, name, &status.name, since_seconds, tx, tripwire, Some(phase_rx));
+                tails.insert(key, phase_tx);
+            }
+        }
+    }
+}
+
+fn remove_pod(tails: &mut Tails, pod: &str) {
+    let keys: Vec<(String, String)> = tails.keys().filter(|(name, _)| name == pod).cloned().collect();
+    for key in keys {
+        if let Some(tail) = tails.remove(&key) {
+            let _ = tail.send(ContainerPhase::Removed);
+        }
+    }
+}
+
+fn spawn_tail(k8s_client: &KubeClient, namespace: &str, pod: &str, container: &str, since_seconds: u32, tx: &Sender<LogStreamEvent>, tripwire: &Tripwire, phase: Option<watch::Receiver<ContainerPhase>>) {
+    let tripwire = tripwire.clone();
+    let k8s_client = k8s_client.clone();
+    let tx = tx.clone();
+    let namespace = namespace.to_string();
+    let pod = pod.to_string();
+    let container = container.to_string();
+
+    tokio::task::spawn(async move {
+        log::info!("Start tail for {} ({})", pod, container);
+        tail_container(k8s_client, &namespace, &pod, &container, since_seconds, tx, tripwire, phase).await;
+        log::info!("Stopped tail for: {} ({})", pod, container);
+    });
+}
+
+fn previous_log_options() -> LogOptions {
+    LogOptions {
+        previous: Some(true),
+        tail_lines: Some(PREVIOUS_LOGS_TAIL_LINES),
+        limit_bytes: Some(PREVIOUS_LOGS_LIMIT_BYTES),
+        ..LogOptions::default()
+    }
+}
+
+fn spawn_logs_once(k8s_client: &KubeClient, namespace: &str, pod: &str, container: &str, options: LogOptions, previous: bool, tx: &Sender<LogStreamEvent>, tripwire: &Tripwire) {
+    let tripwire = tripwire.clone();
+    let k8s_client = k8s_client.clone();
+    let tx = tx.clone();
+    let namespace = namespace.to_string();
+    let pod = pod.to_string();
+    let container = container.to_string();
+
+    tokio::task::spawn(async move {
+        logs_once(k8s_client, &namespace, &pod, &container, options, previous, tx, tripwire).await;
+    });
+}
+
+/// Loads the logs of a container without following them, e.g. of the previous
+/// container instance or of a finished init container, they don't change anymore.
+async fn logs_once(k8s_client: KubeClient, namespace: &str, pod: &str, container: &str, options: LogOptions, previous: bool, tx: Sender<LogStreamEvent>, tripwire: Tripwire) {
+    let res = match k8s_client.logs(namespace, pod, Some(container), Some(options)).await {
+        Ok(res) => res,
+        Err(e) if previous => {
+            // The container was not restarted yet
+            log::info!("No previous logs for {} ({}): {}", pod, container, e);
+            return;
+        }
+        Err(e) => {
+            log::warn!("Loading logs of {} ({}) failed: {}", pod, container, e);
+            return;
+        }
+    };
+
+    let mut res = res.take_until_if(tripwire);
+    let mut decoder = LogLineDecoder::new(MAX_LINE_LENGTH);
+    while let Some(bytes) = res.next().await {
+        let bytes = match bytes {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                log::warn!("Loading logs of {} ({}) failed: {}", pod, container, e);
+                return;
+            }
+        };
+        for line in decoder.decode(&bytes) {
+            let log_data = LogData { pod: pod.to_string(), container: container.to_string(), text: line.text, timestamp: line.timestamp, previous, fields: None, severity: None, styles: vec![] };
+            if let Err(e) = tx.send(LogStreamEvent::Data(log_data)).await {
+                log::error!("Failed to send data: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+async fn send_status(tx: &Sender<LogStreamEvent>, pod: &str, container: &str, state: StreamState) {
+    let status = StreamStatus {
+        pod: pod.to_string(),
+        container: container.to_string(),
+        state,
+    };
+    if let Err(e) = tx.send(LogStreamEvent::Status(status)).await {
+        log::error!("Failed to send status: {}", e);
+    }
+}
+
+/// Follows the logs of a container until the tripwire is triggered or the container gets removed.
+/// Dropped connections are resumed from the timestamp of the last received line,
+/// a terminated container is resumed when it runs again. Without phase updates
+/// the tail stops once the container terminated.
+async fn tail_container(k8s_client: KubeClient, namespace: &str, pod: &str, container: &str, since_seconds: u32, tx: Sender<LogStreamEvent>, tripwire: Tripwire, mut phase: Option<watch::Receiver<ContainerPhase>>) {
+    let mut delivered = DeliveredLines::new();
+    let mut delay = RECONNECT_DELAY_MIN;
+    loop {
+        delivered.resume();
+        let res = k8s_client.logs(namespace, pod, Some(container), Some(LogOptions {
+            since_seconds: Some(since_seconds),
+            since_time: delivered.timestamp,
+            follow: Some(true),
+            ..LogOptions::default()
+        })).await;
+
+        // The server ends the stream of a terminated container
+        let mut closed = false;
+        let reason = match res {
+            Ok(res) => {
+                send_status(&tx, pod, container, StreamState::Connected).await;
+                let mut res = res.take_until_if(tripwire.clone());
+                let mut decoder = LogLineDecoder::new(MAX_LINE_LENGTH);
+                let mut reason = "Stream closed by server".to_string();
+                closed = true;
+                while let Some(bytes) = res.next().await {
+                    let bytes = match bytes {
+                        Ok(bytes) => bytes,
+                        Err(e) => {
+                            reason = e.to_string();
+                            closed = false;
+                            break;
+                        }
+                    };
+                    for line in decoder.decode(&bytes) {
+                        if delivered.deliver(line.timestamp, &line.text) {
+                            // Lines which were sent again after resuming don't count as a working stream
+                            delay = RECONNECT_DELAY_MIN;
+                            let log_data = LogData { pod: pod.to_string(), container: container.to_string(), text: line.text, timestamp: line.timestamp, previous: false, fields: None, severity: None, styles: vec![] };
+                            if let Err(e) = tx.send(LogStreamEvent::Data(log_data)).await {
                                 log::error!("Failed to send data: {}", e);
+                                return;
                             }
                         }
-                        else {
-                            log::error!("Invalid log data data without timestamp")
+                    }
+                }
+                reason
+            }
+            Err(e) => {
+                if e.downcast_ref::<reqwest::Error>().and_then(|e| e.status()) == Some(StatusCode::NOT_FOUND) {
+                    log::warn!("Stop tail for {} ({}): {}", pod, container, e);
+                    send_status(&tx, pod, container, StreamState::Stopped).await;
+                    return;
+                }
+                e.to_string()
+            }
+        };
+
+        if tx.is_closed() || tripwire.clone().now_or_never().is_some() {
+            return;
+        }
+
+        let current_phase = match &phase {
+            Some(phase) => *phase.borrow(),
+            None if closed => load_container_phase(&k8s_client, namespace, pod, container).await,
+            None => ContainerPhase::Running,
+        };
+        match current_phase {
+            ContainerPhase::Running => {}
+            ContainerPhase::Removed => {
+                send_status(&tx, pod, container, StreamState::Stopped).await;
+                return;
+            }
+            ContainerPhase::Terminated => {
+                send_status(&tx, pod, container, StreamState::Stopped).await;
+                let phase = match phase.as_mut() {
+                    Some(phase) => phase,
+                    None => return,
+                };
+                // Wait until the container was restarted
+                loop {
+                    match futures::future::select(Box::pin(phase.changed()), Box::pin(tripwire.clone())).await {
+                        Either::Left((Ok(_), _)) => {}
+                        _ => return,
+                    }
+                    let current_phase = *phase.borrow();
+                    match current_phase {
+                        ContainerPhase::Running => break,
+                        ContainerPhase::Removed => return,
+                        ContainerPhase::Terminated => {}
+                    }
+                }
+                delay = RECONNECT_DELAY_MIN;
+                continue;
+            }
+        }
+
+        log::warn!("Reconnecting tail for {} ({}) in {:?}: {}", pod, container, delay, reason);
+        send_status(&tx, pod, container, StreamState::Reconnecting(reason)).await;
+        let sleep = Box::pin(tokio::time::sleep(delay));
+        if let Either::Right(_) = futures::future::select(sleep, Box::pin(tripwire.clone())).await {
+            return;
+        }
+        delay = std::cmp::min(delay * 2, RECONNECT_DELAY_MAX);
+    }
+}
+
+fn cluster_context(path: &str, ctx: &str) -> AppResult<ClusterContext> {
+    let cfg = KubeConfig::load_paths(path).map_err(|e| AppError::for_context(ctx, e))?;
+    cfg.context(ctx).map_err(|e| AppError::for_context(ctx, e))
+}
+
+pub fn k8s_client(path: &str, ctx: &str) -> AppResult<KubeClient> {
+    let context = cluster_context(path, ctx)?;
+    KubeClient::new(&context).map_err(|e| AppError::for_context(ctx, e))
+}
+
+pub fn k8s_client_with_timeout(path: &str, ctx: &str) -> AppResult<KubeClient> {
+    let context = cluster_context(path, ctx)?;
+    KubeClient::with_timeout(&context).map_err(|e| AppError::for_context(ctx, e))
+}
+
+/// Turns the text of a log line into structured fields.
+/// Parsers of formats with arbitrary keys extract the well-known and the configured additional fields,
+/// parsers of fixed formats all of their fields.
+pub trait LogParser: Send {
+    fn kind(&self) -> ParserKind;
+    fn parse(&self, text: &str) -> Option<LogFields>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ParserKind {
+    Auto,
+    Plain,
+    Json,
+    Logfmt,
+    Combined,
+    Klog,
+    Log4j,
+}
+
+impl ParserKind {
+    pub const ALL: [ParserKind; 7] = [ParserKind::Auto, ParserKind::Plain, ParserKind::Json, ParserKind::Logfmt, ParserKind::Combined, ParserKind::Klog, ParserKind::Log4j];
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            ParserKind::Auto => "auto",
+            ParserKind::Plain => "plain",
+            ParserKind::Json => "json",
+            ParserKind::Logfmt => "logfmt",
+            ParserKind::Combined => "combined",
+            ParserKind::Klog => "klog",
+            ParserKind::Log4j => "log4j",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ParserKind::Auto => "Auto detect",
+            ParserKind::Plain => "Plain text",
+            ParserKind::Json => "JSON",
+            ParserKind::Logfmt => "logfmt",
+            ParserKind::Combined => "nginx / apache combined",
+            ParserKind::Klog => "klog / glog",
+            ParserKind::Log4j => "Log4j / Logback pattern",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::ALL.iter().find(|k| k.id() == id).cloned()
+    }
+}
+
+pub struct JsonParser {
+    additional_fields: Vec<String>,
+}
+
+impl LogParser for JsonParser {
+    fn kind(&self) -> ParserKind {
+        ParserKind::Json
+    }
+
+    fn parse(&self, text: &str) -> Option<LogFields> {
+        parse_json_fields(text, &self.additional_fields)
+    }
+}
+
+/// Parses `key=value` pairs, values may be quoted, e.g. `level=info msg="request done" duration=12ms`.
+pub struct LogfmtParser {
+    additional_fields: Vec<String>,
+}
+
+impl LogfmtParser {
+    fn pairs(text: &str) -> Option<HashMap<String, String>> {
+        let mut pairs = HashMap::new();
+        let mut chars = text.trim().chars().peekable();
+        while chars.peek().is_some() {
+            let mut key = String::new();
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != '=') {
+                if !(c.is_alphanumeric() || "_.-/@".contains(c)) {
+                    return None;
+                }
+                key.push(c);
+            }
+            if key.is_empty() {
+                return None;
+            }
+            let mut value = String::new();
+            if chars.next_if_eq(&'=').is_some() {
+                if chars.next_if_eq(&'"').is_some() {
+                    loop {
+                        match chars.next()? {
+                            '\\' => value.push(chars.next()?),
+                            '"' => break,
+                            c => value.push(c),
                         }
-                        buffer.clear();
+                    }
+                } else {
+                    while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                        value.push(c);
                     }
                 }
-                log::info!("Stopped tail for: {} ({})", pod.name, container);
-            });
+            } else {
+                // Keys without value are flags
+                value.push_str("true");
+            }
+            if chars.next_if(|c| c.is_whitespace()).is_none() && chars.peek().is_some() {
+                return None;
+            }
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            pairs.insert(key, value);
         }
+        Some(pairs)
     }
+}
 
-    (ReceiverStream::new(rx), trigger)
+impl LogParser for LogfmtParser {
+    fn kind(&self) -> ParserKind {
+        ParserKind::Logfmt
+    }
+
+    fn parse(&self, text: &str) -> Option<LogFields> {
+        // Plain text like "user=bob logged in" is no logfmt, at least two pairs are required
+        if text.matches('=').count() < 2 {
+            return None;
+        }
+        let pairs = Self::pairs(text)?;
+        Some(extract_fields(|key| pairs.get(key).cloned(), &self.additional_fields))
+    }
+}
+
+/// Parses the nginx / apache combined and common log format.
+pub struct CombinedLogParser {
+    regex: Regex,
+}
+
+impl CombinedLogParser {
+    fn new() -> Self {
+        Self {
+            regex: Regex::new(r#"^(?P<remote_addr>\S+) \S+ (?P<user>\S+) \[(?P<time>[^\]]+)\] "(?P<request>[^"]*)" (?P<status>\d{3}) (?P<bytes>\d+|-)(?: "(?P<referer>[^"]*)" "(?P<user_agent>[^"]*)")?"#)
+                .expect("Combined log regex should be valid"),
+        }
+    }
+}
+
+impl LogParser for CombinedLogParser {
+    fn kind(&self) -> ParserKind {
+        ParserKind::Combined
+    }
+
+    fn parse(&self, text: &str) -> Option<LogFields> {
+        let captures = self.regex.captures(text.trim_end())?;
+        let mut fields = captured_fields(&self.regex, &captures);
+        let status = captures.name("status")?.as_str();
+        let level = match status.chars().next() {
+            Some('5') => Severity::Error,
+            Some('4') => Severity::Warn,
+            _ => Severity::Info,
+        };
+        fields.insert(LEVEL, level.name().to_string());
+        fields.insert(MSG, format!("{} {}", captures.name("request")?.as_str(), status));
+        Some(fields)
+    }
 }
 
-pub fn k8s_client(path: &str, ctx: &str) -> KubeClient {
-    let cfg = KubeConfig::load(path).unwrap();
-    let ctx = cfg.context(ctx).unwrap();
-    KubeClient::new(&ctx).unwrap()
+/// Parses the klog / glog header, e.g. `I0102 15:04:05.123456    1 controller.go:42] msg`.
+pub struct KlogParser {
+    regex: Regex,
+}
+
+impl KlogParser {
+    fn new() -> Self {
+        Self {
+            regex: Regex::new(r"^(?P<level>[IWEF])(?P<time>\d{4} \d{2}:\d{2}:\d{2}\.\d+)\s+(?P<thread>\d+) (?P<logger>[^:\]\s]+:\d+)\] (?P<msg>.*)$")
+                .expect("Klog regex should be valid"),
+        }
+    }
 }
 
-pub fn k8s_client_with_timeout(path: &str, ctx: &str) -> KubeClient {
-    let cfg = KubeConfig::load(path).unwrap();
-    let ctx = cfg.context(ctx).unwrap();
-    KubeClient::with_timeout(&ctx).unwrap()
+impl LogParser for KlogParser {
+    fn kind(&self) -> ParserKind {
+        ParserKind::Klog
+    }
+
+    fn parse(&self, text: &str) -> Option<LogFields> {
+        let captures = self.regex.captures(text.trim_end())?;
+        let mut fields = captured_fields(&self.regex, &captures);
+        let level = match captures.name("level")?.as_str() {
+            "I" => Severity::Info,
+            "W" => Severity::Warn,
+            "E" => Severity::Error,
+            _ => Severity::Fatal,
+        };
+        fields.insert(LEVEL, level.name().to_string());
+        Some(fields)
+    }
+}
+
+/// Parses lines written with a Log4j / Logback pattern layout like `%d [%thread] %-5level %logger{36} - %msg%n`.
+pub struct Log4jParser {
+    regexes: Vec<Regex>,
+}
+
+impl Log4jParser {
+    fn new(patterns: &[String]) -> Self {
+        let regexes = patterns.iter().filter_map(|pattern| match log4j_pattern_regex(pattern) {
+            Ok(regex) => Some(regex),
+            Err(e) => {
+                log::warn!("Invalid log4j pattern {}: {}", pattern, e);
+                None
+            }
+        }).collect();
+        Self { regexes }
+    }
+}
+
+impl LogParser for Log4jParser {
+    fn kind(&self) -> ParserKind {
+        ParserKind::Log4j
+    }
+
+    fn parse(&self, text: &str) -> Option<LogFields> {
+        let text = text.trim_end();
+        self.regexes.iter().find_map(|regex| {
+            let captures = regex.captures(text)?;
+            Some(captured_fields(regex, &captures))
+        }).filter(|fields| fields.severity().is_some())
+    }
+}
+
+/// Converts the conversion patterns of a Log4j / Logback layout into a regex with named groups.
+/// Unknown conversions match anything.
+fn log4j_pattern_regex(pattern: &str) -> Result<Regex, regex::Error> {
+    let mut regex = String::from("^");
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '%' {
+            if chars.next_if_eq(&'%').is_some() {
+                regex.push('%');
+                continue;
+            }
+            // Format modifiers like -5 or .30 pad the value with spaces
+            let mut padded = false;
+            while chars.next_if(|c| c.is_ascii_digit() || *c == '-' || *c == '.').is_some() {
+                padded = true;
+            }
+            let mut name = String::new();
+            while let Some(c) = chars.next_if(|c| c.is_alphabetic()) {
+                name.push(c);
+            }
+            let mut option = String::new();
+            if chars.next_if_eq(&'{').is_some() {
+                while let Some(c) = chars.next_if(|c| *c != '}') {
+                    option.push(c);
+                }
+                chars.next();
+            }
+            if padded {
+                regex.push_str(r"\s*");
+            }
+            let group = match name.as_str() {
+                "d" | "date" => r"(?P<time>\d[\w\-/:.,+ ]*?)".to_string(),
+                "p" | "le" | "level" => r"(?P<level>[A-Za-z]+)".to_string(),
+                "t" | "thread" => r"(?P<thread>.*?)".to_string(),
+                "c" | "lo" | "logger" | "C" | "class" => r"(?P<logger>\S+?)".to_string(),
+                "m" | "msg" | "message" => r"(?P<msg>.*)".to_string(),
+                "n" => String::new(),
+                "X" | "mdc" if option.chars().all(|c| c.is_alphanumeric() || c == '_') && !option.is_empty() => {
+                    format!(r"(?P<{}>.*?)", option)
+                }
+                _ => r".*?".to_string(),
+            };
+            regex.push_str(&group);
+            if padded {
+                regex.push_str(r"\s*");
+            }
+        } else if c.is_whitespace() {
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            regex.push_str(r"\s+");
+        } else {
+            regex.push_str(&regex::escape(&c.to_string()));
+        }
+    }
+    regex.push('$');
+    Regex::new(&regex)
+}
+
+fn captured_fields(regex: &Regex, captures: &regex::Captures) -> LogFields {
+    let mut fields = LogFields::default();
+    for name in regex.capture_names().flatten() {
+        if let Some(value) = captures.name(name) {
+            fields.insert(name, value.as_str().trim().to_string());
+        }
+    }
+    fields
+}
+
+/// Parses the lines with the parser selected for the container or the one detected for it.
+pub struct LogParsers {
+    parsers: Vec<Box<dyn LogParser>>,
+    selected: HashMap<String, ParserKind>,
+    detected: HashMap<String, usize>,
+}
+
+impl LogParsers {
+    pub fn new(additional_fields: Vec<String>, log4j_patterns: &[String], selected: HashMap<String, ParserKind>) -> Self {
+        // Order of the auto detection, stricter formats first
+        let parsers: Vec<Box<dyn LogParser>> = vec![
+            Box::new(JsonParser { additional_fields: additional_fields.clone() }),
+            Box::new(KlogParser::new()),
+            Box::new(CombinedLogParser::new()),
+            Box::new(Log4jParser::new(log4j_patterns)),
+            Box::new(LogfmtParser { additional_fields }),
+        ];
+        Self {
+            parsers,
+            selected,
+            detected: HashMap::new(),
+        }
+    }
+
+    pub fn select(&mut self, container: &str, kind: ParserKind) {
+        self.selected.insert(container.to_string(), kind);
+        self.detected.remove(container);
+    }
+
+    /// Sets the fields and the level of the line.
+    pub fn parse_data(&mut self, datum: &mut LogData) {
+        // Continuation lines of multi-line events are not part of the format
+        let first_line = datum.text.split('\n').next().unwrap_or_default();
+        datum.fields = self.parse(&datum.container, first_line);
+        datum.severity = datum.fields.as_ref()
+            .and_then(|f| f.severity())
+            .or_else(|| detect_severity(&datum.text));
+    }
+
+    pub fn parse(&mut self, container: &str, text: &str) -> Option<LogFields> {
+        match self.selected.get(container).cloned().unwrap_or(ParserKind::Auto) {
+            ParserKind::Plain => None,
+            ParserKind::Auto => {
+                // The format detected for a container is tried first
+                if let Some(parser) = self.detected.get(container).and_then(|idx| self.parsers.get(*idx)) {
+                    if let Some(fields) = parser.parse(text) {
+                        return Some(fields);
+                    }
+                }
+                let (idx, fields) = self.parsers.iter().enumerate()
+                    .find_map(|(idx, parser)| parser.parse(text).map(|fields| (idx, fields)))?;
+                self.detected.insert(container.to_string(), idx);
+                Some(fields)
+            }
+            kind => self.parsers.iter().find(|p| p.kind() == kind)?.parse(text),
+        }
+    }
+}
+
+#[test]
+pub fn test_delivered_lines() {
+    let t1 = DateTime::parse_from_rfc3339("2022-01-02T10:00:00Z").unwrap().with_timezone(&Utc);
+    let t2 = DateTime::parse_from_rfc3339("2022-01-02T10:00:01Z").unwrap().with_timezone(&Utc);
+    let mut delivered = DeliveredLines::new();
+    assert!(delivered.deliver(t1, "a\n"));
+    assert!(delivered.deliver(t2, "b\n"));
+    assert!(delivered.deliver(t2, "b\n"));
+
+    delivered.resume();
+    assert!(!delivered.deliver(t1, "a\n"));
+    assert!(!delivered.deliver(t2, "b\n"));
+    assert!(!delivered.deliver(t2, "b\n"));
+    assert!(delivered.deliver(t2, "b\n"));
+    assert!(delivered.deliver(t2, "c\n"));
+}
+
+#[test]
+pub fn test_logfmt_parser() {
+    let parser = LogfmtParser { additional_fields: vec!["duration".to_string()] };
+    let fields = parser.parse("time=2022-01-02T10:00:00Z level=warn msg=\"slow \\\"query\\\"\" duration=1.2s cached\n").unwrap();
+    assert_eq!(fields.severity(), Some(Severity::Warn));
+    assert_eq!(fields.message(), Some("slow \"query\""));
+    assert_eq!(fields.get("duration"), Some("1.2s"));
+
+    assert!(parser.parse("user=bob logged in\n").is_none());
+    assert!(parser.parse("a=1 b=2 and some text (x)\n").is_none());
+    assert!(parser.parse("msg=\"unterminated value=1\n").is_none());
+}
+
+#[test]
+pub fn test_combined_log_parser() {
+    let parser = CombinedLogParser::new();
+    let fields = parser.parse("10.0.0.1 - bob [02/Jan/2022:10:00:00 +0000] \"GET /api/users HTTP/1.1\" 503 12 \"-\" \"curl/7.79\"\n").unwrap();
+    assert_eq!(fields.get("remote_addr"), Some("10.0.0.1"));
+    assert_eq!(fields.get("status"), Some("503"));
+    assert_eq!(fields.get("user_agent"), Some("curl/7.79"));
+    assert_eq!(fields.severity(), Some(Severity::Error));
+    assert_eq!(fields.message(), Some("GET /api/users HTTP/1.1 503"));
+
+    let fields = parser.parse("10.0.0.1 - - [02/Jan/2022:10:00:00 +0000] \"GET / HTTP/1.1\" 200 -\n").unwrap();
+    assert_eq!(fields.severity(), Some(Severity::Info));
+}
+
+#[test]
+pub fn test_klog_parser() {
+    let parser = KlogParser::new();
+    let fields = parser.parse("E0102 15:04:05.123456       1 controller.go:42] Failed to sync \"default/web\"\n").unwrap();
+    assert_eq!(fields.severity(), Some(Severity::Error));
+    assert_eq!(fields.get(crate::log_fields::LOGGER), Some("controller.go:42"));
+    assert_eq!(fields.message(), Some("Failed to sync \"default/web\""));
+    assert!(parser.parse("Error: something\n").is_none());
+}
+
+#[test]
+pub fn test_log4j_parser() {
+    let parser = Log4jParser::new(&vec![
+        "%d{HH:mm:ss.SSS} [%thread] %-5level %logger{36} - %msg%n".to_string(),
+        "%d{yyyy-MM-dd HH:mm:ss.SSS} %5p %pid --- [%t] %-40.40logger{39} : %X{traceId} %m%n".to_string(),
+    ]);
+    let fields = parser.parse("10:00:00.123 [main] WARN  com.acme.Api - cache miss\n").unwrap();
+    assert_eq!(fields.severity(), Some(Severity::Warn));
+    assert_eq!(fields.get("thread"), Some("main"));
+    assert_eq!(fields.get(crate::log_fields::LOGGER), Some("com.acme.Api"));
+    assert_eq!(fields.message(), Some("cache miss"));
+
+    let fields = parser.parse("2022-01-02 10:00:00.123 ERROR 1 --- [nio-8080-exec-1] o.a.c.c.C.[Tomcat].[localhost]         : abc123 Servlet failed\n").unwrap();
+    assert_eq!(fields.severity(), Some(Severity::Error));
+    assert_eq!(fields.get("thread"), Some("nio-8080-exec-1"));
+    assert_eq!(fields.get("traceId"), Some("abc123"));
+    assert_eq!(fields.message(), Some("Servlet failed"));
+    assert!(parser.parse("some plain text\n").is_none());
+}
+
+#[test]
+pub fn test_log_parsers_detection() {
+    let mut selected = HashMap::new();
+    selected.insert("legacy".to_string(), ParserKind::Plain);
+    let mut parsers = LogParsers::new(vec![], &vec!["%d [%t] %-5level %logger - %msg%n".to_string()], selected);
+    assert!(parsers.parse("api", "{\"level\":\"info\",\"msg\":\"started\"}\n").is_some());
+    assert_eq!(parsers.parse("go", "level=error msg=failed\n").and_then(|f| f.severity()), Some(Severity::Error));
+    assert!(parsers.parse("legacy", "level=error msg=failed\n").is_none());
+    assert!(parsers.parse("api", "plain text\n").is_none());
+
+    parsers.select("legacy", ParserKind::Logfmt);
+    assert!(parsers.parse("legacy", "level=error msg=failed\n").is_some());
+    assert!(parsers.parse("legacy", "{\"level\":\"info\"}\n").is_none());
 }
@@ -0,0 +1,532 @@
+use std::rc::Rc;
+use std::sync::Arc;
+use gtk4_helper::{
+    prelude::*,
+    gtk,
+};
+use gtk4_helper::prelude::{Command, MsgHandler};
+use gtk4_helper::component::Component;
+use gtk4_helper::gio::SimpleActionGroup;
+use crate::cli::TailTarget;
+use crate::cluster_list_view::NamespaceViewData;
+use crate::config::{CONFIG, LogTab, Workspace};
+use crate::local_source::LocalSource;
+use crate::log_view::{LogView, LogViewMsg, DEFAULT_MARGIN};
+
+/// Tabs can be dragged between the notebooks of the group
+const TAB_GROUP: &'static str = "log-tabs";
+const TAB_WIDGET_PREFIX: &'static str = "log-tab-";
+
+#[derive(Clone)]
+pub enum LogTabsMsg {
+    /// Message for the log view of the active tab
+    Active(LogViewMsg),
+    /// Message of the log view of a tab
+    Tab(u64, LogViewMsg),
+    AddTab,
+    /// Opens the tail in an empty or a new tab
+    OpenTail(TailTarget),
+    OpenWorkspace(Workspace),
+    OpenLocal(Vec<LocalSource>, bool),
+    CloseTab(u64),
+    ActivateTab(u64),
+    TabsMoved,
+    ToggleSplit(bool),
+    /// Namespace of the active tab, the cluster and pod lists follow it
+    ActiveTabChanged(Option<NamespaceViewData>),
+}
+
+/// Log view with its own stream, along with what it shows so that it can be restored.
+struct Tab {
+    id: u64,
+    view: ComponentContainer<LogView>,
+    actions: Rc<SimpleActionGroup>,
+    header: gtk::Box,
+    label: gtk::Label,
+    context: Option<NamespaceViewData>,
+    pods: Vec<String>,
+    label_selector: Option<String>,
+    /// Name of a recording or local files, which are not restored
+    source_name: Option<String>,
+    since_seconds: Option<u32>,
+    search: Option<String>,
+}
+
+impl Tab {
+    fn widget(&self) -> gtk::Widget {
+        self.view.view().clone().upcast()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.context.is_none() && self.source_name.is_none()
+    }
+
+    fn title(&self) -> String {
+        if let Some(name) = &self.source_name {
+            return name.clone();
+        }
+        if self.pods.len() > 0 {
+            return self.pods.join(", ");
+        }
+        if let Some(label_selector) = &self.label_selector {
+            return label_selector.clone();
+        }
+        self.context.as_ref()
+            .map(|ctx| format!("{} ({})", ctx.name, ctx.context))
+            .unwrap_or("New tab".to_string())
+    }
+
+    /// Keeps track of the selection of the log view, returns true if it changed.
+    fn observe(&mut self, msg: &LogViewMsg) -> bool {
+        match msg {
+            LogViewMsg::ContextSelected(ctx) => {
+                self.context = Some(ctx.clone());
+                self.pods.clear();
+                self.label_selector = None;
+            }
+            LogViewMsg::PodSelected(pods) | LogViewMsg::PreviousLogsSelected(pods, _) => {
+                self.pods = pods.iter().map(|pod| pod.name.clone()).collect();
+                self.label_selector = None;
+                self.source_name = None;
+            }
+            LogViewMsg::WorkloadSelected(label_selector) => {
+                self.pods.clear();
+                self.label_selector = Some(label_selector.clone());
+                self.source_name = None;
+            }
+            LogViewMsg::OpenTail(target) => {
+                self.context = Some(target.namespace_view_data());
+                self.pods = target.pods.clone();
+                self.label_selector = target.selector.clone();
+                self.source_name = None;
+                self.since_seconds = target.since_seconds.or(self.since_seconds);
+                self.search = target.search.clone().or(self.search.take());
+            }
+            LogViewMsg::OpenWorkspace(workspace) => {
+                let target = TailTarget::for_workspace(workspace);
+                self.context = Some(target.namespace_view_data());
+                self.pods = target.pods;
+                self.label_selector = target.selector;
+                self.source_name = None;
+                self.since_seconds = target.since_seconds.or(self.since_seconds);
+                self.search = target.search;
+            }
+            LogViewMsg::SinceTimespanChanged(id) => {
+                self.since_seconds = id.parse::<u32>().ok();
+            }
+            LogViewMsg::Search(query) => {
+                self.search = Some(query.clone()).filter(|q| !q.is_empty());
+            }
+            LogViewMsg::RecordingSelected(Some(path)) => {
+                self.source_name = path.file_name().map(|name| name.to_string_lossy().to_string());
+            }
+            LogViewMsg::LocalSourcesSelected(sources, _) if sources.len() > 0 => {
+                self.source_name = Some(sources.iter().map(|source| source.name()).collect::<Vec<String>>().join(", "));
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn saved(&self, split: bool) -> Option<LogTab> {
+        let ctx = self.context.as_ref()?;
+        let restored = self.source_name.is_none();
+        Some(LogTab {
+            config_path: ctx.config_path.clone(),
+            context: ctx.context.clone(),
+            namespace: ctx.name.clone(),
+            pods: if restored { self.pods.clone() } else { vec![] },
+            label_selector: self.label_selector.clone().filter(|_| restored),
+            since_seconds: self.since_seconds,
+            search: self.search.clone(),
+            split,
+        })
+    }
+}
+
+fn tail_target(tab: &LogTab) -> TailTarget {
+    TailTarget {
+        kubeconfig: tab.config_path.clone(),
+        context: tab.context.clone(),
+        namespace: tab.namespace.clone(),
+        pods: tab.pods.clone(),
+        selector: tab.label_selector.clone(),
+        since_seconds: tab.since_seconds,
+        search: tab.search.clone(),
+    }
+}
+
+/// The log views of a window, as tabs of two notebooks which are shown side by side when split.
+pub struct LogTabs {
+    container: gtk::Paned,
+    primary: gtk::Notebook,
+    secondary: gtk::Notebook,
+    tabs: Vec<Tab>,
+    active: Option<u64>,
+    next_id: u64,
+    sender: Arc<dyn MsgHandler<LogTabsMsg>>,
+    app_wnd: Rc<gtk::ApplicationWindow>,
+}
+
+impl LogTabs {
+    fn add_tab(&mut self, context: Option<NamespaceViewData>, split: bool) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let actions = Rc::new(SimpleActionGroup::new());
+        let tx = self.sender.clone();
+        let view = LogView::new_with_data(move |m| {
+            tx(LogTabsMsg::Tab(id, m));
+        }, actions.clone());
+        let widget = view.view();
+        widget.set_widget_name(&format!("{}{}", TAB_WIDGET_PREFIX, id));
+        // Menus of a tab use its own actions, shortcuts the ones of the active tab
+        widget.insert_action_group("app", Some(&*actions));
+        let click = gtk::GestureClick::new();
+        click.set_propagation_phase(gtk::PropagationPhase::Capture);
+        let tx = self.sender.clone();
+        click.connect_pressed(move |_, _, _, _| {
+            tx(LogTabsMsg::ActivateTab(id));
+        });
+        widget.add_controller(&click);
+
+        let label = gtk::builders::LabelBuilder::new()
+            .max_width_chars(30)
+            .ellipsize(gtk::pango::EllipsizeMode::End)
+            .build();
+        let close_btn = gtk::builders::ButtonBuilder::new()
+            .icon_name("window-close-symbolic")
+            .tooltip_text("Close tab")
+            .has_frame(false)
+            .build();
+        let tx = self.sender.clone();
+        close_btn.connect_clicked(move |_| {
+            tx(LogTabsMsg::CloseTab(id));
+        });
+        let header = gtk::Box::new(gtk::Orientation::Horizontal, DEFAULT_MARGIN);
+        header.append(&label);
+        header.append(&close_btn);
+
+        let notebook = if split { &self.secondary } else { &self.primary };
+        notebook.append_page(widget, Some(&header));
+        notebook.set_tab_reorderable(widget, true);
+        notebook.set_tab_detachable(widget, true);
+
+        let tab = Tab {
+            id,
+            view,
+            actions,
+            header,
+            label,
+            context,
+            pods: vec![],
+            label_selector: None,
+            source_name: None,
+            since_seconds: None,
+            search: None,
+        };
+        tab.label.set_text(&tab.title());
+        self.tabs.push(tab);
+        id
+    }
+
+    fn tab(&self, id: u64) -> Option<&Tab> {
+        self.tabs.iter().find(|tab| tab.id == id)
+    }
+
+    fn tab_of_widget(&self, widget: &gtk::Widget) -> Option<&Tab> {
+        let id = widget.widget_name().strip_prefix(TAB_WIDGET_PREFIX)?.parse::<u64>().ok()?;
+        self.tab(id)
+    }
+
+    /// Makes the tab the target of the cluster and pod lists and of the shortcuts.
+    fn activate(&mut self, id: u64) {
+        if self.active == Some(id) {
+            return;
+        }
+        if let Some(tab) = self.tab(id) {
+            self.app_wnd.insert_action_group("app", Some(&*tab.actions));
+            let context = tab.context.clone();
+            self.active = Some(id);
+            let tx = self.sender.clone();
+            tx(LogTabsMsg::ActiveTabChanged(context));
+        }
+    }
+
+    fn show_tab(&mut self, id: u64) {
+        if let Some(widget) = self.tab(id).map(|tab| tab.widget()) {
+            for notebook in [&self.primary, &self.secondary] {
+                if let Some(page) = notebook.page_num(&widget) {
+                    notebook.set_current_page(Some(page));
+                }
+            }
+        }
+        // Announced again, the tab may have been reused for another namespace
+        self.active = None;
+        self.activate(id);
+    }
+
+    fn empty_tab(&self) -> Option<u64> {
+        self.tabs.iter().find(|tab| tab.is_empty()).map(|tab| tab.id)
+    }
+
+    fn move_tab(&self, widget: &gtk::Widget, to_secondary: bool) {
+        let (from, to) = if to_secondary {
+            (&self.primary, &self.secondary)
+        } else {
+            (&self.secondary, &self.primary)
+        };
+        let header = match self.tab_of_widget(widget) {
+            Some(tab) => tab.header.clone(),
+            None => return,
+        };
+        if from.page_num(widget).is_none() {
+            return;
+        }
+        from.detach_tab(widget);
+        let page = to.append_page(widget, Some(&header));
+        to.set_tab_reorderable(widget, true);
+        to.set_tab_detachable(widget, true);
+        to.set_current_page(Some(page));
+    }
+
+    fn store_tabs(&self) {
+        let mut saved = vec![];
+        for (notebook, split) in [(&self.primary, false), (&self.secondary, true)] {
+            for page in 0..notebook.n_pages() {
+                if let Some(tab) = notebook.nth_page(Some(page)).and_then(|widget| self.tab_of_widget(&widget)) {
+                    saved.extend(tab.saved(split));
+                }
+            }
+        }
+        if let Ok(mut cfg) = CONFIG.lock() {
+            cfg.log_tabs = saved;
+        }
+    }
+}
+
+impl Component for LogTabs {
+    type Msg = LogTabsMsg;
+    type View = gtk::Paned;
+    type Input = Rc<gtk::ApplicationWindow>;
+
+    fn create<T: MsgHandler<Self::Msg> + Clone>(sender: T, input: Option<Self::Input>) -> Self {
+        let app_wnd = input.expect("Input is required!");
+        let primary = tab_notebook(sender.clone());
+        let secondary = tab_notebook(sender.clone());
+        secondary.set_visible(false);
+
+        let add_btn = gtk::builders::ButtonBuilder::new()
+            .icon_name("list-add-symbolic")
+            .tooltip_text("New tab")
+            .has_frame(false)
+            .build();
+        let tx = sender.clone();
+        add_btn.connect_clicked(move |_| {
+            tx(LogTabsMsg::AddTab);
+        });
+        let split_btn = gtk::builders::ToggleButtonBuilder::new()
+            .icon_name("view-dual-symbolic")
+            .tooltip_text("Show tabs side by side, tabs can be dragged between both sides")
+            .has_frame(false)
+            .build();
+        let tx = sender.clone();
+        split_btn.connect_toggled(move |btn| {
+            tx(LogTabsMsg::ToggleSplit(btn.is_active()));
+        });
+        let actions = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+        actions.append(&add_btn);
+        actions.append(&split_btn);
+        primary.set_action_widget(&actions, gtk::PackType::End);
+
+        // The second side is only shown while it has tabs
+        let btn = split_btn.clone();
+        secondary.connect_page_added(move |notebook, _, _| {
+            notebook.set_visible(true);
+            btn.set_active(true);
+        });
+        secondary.connect_page_removed(move |notebook, _, _| {
+            if notebook.n_pages() == 0 {
+                notebook.set_visible(false);
+                split_btn.set_active(false);
+            }
+        });
+
+        let container = gtk::builders::PanedBuilder::new()
+            .orientation(gtk::Orientation::Horizontal)
+            .start_child(&primary)
+            .end_child(&secondary)
+            .build();
+
+        let mut log_tabs = Self {
+            container,
+            primary,
+            secondary,
+            tabs: vec![],
+            active: None,
+            next_id: 0,
+            sender: Arc::new(sender.clone()),
+            app_wnd,
+        };
+
+        let saved = CONFIG.lock().map(|cfg| cfg.log_tabs.clone()).unwrap_or_default();
+        for tab in saved {
+            let target = tail_target(&tab);
+            let id = log_tabs.add_tab(Some(target.namespace_view_data()), tab.split);
+            sender(LogTabsMsg::Tab(id, LogViewMsg::OpenTail(target)));
+        }
+        if log_tabs.tabs.is_empty() {
+            log_tabs.add_tab(None, false);
+        }
+        if let Some(id) = log_tabs.tabs.first().map(|tab| tab.id) {
+            log_tabs.show_tab(id);
+        }
+        log_tabs
+    }
+
+    fn update(&mut self, msg: Self::Msg) -> Command<Self::Msg> {
+        match msg {
+            LogTabsMsg::Active(msg) => {
+                if let Some(id) = self.active {
+                    return self.update(LogTabsMsg::Tab(id, msg));
+                }
+            }
+            LogTabsMsg::Tab(id, msg) => {
+                let mut changed = false;
+                if let Some(tab) = self.tabs.iter_mut().find(|tab| tab.id == id) {
+                    changed = tab.observe(&msg);
+                    if changed {
+                        tab.label.set_text(&tab.title());
+                    }
+                    tab.view.update(msg);
+                }
+                if changed {
+                    self.store_tabs();
+                }
+            }
+            LogTabsMsg::AddTab => {
+                let context = self.active.and_then(|id| self.tab(id)).and_then(|tab| tab.context.clone());
+                let id = self.add_tab(context.clone(), false);
+                if let Some(context) = context {
+                    let tx = self.sender.clone();
+                    tx(LogTabsMsg::Tab(id, LogViewMsg::ContextSelected(context)));
+                }
+                self.show_tab(id);
+            }
+            LogTabsMsg::OpenTail(target) => {
+                let id = self.empty_tab().unwrap_or_else(|| self.add_tab(None, false));
+                if let Some(tab) = self.tabs.iter_mut().find(|tab| tab.id == id) {
+                    tab.context = Some(target.namespace_view_data());
+                }
+                self.show_tab(id);
+                let tx = self.sender.clone();
+                tx(LogTabsMsg::Tab(id, LogViewMsg::OpenTail(target)));
+            }
+            LogTabsMsg::OpenWorkspace(workspace) => {
+                let target = TailTarget::for_workspace(&workspace);
+                let id = self.empty_tab().unwrap_or_else(|| self.add_tab(None, false));
+                if let Some(tab) = self.tabs.iter_mut().find(|tab| tab.id == id) {
+                    tab.context = Some(target.namespace_view_data());
+                }
+                self.show_tab(id);
+                let tx = self.sender.clone();
+                tx(LogTabsMsg::Tab(id, LogViewMsg::OpenWorkspace(workspace)));
+            }
+            LogTabsMsg::OpenLocal(sources, follow) => {
+                let id = self.empty_tab().unwrap_or_else(|| self.add_tab(None, false));
+                self.show_tab(id);
+                let tx = self.sender.clone();
+                tx(LogTabsMsg::Tab(id, LogViewMsg::LocalSourcesSelected(sources, follow)));
+            }
+            LogTabsMsg::CloseTab(id) => {
+                if let Some(pos) = self.tabs.iter().position(|tab| tab.id == id) {
+                    // Dropping the log view stops its stream
+                    let tab = self.tabs.remove(pos);
+                    let widget = tab.widget();
+                    for notebook in [&self.primary, &self.secondary] {
+                        if let Some(page) = notebook.page_num(&widget) {
+                            notebook.remove_page(Some(page));
+                        }
+                    }
+                    if self.active == Some(id) {
+                        self.active = None;
+                    }
+                    if self.tabs.is_empty() {
+                        self.add_tab(None, false);
+                    }
+                    if self.active.is_none() {
+                        let current = self.primary.current_page()
+                            .and_then(|page| self.primary.nth_page(Some(page)))
+                            .and_then(|widget| self.tab_of_widget(&widget))
+                            .or(self.tabs.first())
+                            .map(|tab| tab.id);
+                        if let Some(current) = current {
+                            self.show_tab(current);
+                        }
+                    }
+                    self.store_tabs();
+                }
+            }
+            LogTabsMsg::ActivateTab(id) => {
+                self.activate(id);
+            }
+            LogTabsMsg::TabsMoved => {
+                self.store_tabs();
+            }
+            LogTabsMsg::ToggleSplit(split) => {
+                if split && self.secondary.n_pages() == 0 {
+                    let active = self.active.and_then(|id| self.tab(id)).map(|tab| (tab.widget(), tab.context.clone()));
+                    match active {
+                        Some((widget, _)) if self.primary.n_pages() > 1 => self.move_tab(&widget, true),
+                        Some((_, context)) => {
+                            let id = self.add_tab(context.clone(), true);
+                            if let Some(context) = context {
+                                let tx = self.sender.clone();
+                                tx(LogTabsMsg::Tab(id, LogViewMsg::ContextSelected(context)));
+                            }
+                            self.show_tab(id);
+                        }
+                        None => {}
+                    }
+                    self.container.set_position(self.container.width() / 2);
+                } else if !split {
+                    while let Some(widget) = self.secondary.nth_page(Some(0)) {
+                        self.move_tab(&widget, false);
+                    }
+                }
+                self.store_tabs();
+            }
+            LogTabsMsg::ActiveTabChanged(_) => {}
+        }
+        Command::None
+    }
+
+    fn view(&self) -> &Self::View {
+        &self.container
+    }
+}
+
+fn tab_notebook<T: MsgHandler<LogTabsMsg> + Clone>(sender: T) -> gtk::Notebook {
+    let notebook = gtk::builders::NotebookBuilder::new()
+        .scrollable(true)
+        .hexpand(true)
+        .vexpand(true)
+        .group_name(TAB_GROUP)
+        .build();
+
+    let tx = sender.clone();
+    notebook.connect_switch_page(move |_, page, _| {
+        if let Some(id) = page.widget_name().strip_prefix(TAB_WIDGET_PREFIX).and_then(|id| id.parse::<u64>().ok()) {
+            tx(LogTabsMsg::ActivateTab(id));
+        }
+    });
+    // Tabs dragged within or between the notebooks
+    let tx = sender.clone();
+    notebook.connect_page_reordered(move |_, _, _| {
+        tx(LogTabsMsg::TabsMoved);
+    });
+    notebook.connect_page_added(move |_, _, _| {
+        sender(LogTabsMsg::TabsMoved);
+    });
+    notebook
+}
@@ -1,6 +1,8 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
 use std::rc::Rc;
 use std::sync::Arc;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, TimeZone, Utc};
 use futures::StreamExt;
 use uuid::Uuid;
 use gtk4_helper::{
@@ -10,7 +12,8 @@ use gtk4_helper::{
     gio
 };
 use crate::util;
-use gtk4_helper::gtk::{ComboBoxText, TextTag, TextTagTable, WrapMode};
+use crate::ansi::{parse_ansi, AnsiStyle};
+use gtk4_helper::gtk::{gdk, ComboBoxText, TextTag, TextTagTable, WrapMode};
 
 use gtk4_helper::prelude::{Command, MsgHandler};
 use gtk4_helper::component::Component;
@@ -19,15 +22,25 @@ use gtk4_helper::glib::SourceId;
 use regex::Regex;
 use sourceview5::Buffer;
 use stream_cancel::Trigger;
-use tokio_stream::wrappers::IntervalStream;
+use crate::cli::{resolve_pods, TailTarget};
 use crate::cluster_list_view::NamespaceViewData;
-use crate::config::{CONFIG};
+use crate::config::{CONFIG, DisplaySettings, FilterSet, Highlighter, Workspace};
 use crate::gtk::{TextIter, ToggleButton};
+use crate::log_buffer::{BufferStats, LogBuffer};
+use crate::log_export::{export, in_range, ExportFormat, ExportHighlighter, ExportRange};
+use crate::log_fields::{format_columns, Severity};
+use crate::log_filter::{FilterKind, LineFilter, LineFilters};
+use crate::log_grouping::{ContinuationDetector, EventGrouper};
 use crate::log_overview::{LogOverview, LogOverviewMsg};
-use crate::log_stream::LogData;
+use crate::log_stream::{LogData, LogParsers, LogStreamEvent, ParserKind, PreviousLogs, StreamState, StreamStatus};
 use crate::log_text_contrast::matching_foreground_color_for_background;
+use crate::k8s_client::KubeClient;
+use crate::local_source::{local_log_stream, LocalSource};
 use crate::pod_list_view::PodViewData;
-use crate::util::search_offset;
+use crate::pod_status::format_age;
+use crate::recording::{recording_path, replay_stream, SessionRecorder, SharedRecorder};
+use crate::result::{AppError, AppResult};
+use crate::util::show_and_log_error;
 
 pub const SEARCH_TAG: &'static str = "SEARCH";
 pub const SEARCH_COLOR: &'static str = "rgba(188,150,0,0.7)";
@@ -36,6 +49,15 @@ pub const SELECTED_SEARCH_TAG: &'static str = "SELECTED_SEARCH";
 pub const SELECTED_SEARCH_COLOR: &'static str = "rgba(188,150,0,1)";
 const SCROLL_TO_LINE_MARKER: &'static str = "SCROLL_TO_LINE_MARKER";
 
+/// Continuation lines of multi-line events
+const CONTINUATION_TAG: &'static str = "CONTINUATION";
+const FOLDED_TAG: &'static str = "FOLDED";
+/// First line of a folded event
+const FOLDED_HEAD_TAG: &'static str = "FOLDED_HEAD";
+
+/// Events are complete once their container did not log for this long.
+const EVENT_IDLE_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(200);
+
 pub const DEFAULT_MARGIN: i32 = 4;
 
 #[derive(Clone)]
@@ -64,6 +86,37 @@ pub struct HighlightResultData {
     pub matching_highlighters: Vec<String>,
 }
 
+/// Lines dropped from the start of the log view to stay within the budget.
+#[derive(Clone)]
+pub struct EvictedData {
+    pub text_lines: usize,
+    /// Highlighters which matched the dropped lines
+    pub highlights: Vec<HighlightResultData>,
+    /// Levels of the dropped lines
+    pub levels: Vec<(DateTime<Utc>, Severity)>,
+    /// Timestamp of the oldest line which is still shown
+    pub oldest: Option<DateTime<Utc>>,
+}
+
+/// File the shown lines are exported to.
+#[derive(Clone)]
+pub struct ExportTarget {
+    pub path: PathBuf,
+    pub format: ExportFormat,
+    /// Only export the time range selected in the overview
+    pub selection: bool,
+}
+
+/// What the log stream is loaded for.
+#[derive(Clone)]
+enum LogSource {
+    Pods(Vec<PodViewData>, PreviousLogs),
+    LabelSelector(String),
+    Recording(PathBuf),
+    /// Local files or stdin, optionally followed
+    Local(Vec<LocalSource>, bool),
+}
+
 pub struct LogView {
     container: gtk::Box,
     exit_trigger: Option<Arc<Trigger>>,
@@ -71,16 +124,30 @@ pub struct LogView {
     text_buffer: Buffer,
     text_view: sourceview5::View,
     selected_context: Option<NamespaceViewData>,
-    selected_pods: Option<Vec<PodViewData>>,
+    selected_source: Option<LogSource>,
     since_seconds: u32,
     active_search: Option<Regex>,
     highlighters: Vec<SearchData>,
+    /// The highlighters as configured, saved with workspaces
+    highlighter_rules: Vec<Highlighter>,
     scroll_handler: Option<SourceId>,
     overview: ComponentContainer<LogOverview>,
     settings: Settings,
+    field_columns: Vec<String>,
     search_match_markers: Vec<String>,
     search_results_lbl: gtk::Label,
+    stream_status_lbl: gtk::Label,
+    stream_states: BTreeMap<String, StreamStatus>,
     current_search_match_pos: Option<usize>,
+    filters: LineFilters,
+    filter_chips: gtk::Box,
+    buffer_stats_lbl: gtk::Label,
+    filter_set_selector: ComboBoxText,
+    since_selector: ComboBoxText,
+    search_entry: gtk::SearchEntry,
+    selected_range: Option<ExportRange>,
+    recorder: SharedRecorder,
+    record_btn: ToggleButton,
     worker_action: std::sync::mpsc::Sender<WorkerData>,
     settings_obj: glib::Object
 }
@@ -88,15 +155,35 @@ pub struct LogView {
 #[derive(Clone)]
 pub enum LogViewMsg {
     PodSelected(Vec<PodViewData>),
+    PreviousLogsSelected(Vec<PodViewData>, PreviousLogs),
+    WorkloadSelected(String),
     ContextSelected(NamespaceViewData),
+    OpenTail(TailTarget),
+    OpenWorkspace(Workspace),
+    SaveWorkspace(String),
     Loaded(Arc<Trigger>),
+    LoadFailed(AppError),
     LogDataLoaded(Vec<LogData>),
+    StreamStatus(StreamStatus),
     LogDataProcessed(Vec<(i64, LogData)>),
+    LogDataFiltered(Vec<(i64, LogData)>),
+    LogDataEvicted(EvictedData),
+    BufferStats(BufferStats),
+    AddFilter(FilterKind, String),
+    RemoveFilter(usize),
+    FilterSetSelected(String),
+    SaveFilterSet(String),
     EnableScroll(bool),
     ToggleWrapText,
     ToggleShowContainerNames,
     ToggleShowPodNames,
     ToggleShowTimestamps,
+    ToggleShowFields,
+    ToggleFoldEvents,
+    ToggleRenderAnsi,
+    ToggleFold(i32),
+    ParserSelected(String, ParserKind),
+    MinSeveritySelected(Option<Severity>),
     SinceTimespanChanged(String),
     Search(String),
     SearchResult(SearchResultData),
@@ -104,16 +191,79 @@ pub enum LogViewMsg {
     LogOverview(LogOverviewMsg),
     SelectNextSearchMatch,
     SelectPrevSearchMatch,
+    ToggleRecording(bool),
+    RecordingFailed(String),
+    OpenRecording,
+    RecordingSelected(Option<PathBuf>),
+    OpenLocalSources,
+    LocalSourcesSelected(Vec<LocalSource>, bool),
+    ExportSelected,
+    ExportTargetSelected(Option<ExportTarget>),
+    Exported(PathBuf, Result<usize, AppError>),
     ScrollToLine(i64),
 }
 
 impl LogView {
     fn clear(&mut self) {
-        self.overview.update(LogOverviewMsg::Clear);
-        self.clear_search_markers();
         if let Err(e) = self.worker_action.send(WorkerData::Clear) {
             log::error!("Could not send msg to worker: {}", e);
         }
+        self.clear_text();
+        self.selected_range.take();
+        self.update_buffer_stats_label(BufferStats { shown: 0, hidden: 0, dropped: 0 });
+        if let Some(exit) = self.exit_trigger.take() {
+            drop(exit);
+        }
+        self.stream_states.clear();
+        self.update_stream_status_label();
+    }
+
+    /// Replaces the highlighters, lines loaded afterwards are highlighted by the new ones.
+    fn set_highlighters(&mut self, highlighters: Vec<Highlighter>) {
+        add_highlighter_tags(&self.text_buffer.tag_table(), &highlighters);
+        self.highlighters = highlighter_search_data(&highlighters);
+        if let Err(e) = self.worker_action.send(WorkerData::SetHighlighters(self.highlighters.clone(), export_highlighters(&highlighters))) {
+            log::error!("Could not send msg to worker: {}", e);
+        }
+        self.overview.update(LogOverviewMsg::SetHighlighters(highlighters.clone()));
+        self.highlighter_rules = highlighters;
+    }
+
+    /// Applies the display toggles of a workspace, the lines are composed again by the next `apply_filters`.
+    /// The property actions still report the changes, but their handlers find the settings unchanged.
+    fn set_display_settings(&mut self, display: &DisplaySettings) {
+        self.settings.wrap_text = display.wrap_text;
+        self.settings.show_pod_names = display.show_pod_names;
+        self.settings.show_container_names = display.show_container_names;
+        self.settings.show_timestamps = display.show_timestamps;
+        self.settings.show_fields = display.show_fields;
+        self.settings.fold_events = display.fold_events;
+        self.settings.render_ansi = display.render_ansi;
+        for (property, value) in [
+            (Settings::wrap_text, display.wrap_text),
+            (Settings::show_pod_names, display.show_pod_names),
+            (Settings::show_container_names, display.show_container_names),
+            (Settings::show_timestamps, display.show_timestamps),
+            (Settings::show_fields, display.show_fields),
+            (Settings::fold_events, display.fold_events),
+            (Settings::render_ansi, display.render_ansi),
+        ] {
+            if self.settings_obj.property::<bool>(property) != value {
+                self.settings_obj.set_property(property, value);
+            }
+        }
+        self.text_view.set_wrap_mode(
+            if display.wrap_text {
+                WrapMode::WordChar
+            } else {
+                WrapMode::None
+            }
+        );
+    }
+
+    fn clear_text(&mut self) {
+        self.overview.update(LogOverviewMsg::Clear);
+        self.clear_search_markers();
 
         let (start, end) = self.text_buffer.bounds();
         for highlighter in &self.highlighters {
@@ -121,11 +271,296 @@ impl LogView {
         }
 
         self.text_buffer.set_text("");
-        if let Some(exit) = self.exit_trigger.take() {
-            drop(exit);
+    }
+
+    fn insert_log_data(&mut self, res: Vec<(i64, LogData)>) {
+        let timestamps: Vec<(DateTime<Utc>, Option<Severity>)> = res.iter().map(|(_, d)| (d.timestamp.clone(), d.severity)).collect();
+        self.overview.update(LogOverviewMsg::LogData(timestamps));
+        for (idx, data) in res {
+            if let Some(mut insert_at) = self.text_buffer.iter_at_line(idx as i32) {
+                let mut log_line = String::new();
+                if self.settings.show_pod_names {
+                    log_line.push_str(&data.pod)
+                }
+                if self.settings.show_container_names {
+                    log_line.push_str(&format!(" {}", data.container))
+                }
+                if data.previous {
+                    log_line.push_str(" [previous]")
+                }
+                if self.settings.show_timestamps {
+                    log_line.push_str(&format!(" {}", data.timestamp))
+                }
+
+                // Styles are only rendered if the text is shown as it is
+                let mut text_offset = None;
+                match data.fields.as_ref().filter(|_| self.settings.show_fields) {
+                    Some(fields) => {
+                        // Fields are parsed from the first line, continuation lines are shown as they are
+                        let (head, continuation) = data.text.split_once('\n').unwrap_or((data.text.as_str(), ""));
+                        log_line.push_str(&format!(" {}{}", format_columns(fields, &self.field_columns, head), continuation));
+                    }
+                    None => {
+                        log_line.push(' ');
+                        text_offset = Some(insert_at.offset() + log_line.chars().count() as i32);
+                        log_line.push_str(&data.text);
+                    }
+                }
+                let first_line = insert_at.line();
+                self.text_buffer.insert(&mut insert_at, &log_line);
+
+                if let Some(text_offset) = text_offset.filter(|_| self.settings.render_ansi) {
+                    for span in &data.styles {
+                        let start = self.text_buffer.iter_at_offset(text_offset + span.start as i32);
+                        let end = self.text_buffer.iter_at_offset(text_offset + span.end as i32);
+                        self.text_buffer.apply_tag_by_name(&self.ansi_tag(&span.style), &start, &end);
+                    }
+                }
+
+                let continuation_lines = data.text.trim_end_matches(|c| c == '\r' || c == '\n').matches('\n').count() as i32;
+                if continuation_lines > 0 {
+                    self.tag_continuation(insert_at.line() - continuation_lines, &insert_at);
+                }
+
+                let mut highlighters = self.highlighters.clone();
+                if let Some(query) = self.active_search.as_ref() {
+                    highlighters.push(SearchData {
+                        search: query.clone(),
+                        name: SEARCH_TAG.to_string(),
+                    });
+                }
+
+                let text_marker_id = Uuid::new_v4().to_string();
+                if let Some(iter) = self.text_buffer.iter_at_line(first_line) {
+                    self.text_buffer.add_mark(&gtk::TextMark::new(Some(&text_marker_id), false), &iter);
+                }
+
+                if let Err(e) = self.worker_action.send(WorkerData::ProcessHighlighters(highlighters, data, text_marker_id)) {
+                    log::error!("Could not send msg to worker: {}", e);
+                }
+            } else {
+                log::error!("No iter at line: {}", idx);
+            }
+        }
+    }
+
+    /// Name of the tag rendering the style, the tag is created on first use.
+    fn ansi_tag(&self, style: &AnsiStyle) -> String {
+        let name = style.tag_name();
+        let tag_table = self.text_buffer.tag_table();
+        if tag_table.lookup(&name).is_none() {
+            let tag = TextTag::new(Some(&name));
+            if let Some(foreground) = style.foreground {
+                tag.set_foreground(Some(&foreground.css()));
+            }
+            if let Some(background) = style.background {
+                tag.set_background(Some(&background.css()));
+            }
+            if style.bold {
+                tag.set_weight(700);
+            }
+            if style.italic {
+                tag.set_style(gtk::pango::Style::Italic);
+            }
+            if style.underline {
+                tag.set_underline(gtk::pango::Underline::Single);
+            }
+            tag.set_strikethrough(style.strikethrough);
+            tag_table.add(&tag);
+            // Highlighters and search matches take precedence
+            tag.set_priority(0);
+        }
+        name
+    }
+
+    fn tag_continuation(&self, line: i32, end: &TextIter) {
+        if let Some(start) = self.text_buffer.iter_at_line(line) {
+            self.text_buffer.apply_tag_by_name(CONTINUATION_TAG, &start, end);
+            if self.settings.fold_events {
+                self.set_folded(&start, end, true);
+            }
+        }
+    }
+
+    /// Folds or unfolds the continuation lines between start and end.
+    fn set_folded(&self, start: &TextIter, end: &TextIter, folded: bool) {
+        let mut head_start = start.clone();
+        head_start.backward_line();
+        let mut head_end = head_start.clone();
+        head_end.forward_to_line_end();
+        if folded {
+            self.text_buffer.apply_tag_by_name(FOLDED_TAG, start, end);
+            self.text_buffer.apply_tag_by_name(FOLDED_HEAD_TAG, &head_start, &head_end);
+        } else {
+            self.text_buffer.remove_tag_by_name(FOLDED_TAG, start, end);
+            self.text_buffer.remove_tag_by_name(FOLDED_HEAD_TAG, &head_start, &head_end);
+        }
+    }
+
+    fn fold_all(&self, folded: bool) {
+        let continuation = match self.text_buffer.tag_table().lookup(CONTINUATION_TAG) {
+            Some(tag) => tag,
+            None => return,
+        };
+        let mut iter = self.text_buffer.start_iter();
+        while iter.forward_to_tag_toggle(Some(&continuation)) {
+            if iter.starts_tag(Some(&continuation)) {
+                let start = iter.clone();
+                iter.forward_to_tag_toggle(Some(&continuation));
+                self.set_folded(&start, &iter, folded);
+            }
+        }
+    }
+
+    /// Folds or unfolds the event shown at the line.
+    fn toggle_fold(&self, line: i32) {
+        let tag_table = self.text_buffer.tag_table();
+        let (continuation, folded) = match (tag_table.lookup(CONTINUATION_TAG), tag_table.lookup(FOLDED_TAG)) {
+            (Some(continuation), Some(folded)) => (continuation, folded),
+            _ => return,
+        };
+        if let Some(mut head) = self.text_buffer.iter_at_line(line) {
+            while head.has_tag(&continuation) && head.backward_line() {}
+            let mut start = head.clone();
+            if start.forward_line() && start.has_tag(&continuation) {
+                let end = self.event_end(&head);
+                self.set_folded(&start, &end, !start.has_tag(&folded));
+            }
+        }
+    }
+
+    /// End of the event starting at the iter, including its continuation lines.
+    fn event_end(&self, start: &TextIter) -> TextIter {
+        let mut end = start.clone();
+        end.forward_to_line_end();
+        if let Some(continuation) = self.text_buffer.tag_table().lookup(CONTINUATION_TAG) {
+            let mut next = end.clone();
+            while next.forward_line() && next.has_tag(&continuation) {
+                end = next.clone();
+                end.forward_to_line_end();
+            }
+        }
+        end
+    }
+
+    /// Replaces the filters by the patterns of a filter set or workspace, invalid ones are skipped.
+    fn set_filters(&mut self, include: &[String], exclude: &[String], fields: &[String], origin: &str) {
+        self.filters.clear();
+        let include = include.iter().map(|p| (FilterKind::Include, p));
+        let exclude = exclude.iter().map(|p| (FilterKind::Exclude, p));
+        let fields = fields.iter().map(|p| (FilterKind::Field, p));
+        for (kind, pattern) in include.chain(exclude).chain(fields) {
+            match LineFilter::new(kind, pattern) {
+                Ok(filter) => self.filters.push(filter),
+                Err(e) => log::warn!("Invalid filter {} in {}: {}", pattern, origin, e),
+            }
+        }
+        self.apply_filters();
+    }
+
+    /// Sends the filters to the worker which answers with all lines that pass them.
+    fn apply_filters(&mut self) {
+        self.update_filter_chips();
+        if let Err(e) = self.worker_action.send(WorkerData::SetFilters(self.filters.clone(), self.settings.show_fields)) {
+            log::error!("Could not send msg to worker: {}", e);
+        }
+    }
+
+    fn update_filter_chips(&self) {
+        while let Some(child) = self.filter_chips.first_child() {
+            self.filter_chips.remove(&child);
+        }
+        for (idx, filter) in self.filters.iter().enumerate() {
+            let chip = gtk::builders::ButtonBuilder::new()
+                .label(&format!("{} {}", filter.kind.symbol(), filter.pattern))
+                .tooltip_text("Remove filter")
+                .margin_end(DEFAULT_MARGIN)
+                .build();
+            let tx = self.sender.clone();
+            chip.connect_clicked(move |_| {
+                tx(LogViewMsg::RemoveFilter(idx));
+            });
+            self.filter_chips.append(&chip);
         }
     }
 
+    /// Removes the first lines of the text buffer along with their markers.
+    fn remove_first_lines(&mut self, lines: usize) {
+        if lines == 0 {
+            return;
+        }
+        // Markers of removed lines would otherwise end up at the start of the buffer
+        for line in 0..lines {
+            if let Some(iter) = self.text_buffer.iter_at_line(line as i32) {
+                for mark in iter.marks() {
+                    if let Some(name) = mark.name().map(|n| n.to_string()) {
+                        if self.search_match_markers.contains(&name) || Uuid::parse_str(&name).is_ok() {
+                            self.search_match_markers.retain(|m| m != &name);
+                            self.text_buffer.delete_mark(&mark);
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut start = self.text_buffer.start_iter();
+        let mut end = self.text_buffer.iter_at_line(lines as i32).unwrap_or(self.text_buffer.end_iter());
+        self.text_buffer.delete(&mut start, &mut end);
+        self.current_search_match_pos.take();
+        self.update_search_label();
+    }
+
+    fn update_buffer_stats_label(&self, stats: BufferStats) {
+        let mut label = vec![];
+        if !self.filters.is_empty() || stats.hidden > 0 {
+            label.push(format!("{} shown, {} hidden", stats.shown, stats.hidden));
+        }
+        if stats.dropped > 0 {
+            label.push(format!("{} earlier lines dropped", stats.dropped));
+        }
+        self.buffer_stats_lbl.set_label(&label.join(" | "));
+        self.buffer_stats_lbl.set_tooltip_text(if stats.dropped > 0 {
+            Some("The oldest lines were dropped to stay within the line and byte budget of the log view")
+        } else {
+            None
+        });
+    }
+
+    fn update_filter_sets(&self) {
+        self.filter_set_selector.remove_all();
+        if let Ok(cfg) = CONFIG.lock() {
+            for filter_set in &cfg.filter_sets {
+                self.filter_set_selector.append(Some(&filter_set.name), &filter_set.name);
+            }
+        }
+    }
+
+    fn update_stream_status_label(&self) {
+        let mut reconnecting = vec![];
+        let mut stopped = vec![];
+        let mut reasons = vec![];
+        for (name, status) in &self.stream_states {
+            match &status.state {
+                StreamState::Reconnecting(reason) => {
+                    reconnecting.push(name.as_str());
+                    reasons.push(format!("{}: {}", name, reason));
+                }
+                StreamState::Stopped => stopped.push(name.as_str()),
+                StreamState::Connected => {}
+            }
+        }
+
+        let mut label = vec![];
+        if reconnecting.len() > 0 {
+            label.push(format!("Reconnecting: {}", reconnecting.join(", ")));
+        }
+        if stopped.len() > 0 {
+            label.push(format!("Stopped: {}", stopped.join(", ")));
+        }
+        self.stream_status_lbl.set_label(&label.join(" | "));
+        self.stream_status_lbl.set_tooltip_text(Some(&reasons.join("\n")));
+    }
+
     fn update_search_label(&self) {
         if self.search_match_markers.len() <= 0 {
             self.search_results_lbl.set_label("");
@@ -161,8 +596,7 @@ impl LogView {
                 self.scroll_to_mark(&next_marker);
                 self.clear_active_search_highlight();
                 let line_start = self.text_buffer.iter_at_mark(&marker);
-                let mut line_end = line_start.clone();
-                line_end.forward_to_line_end();
+                let line_end = self.event_end(&line_start);
                 self.text_buffer.apply_tag_by_name(SELECTED_SEARCH_TAG, &line_start, &line_end);
                 self.current_search_match_pos = Some(pos);
             }
@@ -170,13 +604,16 @@ impl LogView {
     }
 
     fn reload(&mut self) -> Command<LogViewMsg> {
-        if let Some(pods) = self.selected_pods.as_ref()
-            .map(|pods|pods.clone())
-        {
+        if let Some(source) = self.selected_source.clone() {
             self.clear();
             let tx = self.sender.clone();
-            let ctx = self.selected_context.clone().unwrap();
-            return self.run_async(load_log_stream(ctx, pods, tx, self.since_seconds));
+            let ctx = self.selected_context.clone();
+            // Recordings are not recorded again
+            let recorder = match source {
+                LogSource::Recording(_) => None,
+                _ => Some(self.recorder.clone()),
+            };
+            return self.run_async(load_log_stream(ctx, source, recorder, tx, self.since_seconds));
         }
         Command::None
     }
@@ -224,8 +661,12 @@ impl LogView {
 enum WorkerData {
     ProcessLogData(Vec<LogData>),
     ProcessHighlighters(Vec<SearchData>, LogData, String),
+    SetFilters(LineFilters, bool),
+    SelectParser(String, ParserKind),
     Clear,
+    SetHighlighters(Vec<SearchData>, Vec<ExportHighlighter>),
     GetOffsetForTimestamp(i64),
+    Export(ExportTarget, Option<ExportRange>),
 }
 
 use gtk4_helper::model::prelude::*;
@@ -242,6 +683,12 @@ struct Settings {
     show_container_names: bool,
     #[field]
     show_timestamps: bool,
+    #[field]
+    show_fields: bool,
+    #[field]
+    fold_events: bool,
+    #[field]
+    render_ansi: bool,
 }
 
 impl Component for LogView {
@@ -255,8 +702,17 @@ impl Component for LogView {
             show_timestamps: cfg.log_view_settings.show_timestamps,
             show_container_names: cfg.log_view_settings.show_container_names,
             show_pod_names: cfg.log_view_settings.show_pod_names,
-            wrap_text: cfg.log_view_settings.wrap_text
+            wrap_text: cfg.log_view_settings.wrap_text,
+            show_fields: cfg.log_view_settings.show_fields,
+            fold_events: cfg.log_view_settings.fold_events,
+            render_ansi: cfg.log_view_settings.render_ansi,
         }).unwrap_or(Settings::default());
+        let (field_columns, mut parsers) = CONFIG.lock()
+            .map(|cfg| (
+                cfg.log_view_settings.field_columns.clone(),
+                LogParsers::new(cfg.log_view_settings.additional_fields.clone(), &cfg.log_view_settings.log4j_patterns, cfg.container_parsers())
+            ))
+            .unwrap_or((vec![], LogParsers::new(vec![], &[], HashMap::new())));
 
         let toolbar = gtk::builders::BoxBuilder::new()
             .margin_start(4)
@@ -275,7 +731,53 @@ impl Component for LogView {
         let since_selector = since_duration_selection(sender.clone());
         toolbar.append(&since_selector);
 
-        let search_results_lbl = add_search_toolbar(global_actions.clone(), &toolbar, sender.clone());
+        let min_severity = CONFIG.lock().ok()
+            .and_then(|cfg| cfg.log_view_settings.min_level.as_ref().and_then(|l| Severity::from_name(l)));
+        let min_severity_selector = min_severity_selection(sender.clone(), min_severity);
+        toolbar.append(&min_severity_selector);
+
+        let record_btn = toggle_btn(sender.clone(), "Record", |active| LogViewMsg::ToggleRecording(active));
+        record_btn.set_tooltip_text(Some("Record the lines of the selected pods to disk"));
+        toolbar.append(&record_btn);
+
+        let open_recording_btn = gtk::builders::ButtonBuilder::new()
+            .label("Open recording")
+            .margin_end(DEFAULT_MARGIN)
+            .build();
+        let tx = sender.clone();
+        open_recording_btn.connect_clicked(move |_| {
+            tx(LogViewMsg::OpenRecording);
+        });
+        toolbar.append(&open_recording_btn);
+
+        let open_files_btn = gtk::builders::ButtonBuilder::new()
+            .label("Open files")
+            .tooltip_text("Open local log files, e.g. kubectl logs --timestamps output or docker json-file logs")
+            .margin_end(DEFAULT_MARGIN)
+            .build();
+        let tx = sender.clone();
+        open_files_btn.connect_clicked(move |_| {
+            tx(LogViewMsg::OpenLocalSources);
+        });
+        toolbar.append(&open_files_btn);
+
+        let export_btn = gtk::builders::ButtonBuilder::new()
+            .label("Export")
+            .tooltip_text("Export the shown lines, or the time range selected in the overview")
+            .margin_end(DEFAULT_MARGIN)
+            .build();
+        let tx = sender.clone();
+        export_btn.connect_clicked(move |_| {
+            tx(LogViewMsg::ExportSelected);
+        });
+        toolbar.append(&export_btn);
+
+        let (search_results_lbl, search_entry) = add_search_toolbar(global_actions.clone(), &toolbar, sender.clone());
+
+        let stream_status_lbl = gtk::builders::LabelBuilder::new()
+            .margin_start(DEFAULT_MARGIN)
+            .build();
+        toolbar.append(&stream_status_lbl);
 
         let search_tag = TextTag::new(Some(SEARCH_TAG));
         search_tag.set_background(Some(SEARCH_COLOR));
@@ -287,9 +789,18 @@ impl Component for LogView {
         let background = selected_search_tag.background_rgba();
         selected_search_tag.set_foreground_rgba(matching_foreground_color_for_background(&background).as_ref());
 
+        let continuation_tag = TextTag::new(Some(CONTINUATION_TAG));
+        let folded_tag = TextTag::new(Some(FOLDED_TAG));
+        folded_tag.set_invisible(true);
+        let folded_head_tag = TextTag::new(Some(FOLDED_HEAD_TAG));
+        folded_head_tag.set_underline(gtk::pango::Underline::Single);
+
         let tag_table = TextTagTable::new();
         tag_table.add(&search_tag);
         tag_table.add(&selected_search_tag);
+        tag_table.add(&continuation_tag);
+        tag_table.add(&folded_tag);
+        tag_table.add(&folded_head_tag);
 
         let buffer = sourceview5::Buffer::new(Some(&tag_table));
         let log_data_view = sourceview5::View::builder()
@@ -309,33 +820,31 @@ impl Component for LogView {
             .vexpand(true)
             .build();
 
-        let search: Vec<SearchData> = if let Ok(cfg) = CONFIG.lock() {
-            for highlighter  in &cfg.highlighters {
-                let tag = TextTag::new(Some(&highlighter.name));
-                tag.set_background(Some(&highlighter.color));
-                let background = tag.background_rgba();
-                tag.set_foreground_rgba(matching_foreground_color_for_background(&background).as_ref());
-                tag_table.add(&tag);
-            }
-
+        let highlighter_rules = if let Ok(cfg) = CONFIG.lock() {
             util::add_css_with_name(&log_data_view,
             "textview",
             &format!("#textview {{ font: {}; }}", cfg.log_view_settings.font)
             );
-
-            let mut search_data = vec![];
-            for highlighter in &cfg.highlighters {
-                if let Ok(regex) = Regex::new(&highlighter.search) {
-                    search_data.push(SearchData {
-                        search: regex,
-                        name: highlighter.name.clone(),
-                    });
-                }
-            }
-            search_data
+            cfg.highlighters.clone()
         } else {
             vec![]
         };
+        add_highlighter_tags(&tag_table, &highlighter_rules);
+        let search = highlighter_search_data(&highlighter_rules);
+
+        // Double clicking an event folds or unfolds its continuation lines
+        let fold_gesture = gtk::GestureClick::new();
+        let text_view = log_data_view.clone();
+        let tx = sender.clone();
+        fold_gesture.connect_pressed(move |_, n_press, x, y| {
+            if n_press == 2 {
+                let (x, y) = text_view.window_to_buffer_coords(gtk::TextWindowType::Widget, x as i32, y as i32);
+                if let Some(iter) = text_view.iter_at_location(x, y) {
+                    tx(LogViewMsg::ToggleFold(iter.line()));
+                }
+            }
+        });
+        log_data_view.add_controller(&fold_gesture);
 
         let scroll_wnd = gtk::ScrolledWindow::new();
         scroll_wnd.set_child(Some(&log_data_view));
@@ -352,40 +861,84 @@ impl Component for LogView {
             .position(110)
             .build();
 
+        let (filter_toolbar, filter_chips, buffer_stats_lbl, filter_set_selector) = filter_toolbar(sender.clone());
+
         let container = gtk::Box::new(gtk::Orientation::Vertical, 0);
         container.append(&toolbar);
+        container.append(&filter_toolbar);
         container.append(&pane);
 
+        let (max_lines, max_bytes) = CONFIG.lock()
+            .map(|cfg| (cfg.log_view_settings.max_lines, cfg.log_view_settings.max_bytes))
+            .unwrap_or((usize::MAX, usize::MAX));
+        let show_fields = settings.show_fields;
         let (w_tx, w_rx) = std::sync::mpsc::channel::<WorkerData>();
         let tx = sender.clone();
+        let mut worker_highlighters = search.clone();
+        let mut export_highlighters = export_highlighters(&highlighter_rules);
+        let mut filters = LineFilters::default();
+        filters.set_min_severity(min_severity);
+        let worker_filters = filters.clone();
         std::thread::spawn(move || {
-            let mut log_entry_times: Vec<i64> = vec![];
+            let mut log_buffer = LogBuffer::new(max_lines, max_bytes);
+            log_buffer.set_show_fields(show_fields);
+            log_buffer.set_filters(worker_filters);
             while let Ok(data) = w_rx.recv() {
                 match data {
                     WorkerData::ProcessLogData(data) => {
                         let mut res = vec![];
-                        for datum in data {
-                            let timestamp = datum.timestamp.timestamp_nanos();
-                            let mut offset = search_offset(&log_entry_times, timestamp);
-                            let len = log_entry_times.len();
-                            while offset < len && log_entry_times[offset] == timestamp {
-                                offset += 1;
-                            }
-                            log_entry_times.insert(offset, timestamp);
-                            // We need to insert a extra entry for lines starting with a linefeed or a new line
-                            if datum.text.starts_with("\r") || datum.text.starts_with("\n") {
-                                // Sourceview seems to ignore those
-                                if datum.text != "\r\n" && datum.text != "\n" {
-                                    log_entry_times.insert(offset, timestamp);
-                                }
+                        for mut datum in data {
+                            parsers.parse_data(&mut datum);
+                            if let Some(offset) = log_buffer.push(datum.clone()) {
+                                res.push((offset as i64, datum));
                             }
-                            res.push((offset as i64, datum));
                         }
 
-                        tx(LogViewMsg::LogDataProcessed(res))
+                        let evicted = log_buffer.evict();
+                        tx(LogViewMsg::LogDataProcessed(res));
+                        if evicted.dropped > 0 {
+                            let highlights = evicted.data.iter().map(|datum| HighlightResultData {
+                                text_marker_id: String::new(),
+                                timestamp: datum.timestamp,
+                                matching_highlighters: worker_highlighters.iter()
+                                    .filter(|h| h.search.is_match(&datum.text))
+                                    .map(|h| h.name.clone())
+                                    .collect(),
+                            }).filter(|h| h.matching_highlighters.len() > 0).collect();
+                            let levels = evicted.data.iter()
+                                .filter_map(|datum| datum.severity.map(|s| (datum.timestamp, s)))
+                                .collect();
+                            tx(LogViewMsg::LogDataEvicted(EvictedData {
+                                text_lines: evicted.text_lines,
+                                highlights,
+                                levels,
+                                oldest: log_buffer.first_entry_time(),
+                            }));
+                        }
+                        tx(LogViewMsg::BufferStats(log_buffer.stats()));
+                    }
+                    WorkerData::SelectParser(container, kind) => {
+                        parsers.select(&container, kind);
+                        let res = log_buffer.update_fields(|datum| {
+                            if datum.container == container {
+                                parsers.parse_data(datum);
+                            }
+                        });
+                        tx(LogViewMsg::LogDataFiltered(res));
+                        tx(LogViewMsg::BufferStats(log_buffer.stats()));
+                    }
+                    WorkerData::SetFilters(filters, show_fields) => {
+                        log_buffer.set_show_fields(show_fields);
+                        let res = log_buffer.set_filters(filters);
+                        tx(LogViewMsg::LogDataFiltered(res));
+                        tx(LogViewMsg::BufferStats(log_buffer.stats()));
                     }
                     WorkerData::Clear => {
-                        log_entry_times.clear();
+                        log_buffer.clear();
+                    }
+                    WorkerData::SetHighlighters(highlighters, export) => {
+                        worker_highlighters = highlighters;
+                        export_highlighters = export;
                     }
                     WorkerData::ProcessHighlighters(highlighters, data, text_marker_id) => {
                         let mut res = HighlightResultData {
@@ -401,9 +954,18 @@ impl Component for LogView {
                         }
                         tx(LogViewMsg::HighlightResult(res));
                     }
+                    WorkerData::Export(target, range) => {
+                        let res = std::fs::File::create(&target.path)
+                            .map_err(anyhow::Error::from)
+                            .and_then(|file| {
+                                let lines = log_buffer.shown_lines().filter(|datum| in_range(datum, range.as_ref()));
+                                export(&mut std::io::BufWriter::new(file), target.format, lines, &export_highlighters)
+                            });
+                        tx(LogViewMsg::Exported(target.path, res.map_err(AppError::from)));
+                    }
                     WorkerData::GetOffsetForTimestamp(timestamp) => {
                         let ts = timestamp * 1000 * 1000 * 1000; // Seconds to nanoseconds
-                        let offset = search_offset(&log_entry_times, ts);
+                        let offset = log_buffer.offset_for_timestamp(ts);
                         tx(LogViewMsg::ScrollToLine(offset as i64));
                     }
                 }
@@ -417,17 +979,30 @@ impl Component for LogView {
             text_buffer: buffer,
             text_view: log_data_view,
             selected_context: None,
-            selected_pods: None,
+            selected_source: None,
             since_seconds: 60*10,
             active_search: None,
             highlighters: search,
+            highlighter_rules,
             scroll_handler: None,
             overview,
             search_match_markers: vec![],
             search_results_lbl,
+            stream_status_lbl,
+            stream_states: BTreeMap::new(),
             current_search_match_pos: None,
+            filters,
+            filter_chips,
+            buffer_stats_lbl,
+            filter_set_selector,
+            since_selector,
+            search_entry,
+            selected_range: None,
+            recorder: Arc::new(std::sync::Mutex::new(None)),
+            record_btn,
             worker_action: w_tx,
             settings,
+            field_columns,
             settings_obj
         }
     }
@@ -435,67 +1010,141 @@ impl Component for LogView {
     fn update(&mut self, msg: Self::Msg) -> Command<Self::Msg> {
         match msg {
             LogViewMsg::PodSelected(pod_data) => {
-                self.selected_pods = Some(pod_data.clone());
-                self.clear();
-
-                let tx = self.sender.clone();
-                let ctx = self.selected_context.clone().unwrap();
-                return self.run_async(load_log_stream(ctx, pod_data, tx, self.since_seconds));
+                self.selected_source = Some(LogSource::Pods(pod_data, PreviousLogs::Exclude));
+                return self.reload();
+            }
+            LogViewMsg::PreviousLogsSelected(pod_data, previous) => {
+                self.selected_source = Some(LogSource::Pods(pod_data, previous));
+                return self.reload();
+            }
+            LogViewMsg::WorkloadSelected(label_selector) => {
+                self.selected_source = Some(LogSource::LabelSelector(label_selector));
+                return self.reload();
             }
             LogViewMsg::Loaded(exit_tx) => {
                 self.exit_trigger = Some(exit_tx);
             }
+            LogViewMsg::StreamStatus(status) => {
+                let name = format!("{} ({})", status.pod, status.container);
+                if status.state == StreamState::Connected {
+                    self.stream_states.remove(&name);
+                } else {
+                    self.stream_states.insert(name, status);
+                }
+                self.update_stream_status_label();
+            }
+            LogViewMsg::LoadFailed(e) => {
+                show_and_log_error("Failed to load logs", &e.to_string(), None);
+            }
             LogViewMsg::LogDataLoaded(data) => {
-                let timestamps: Vec<DateTime<Utc>> = data.iter().map(|d| d.timestamp.clone()).collect();
-                self.overview.update(LogOverviewMsg::LogData(timestamps));
                 if let Err(e) = self.worker_action.send(WorkerData::ProcessLogData(data)) {
                     eprint!("Could not send msg to worker: {}", e);
                 }
             }
             LogViewMsg::LogDataProcessed(res) => {
-                for (idx, data) in res {
-                    if let Some(mut insert_at) = self.text_buffer.iter_at_line(idx as i32) {
-                        let mut log_line = String::new();
-                        if self.settings.show_pod_names {
-                            log_line.push_str(&data.pod)
-                        }
-                        if self.settings.show_container_names {
-                            log_line.push_str(&format!(" {}", data.container))
-                        }
-                        if self.settings.show_timestamps {
-                            log_line.push_str(&format!(" {}", data.timestamp))
-                        }
-
-                        log_line.push_str(&format!(" {}", data.text));
-                        self.text_buffer.insert(&mut insert_at, &log_line);
-
-                        let mut highlighters = self.highlighters.clone();
-                        if let Some(query) = self.active_search.as_ref() {
-                            highlighters.push(SearchData {
-                                search: query.clone(),
-                                name: SEARCH_TAG.to_string(),
-                            });
-                        }
+                self.insert_log_data(res);
+            }
+            LogViewMsg::LogDataFiltered(res) => {
+                self.clear_text();
+                self.insert_log_data(res);
+            }
+            LogViewMsg::LogDataEvicted(evicted) => {
+                self.remove_first_lines(evicted.text_lines);
+                self.overview.update(LogOverviewMsg::Evicted(evicted.highlights, evicted.levels, evicted.oldest));
+            }
+            LogViewMsg::BufferStats(stats) => {
+                self.update_buffer_stats_label(stats);
+            }
+            LogViewMsg::AddFilter(kind, pattern) => {
+                match LineFilter::new(kind, &pattern) {
+                    Ok(filter) => {
+                        self.filters.push(filter);
+                        self.apply_filters();
+                    }
+                    Err(e) => show_and_log_error("Invalid filter", &e.to_string(), None),
+                }
+            }
+            LogViewMsg::RemoveFilter(idx) => {
+                self.filters.remove(idx);
+                self.apply_filters();
+            }
+            LogViewMsg::FilterSetSelected(name) => {
+                let filter_set = CONFIG.lock().ok().and_then(|cfg| cfg.filter_set(&name).cloned());
+                if let Some(filter_set) = filter_set {
+                    self.set_filters(&filter_set.include, &filter_set.exclude, &filter_set.fields, &format!("filter set {}", name));
+                }
+            }
+            LogViewMsg::OpenWorkspace(workspace) => {
+                self.set_display_settings(&workspace.display);
 
-                        let text_marker_id = Uuid::new_v4().to_string();
-                        if let Some(iter) = self.text_buffer.iter_at_line(insert_at.line() - 1) {
-                            self.text_buffer.add_mark(&gtk::TextMark::new(Some(&text_marker_id), false), &iter);
-                        }
+                self.set_filters(&workspace.include, &workspace.exclude, &workspace.fields, &format!("workspace {}", workspace.name));
 
-                        if let Err(e) = self.worker_action.send(WorkerData::ProcessHighlighters(highlighters, data, text_marker_id)) {
-                            log::error!("Could not send msg to worker: {}", e);
-                        }
-                    } else {
-                        log::error!("No iter at line: {}", idx);
+                if let Some(highlighters) = workspace.highlighters.clone() {
+                    self.set_highlighters(highlighters);
+                }
+                if workspace.search.is_none() {
+                    self.search_entry.set_text("");
+                }
+                return self.update(LogViewMsg::OpenTail(TailTarget::for_workspace(&workspace)));
+            }
+            LogViewMsg::SaveWorkspace(name) => {
+                let ctx = match self.selected_context.as_ref() {
+                    Some(ctx) => ctx.clone(),
+                    None => {
+                        show_and_log_error("Could not save workspace", "No namespace selected", None);
+                        return Command::None;
                     }
+                };
+                let (pods, label_selector) = match self.selected_source.as_ref() {
+                    Some(LogSource::Pods(pods, _)) => (pods.iter().map(|pod| pod.name.clone()).collect(), None),
+                    Some(LogSource::LabelSelector(label_selector)) => (vec![], Some(label_selector.clone())),
+                    _ => (vec![], None),
+                };
+                let search = self.search_entry.text().to_string();
+                let workspace = Workspace {
+                    name,
+                    config_path: ctx.config_path,
+                    context: ctx.context,
+                    namespace: ctx.name,
+                    pods,
+                    label_selector,
+                    since_seconds: Some(self.since_seconds),
+                    search: Some(search).filter(|s| !s.is_empty()),
+                    display: DisplaySettings {
+                        wrap_text: self.settings.wrap_text,
+                        show_pod_names: self.settings.show_pod_names,
+                        show_container_names: self.settings.show_container_names,
+                        show_timestamps: self.settings.show_timestamps,
+                        show_fields: self.settings.show_fields,
+                        fold_events: self.settings.fold_events,
+                        render_ansi: self.settings.render_ansi,
+                    },
+                    include: self.filters.patterns(FilterKind::Include),
+                    exclude: self.filters.patterns(FilterKind::Exclude),
+                    fields: self.filters.patterns(FilterKind::Field),
+                    highlighters: Some(self.highlighter_rules.clone()),
+                };
+                if let Ok(mut cfg) = CONFIG.lock() {
+                    cfg.save_workspace(workspace);
+                }
+            }
+            LogViewMsg::SaveFilterSet(name) => {
+                if let Ok(mut cfg) = CONFIG.lock() {
+                    cfg.save_filter_set(FilterSet {
+                        name: name.clone(),
+                        include: self.filters.patterns(FilterKind::Include),
+                        exclude: self.filters.patterns(FilterKind::Exclude),
+                        fields: self.filters.patterns(FilterKind::Field),
+                    });
                 }
+                self.update_filter_sets();
+                self.filter_set_selector.set_active_id(Some(&name));
             }
             LogViewMsg::HighlightResult(res) => {
                 self.overview.update(LogOverviewMsg::HighlightResults(res.clone()));
                 for highlighter_name in res.matching_highlighters {
                     if let Some(start) = self.text_buffer.mark(&res.text_marker_id).map(|m| self.text_buffer.iter_at_mark(&m)) {
-                        let mut end = start.clone();
-                        end.forward_to_line_end();
+                        let end = self.event_end(&start);
                         self.text_buffer.apply_tag_by_name(&highlighter_name, &start, &end);
 
                         if &highlighter_name == SEARCH_TAG {
@@ -515,7 +1164,8 @@ impl Component for LogView {
                     self.active_search.take();
                 } else {
                     self.active_search = Regex::new(&format!("(?i){}", query)).ok();
-                    let text = self.text_buffer.text(&start, &end, false).to_string();
+                    // Folded lines count as well to keep the line indexes in sync
+                    let text = self.text_buffer.text(&start, &end, true).to_string();
                     if let Some(query) = &self.active_search {
                         return self.run_async(search( query.clone(), text));
                     }
@@ -536,11 +1186,40 @@ impl Component for LogView {
             LogViewMsg::ContextSelected(ctx) => {
                 self.selected_context = Some(ctx);
             }
+            LogViewMsg::OpenTail(target) => {
+                self.selected_context = Some(target.namespace_view_data());
+                if let Some(since_seconds) = target.since_seconds {
+                    self.since_seconds = since_seconds;
+                    let id = since_seconds.to_string();
+                    if !self.since_selector.set_active_id(Some(&id)) {
+                        self.since_selector.append(Some(&id), &format_age(chrono::Duration::seconds(since_seconds as i64)));
+                        self.since_selector.set_active_id(Some(&id));
+                    }
+                }
+                if let Some(search) = target.search.as_ref() {
+                    self.search_entry.set_text(search);
+                    let tx = self.sender.clone();
+                    tx(LogViewMsg::Search(search.clone()));
+                }
+                match target.selector.clone() {
+                    Some(label_selector) => {
+                        self.selected_source = Some(LogSource::LabelSelector(label_selector));
+                        return self.reload();
+                    }
+                    None if target.pods.len() > 0 => {
+                        return self.run_async(open_pods(target));
+                    }
+                    None => {}
+                }
+            }
             LogViewMsg::EnableScroll(enable) => {
                 self.scroll_to_bottom(enable);
             }
             LogViewMsg::ToggleShowContainerNames => {
                 let settings: Settings = Settings::from_object(&self.settings_obj);
+                if settings.show_container_names == self.settings.show_container_names {
+                    return Command::None;
+                }
                 self.settings.show_container_names = settings.show_container_names;
                 if let Ok(mut cfg) = CONFIG.lock() {
                     cfg.log_view_settings.show_container_names = settings.show_container_names;
@@ -549,6 +1228,9 @@ impl Component for LogView {
             }
             LogViewMsg::ToggleShowPodNames => {
                 let settings: Settings = Settings::from_object(&self.settings_obj);
+                if settings.show_pod_names == self.settings.show_pod_names {
+                    return Command::None;
+                }
                 self.settings.show_pod_names = settings.show_pod_names;
                 if let Ok(mut cfg) = CONFIG.lock() {
                     cfg.log_view_settings.show_pod_names = settings.show_pod_names;
@@ -557,14 +1239,69 @@ impl Component for LogView {
             }
             LogViewMsg::ToggleShowTimestamps => {
                 let settings: Settings = Settings::from_object(&self.settings_obj);
+                if settings.show_timestamps == self.settings.show_timestamps {
+                    return Command::None;
+                }
                 self.settings.show_timestamps = settings.show_timestamps;
                 if let Ok(mut cfg) = CONFIG.lock() {
                     cfg.log_view_settings.show_timestamps = settings.show_timestamps;
                 }
                 return self.reload();
             }
+            LogViewMsg::ToggleShowFields => {
+                let settings: Settings = Settings::from_object(&self.settings_obj);
+                if settings.show_fields == self.settings.show_fields {
+                    return Command::None;
+                }
+                self.settings.show_fields = settings.show_fields;
+                if let Ok(mut cfg) = CONFIG.lock() {
+                    cfg.log_view_settings.show_fields = settings.show_fields;
+                }
+                // Lines are composed again from the buffered data
+                self.apply_filters();
+            }
+            LogViewMsg::ToggleFoldEvents => {
+                let settings: Settings = Settings::from_object(&self.settings_obj);
+                if settings.fold_events == self.settings.fold_events {
+                    return Command::None;
+                }
+                self.settings.fold_events = settings.fold_events;
+                if let Ok(mut cfg) = CONFIG.lock() {
+                    cfg.log_view_settings.fold_events = settings.fold_events;
+                }
+                self.fold_all(settings.fold_events);
+            }
+            LogViewMsg::ToggleRenderAnsi => {
+                let settings: Settings = Settings::from_object(&self.settings_obj);
+                if settings.render_ansi == self.settings.render_ansi {
+                    return Command::None;
+                }
+                self.settings.render_ansi = settings.render_ansi;
+                if let Ok(mut cfg) = CONFIG.lock() {
+                    cfg.log_view_settings.render_ansi = settings.render_ansi;
+                }
+                self.apply_filters();
+            }
+            LogViewMsg::ToggleFold(line) => {
+                self.toggle_fold(line);
+            }
+            LogViewMsg::ParserSelected(container, kind) => {
+                if let Err(e) = self.worker_action.send(WorkerData::SelectParser(container, kind)) {
+                    log::error!("Could not send msg to worker: {}", e);
+                }
+            }
+            LogViewMsg::MinSeveritySelected(min_severity) => {
+                self.filters.set_min_severity(min_severity);
+                if let Ok(mut cfg) = CONFIG.lock() {
+                    cfg.log_view_settings.min_level = min_severity.map(|s| s.name().to_string());
+                }
+                self.apply_filters();
+            }
             LogViewMsg::ToggleWrapText => {
                 let settings: Settings = Settings::from_object(&self.settings_obj);
+                if settings.wrap_text == self.settings.wrap_text {
+                    return Command::None;
+                }
                 self.settings.wrap_text = settings.wrap_text;
                 if let Ok(mut cfg) = CONFIG.lock() {
                     cfg.log_view_settings.wrap_text = settings.wrap_text;
@@ -605,8 +1342,12 @@ impl Component for LogView {
                 self.highlight_search_at_pos(next_pos);
             }
             LogViewMsg::SinceTimespanChanged(id) => {
-                self.since_seconds = id.parse::<u32>().expect("Since seconds should be an u32");
-                return self.reload();
+                let since_seconds = id.parse::<u32>().expect("Since seconds should be an u32");
+                // Already applied if the selection was changed by opening a tail
+                if since_seconds != self.since_seconds {
+                    self.since_seconds = since_seconds;
+                    return self.reload();
+                }
             }
             LogViewMsg::LogOverview(msg) => {
                 if let LogOverviewMsg::MouseClick((timestamp, _)) = &msg {
@@ -614,8 +1355,95 @@ impl Component for LogView {
                         log::error!("Could not send msg: {}", e);
                     }
                 }
+                if let LogOverviewMsg::RangeSelected(range) = &msg {
+                    // The overview works in seconds, the end second is included
+                    self.selected_range = range.map(|(from, to)| (Utc.timestamp(from, 0), Utc.timestamp(to + 1, 0)));
+                }
                 self.overview.update(msg);
             }
+            LogViewMsg::ToggleRecording(active) => {
+                let mut recorder = match self.recorder.lock() {
+                    Ok(recorder) => recorder,
+                    Err(e) => {
+                        log::error!("Could not lock recorder: {}", e);
+                        return Command::None;
+                    }
+                };
+                if !active {
+                    if let Some(mut session) = recorder.take() {
+                        if let Err(e) = session.flush() {
+                            log::error!("Could not finish recording: {}", e);
+                        }
+                    }
+                    return Command::None;
+                }
+                if recorder.is_some() {
+                    return Command::None;
+                }
+                let name = self.selected_context.as_ref()
+                    .map(|ctx| format!("{}-{}", ctx.context, ctx.name))
+                    .unwrap_or("session".to_string());
+                let path = recording_path(&name);
+                match SessionRecorder::create(&path) {
+                    Ok(session) => {
+                        log::info!("Recording to {}", path.display());
+                        recorder.replace(session);
+                        drop(recorder);
+                        // Reloading records the whole since window
+                        if !matches!(self.selected_source, Some(LogSource::Recording(_))) {
+                            return self.reload();
+                        }
+                    }
+                    Err(e) => {
+                        drop(recorder);
+                        self.record_btn.set_active(false);
+                        show_and_log_error("Could not start recording", &format!("{}: {}", path.display(), e), None);
+                    }
+                }
+            }
+            LogViewMsg::RecordingFailed(e) => {
+                // The recorder was already dropped
+                self.record_btn.set_active(false);
+                show_and_log_error("Recording stopped", &e, None);
+            }
+            LogViewMsg::OpenRecording => {
+                let window = self.container.root().and_then(|root| root.downcast::<gtk::Window>().ok());
+                return self.run_async(select_recording(window));
+            }
+            LogViewMsg::RecordingSelected(path) => {
+                if let Some(path) = path {
+                    self.selected_source = Some(LogSource::Recording(path));
+                    return self.reload();
+                }
+            }
+            LogViewMsg::OpenLocalSources => {
+                let window = self.container.root().and_then(|root| root.downcast::<gtk::Window>().ok());
+                return self.run_async(select_local_sources(window));
+            }
+            LogViewMsg::LocalSourcesSelected(sources, follow) => {
+                if sources.len() > 0 {
+                    self.selected_source = Some(LogSource::Local(sources, follow));
+                    return self.reload();
+                }
+            }
+            LogViewMsg::ExportSelected => {
+                let window = self.container.root().and_then(|root| root.downcast::<gtk::Window>().ok());
+                return self.run_async(select_export_target(window, self.selected_range.is_some()));
+            }
+            LogViewMsg::ExportTargetSelected(target) => {
+                if let Some(target) = target {
+                    let range = self.selected_range.filter(|_| target.selection);
+                    if let Err(e) = self.worker_action.send(WorkerData::Export(target, range)) {
+                        log::error!("Could not send msg to worker: {}", e);
+                    }
+                }
+            }
+            LogViewMsg::Exported(path, res) => {
+                match res {
+                    Ok(lines) => log::info!("Exported {} lines to {}", lines, path.display()),
+                    Err(e) => show_and_log_error("Export failed", &format!("{}: {}", path.display(), e), None),
+                }
+            }
             LogViewMsg::ScrollToLine(idx) => {
                 if let Some(iter) = self.text_buffer.iter_at_line(idx as i32) {
                     if let Some(m) = self.text_buffer.mark(SCROLL_TO_LINE_MARKER) {
@@ -652,24 +1480,257 @@ async fn search(query: Regex, text: String) -> LogViewMsg {
     LogViewMsg::SearchResult(search_results)
 }
 
-async fn load_log_stream(ctx: NamespaceViewData, pods: Vec<PodViewData>, tx: Arc<dyn MsgHandler<LogViewMsg>>, since_seconds: u32) -> LogViewMsg {
-    let client = crate::log_stream::k8s_client(&ctx.config_path, &ctx.context);
-    let (log_stream, exit) = crate::log_stream::log_stream(&client, &ctx.name, pods, since_seconds).await;
+async fn select_recording(window: Option<gtk::Window>) -> LogViewMsg {
+    let dlg = gtk::FileChooserDialog::builder()
+        .title("Open recording")
+        .modal(true)
+        .action(gtk::FileChooserAction::Open)
+        .build();
+    dlg.set_transient_for(window.as_ref());
+    dlg.add_buttons(&[("Open", gtk::ResponseType::Ok), ("Cancel", gtk::ResponseType::Cancel)]);
+
+    let filter = gtk::FileFilter::new();
+    filter.set_name(Some("KTail recordings"));
+    filter.add_pattern(&format!("*.{}", crate::recording::EXTENSION));
+    dlg.add_filter(&filter);
+    if let Err(e) = dlg.set_current_folder(Some(&gio::File::for_path(crate::dirs::recordings_dir()))) {
+        log::warn!("Could not open recordings folder: {}", e);
+    }
+
+    let path = if dlg.run_future().await == gtk::ResponseType::Ok {
+        dlg.file().and_then(|file| file.path())
+    } else {
+        None
+    };
+
+    dlg.close();
+    LogViewMsg::RecordingSelected(path)
+}
+
+async fn select_local_sources(window: Option<gtk::Window>) -> LogViewMsg {
+    let dlg = gtk::FileChooserDialog::builder()
+        .title("Open log files")
+        .modal(true)
+        .action(gtk::FileChooserAction::Open)
+        .select_multiple(true)
+        .build();
+    dlg.set_transient_for(window.as_ref());
+    dlg.add_buttons(&[("Open", gtk::ResponseType::Ok), ("Cancel", gtk::ResponseType::Cancel)]);
+    // Without options the choice is a check box
+    dlg.add_choice("follow", "Follow", &[], &[]);
+
+    let mut sources = vec![];
+    let mut follow = false;
+    if dlg.run_future().await == gtk::ResponseType::Ok {
+        let files = dlg.files();
+        for idx in 0..files.n_items() {
+            if let Some(path) = files.item(idx).and_then(|item| item.downcast::<gio::File>().ok()).and_then(|file| file.path()) {
+                sources.push(LocalSource::File(path));
+            }
+        }
+        follow = dlg.choice("follow").map(|f| f.as_str() == "true").unwrap_or(false);
+    }
+
+    dlg.close();
+    LogViewMsg::LocalSourcesSelected(sources, follow)
+}
+
+async fn select_export_target(window: Option<gtk::Window>, has_selection: bool) -> LogViewMsg {
+    let dlg = gtk::FileChooserDialog::builder()
+        .title("Export logs")
+        .modal(true)
+        .action(gtk::FileChooserAction::Save)
+        .build();
+    dlg.set_transient_for(window.as_ref());
+    dlg.add_buttons(&[("Export", gtk::ResponseType::Ok), ("Cancel", gtk::ResponseType::Cancel)]);
+
+    let ids: Vec<&str> = ExportFormat::ALL.iter().map(|f| f.id()).collect();
+    let labels: Vec<&str> = ExportFormat::ALL.iter().map(|f| f.label()).collect();
+    dlg.add_choice("format", "Format", &ids, &labels);
+    dlg.set_choice("format", ExportFormat::Plain.id());
+    if has_selection {
+        dlg.add_choice("range", "Lines", &["all", "selection"], &["Whole buffer", "Selected time range"]);
+        dlg.set_choice("range", "selection");
+    }
+    dlg.set_current_name("ktail-export");
+
+    let target = if dlg.run_future().await == gtk::ResponseType::Ok {
+        let format = dlg.choice("format").and_then(|id| ExportFormat::from_id(&id)).unwrap_or(ExportFormat::Plain);
+        dlg.file().and_then(|file| file.path()).map(|mut path| {
+            if path.extension().is_none() {
+                path.set_extension(format.extension());
+            }
+            ExportTarget {
+                path,
+                format,
+                selection: dlg.choice("range").map(|r| r.as_str() == "selection").unwrap_or(false),
+            }
+        })
+    } else {
+        None
+    };
+
+    dlg.close();
+    LogViewMsg::ExportTargetSelected(target)
+}
+
+/// Records the lines as they come from the cluster, before they are processed.
+/// Adds a tag for each highlighter or updates its color.
+fn add_highlighter_tags(tag_table: &TextTagTable, highlighters: &[Highlighter]) {
+    for highlighter in highlighters {
+        let tag = tag_table.lookup(&highlighter.name).unwrap_or_else(|| {
+            let tag = TextTag::new(Some(&highlighter.name));
+            tag_table.add(&tag);
+            tag
+        });
+        tag.set_background(Some(&highlighter.color));
+        let background = tag.background_rgba();
+        tag.set_foreground_rgba(matching_foreground_color_for_background(&background).as_ref());
+    }
+}
+
+fn highlighter_search_data(highlighters: &[Highlighter]) -> Vec<SearchData> {
+    highlighters.iter().filter_map(|h| {
+        Some(SearchData {
+            search: Regex::new(&h.search).ok()?,
+            name: h.name.clone(),
+        })
+    }).collect()
+}
+
+fn export_highlighters(highlighters: &[Highlighter]) -> Vec<ExportHighlighter> {
+    highlighters.iter().filter_map(|h| {
+        let search = Regex::new(&h.search).ok()?;
+        let foreground = matching_foreground_color_for_background(&gdk::RGBA::parse(&h.color).ok())?;
+        Some(ExportHighlighter {
+            search,
+            background: h.color.clone(),
+            foreground: foreground.to_string(),
+        })
+    }).collect()
+}
+
+/// Stops the recording if the lines could not be written.
+fn record(recorder: &SharedRecorder, events: &[LogStreamEvent]) -> anyhow::Result<()> {
+    if let Ok(mut recorder) = recorder.lock() {
+        if let Some(session) = recorder.as_mut() {
+            let res = events.iter()
+                .filter_map(|event| match event {
+                    LogStreamEvent::Data(datum) => Some(datum),
+                    _ => None,
+                })
+                .try_for_each(|datum| session.record(datum))
+                .and_then(|_| session.flush());
+            if res.is_err() {
+                recorder.take();
+            }
+            return res;
+        }
+    }
+    Ok(())
+}
+
+fn cluster_client(ctx: Option<NamespaceViewData>) -> AppResult<(KubeClient, NamespaceViewData)> {
+    let ctx = ctx.ok_or(AppError::Msg("No namespace selected".to_string()))?;
+    let client = crate::log_stream::k8s_client(&ctx.config_path, &ctx.context)?;
+    Ok((client, ctx))
+}
+
+async fn open_pods(target: TailTarget) -> LogViewMsg {
+    let client = match crate::log_stream::k8s_client(&target.kubeconfig, &target.context) {
+        Ok(client) => client,
+        Err(e) => return LogViewMsg::LoadFailed(e),
+    };
+    match resolve_pods(&client, &target.context, &target.namespace, &target.pods).await {
+        Ok(pods) => LogViewMsg::PodSelected(pods),
+        Err(e) => LogViewMsg::LoadFailed(e),
+    }
+}
+
+async fn load_log_stream(ctx: Option<NamespaceViewData>, source: LogSource, recorder: Option<SharedRecorder>, tx: Arc<dyn MsgHandler<LogViewMsg>>, since_seconds: u32) -> LogViewMsg {
+    let (log_stream, exit) = match source {
+        LogSource::Pods(pods, previous) => {
+            let (client, ctx) = match cluster_client(ctx) {
+                Ok(client) => client,
+                Err(e) => return LogViewMsg::LoadFailed(e),
+            };
+            let (stream, exit) = crate::log_stream::log_stream(&client, &ctx.name, pods, since_seconds, previous).await;
+            (stream.boxed(), exit)
+        }
+        LogSource::LabelSelector(label_selector) => {
+            let (client, ctx) = match cluster_client(ctx) {
+                Ok(client) => client,
+                Err(e) => return LogViewMsg::LoadFailed(e),
+            };
+            let excluded_containers = CONFIG.lock()
+                .map(|cfg| cfg.excluded_containers(&ctx.context, &ctx.name))
+                .unwrap_or_default();
+            let (stream, exit) = crate::log_stream::workload_log_stream(&client, &ctx.name, &label_selector, since_seconds, excluded_containers).await;
+            (stream.boxed(), exit)
+        }
+        LogSource::Recording(path) => {
+            match replay_stream(&path) {
+                Ok((stream, exit)) => (stream.boxed(), exit),
+                Err(e) => return LogViewMsg::LoadFailed(AppError::Msg(format!("{}: {}", path.display(), e))),
+            }
+        }
+        LogSource::Local(sources, follow) => {
+            let (stream, exit) = local_log_stream(sources, follow);
+            (stream.boxed(), exit)
+        }
+    };
+    let continuation_patterns = CONFIG.lock()
+        .map(|cfg| cfg.log_view_settings.continuation_patterns.clone())
+        .unwrap_or_default();
     let tx = tx.clone();
     tokio::task::spawn(async move {
+        // Lines are grouped into events before they are merged, so stack traces stay together
+        let mut grouper = EventGrouper::new(ContinuationDetector::new(&continuation_patterns));
         // Throttle the stream to keep the ui responsive.
-        let mut throttled_stream = StreamExt::zip(
-            StreamExt::ready_chunks(log_stream, 1000),
-            IntervalStream::new(tokio::time::interval(std::time::Duration::from_millis(50)))
-        );
-        while let Some((data, _)) = throttled_stream.next().await {
+        let mut chunks = StreamExt::ready_chunks(log_stream, 1000);
+        let mut interval = tokio::time::interval(std::time::Duration::from_millis(50));
+        loop {
+            interval.tick().await;
+            let events = match tokio::time::timeout(EVENT_IDLE_TIMEOUT, chunks.next()).await {
+                Ok(Some(events)) => events,
+                Ok(None) => break,
+                // Nothing new, pending events are flushed below
+                Err(_) => vec![],
+            };
+            if let Some(recorder) = recorder.as_ref() {
+                if let Err(e) = record(recorder, &events) {
+                    tx(LogViewMsg::RecordingFailed(format!("Could not record lines: {}", e)));
+                }
+            }
+            let now = std::time::Instant::now();
+            let mut data = vec![];
+            for event in events {
+                match event {
+                    LogStreamEvent::Data(mut datum) => {
+                        // Escape sequences would break highlighters and search
+                        if let Some((text, styles)) = parse_ansi(&datum.text) {
+                            datum.text = text;
+                            datum.styles = styles;
+                        }
+                        data.extend(grouper.push(datum, now));
+                    }
+                    LogStreamEvent::Status(status) => tx(LogViewMsg::StreamStatus(status)),
+                }
+            }
+            data.extend(grouper.flush_idle(now, EVENT_IDLE_TIMEOUT));
+            if data.len() > 0 {
+                tx(LogViewMsg::LogDataLoaded(data));
+            }
+        }
+        let data = grouper.flush();
+        if data.len() > 0 {
             tx(LogViewMsg::LogDataLoaded(data));
         }
     });
     LogViewMsg::Loaded(Arc::new(exit))
 }
 
-fn add_search_toolbar<T: MsgHandler<LogViewMsg> + Clone>(global_actions: Rc<SimpleActionGroup>,toolbar: &gtk::Box, sender: T) -> gtk::Label {
+fn add_search_toolbar<T: MsgHandler<LogViewMsg> + Clone>(global_actions: Rc<SimpleActionGroup>,toolbar: &gtk::Box, sender: T) -> (gtk::Label, gtk::SearchEntry) {
     let search_entry = gtk::builders::SearchEntryBuilder::new()
         .placeholder_text("Search")
         .margin_end(DEFAULT_MARGIN)
@@ -691,8 +1752,9 @@ fn add_search_toolbar<T: MsgHandler<LogViewMsg> + Clone>(global_actions: Rc<Simp
     });
 
     let action = gio::SimpleAction::new("search", None);
+    let entry = search_entry.clone();
     action.connect_activate(move |_,_|{
-        search_entry.grab_focus();
+        entry.grab_focus();
     });
     global_actions.add_action(&action);
 
@@ -730,11 +1792,87 @@ fn add_search_toolbar<T: MsgHandler<LogViewMsg> + Clone>(global_actions: Rc<Simp
 
     let search_results_lbl = gtk::Label::new(None);
     toolbar.append(&search_results_lbl);
-    search_results_lbl
+    (search_results_lbl, search_entry)
+}
+
+/// Filter entry, the active filters and the saved filter sets.
+fn filter_toolbar<T: MsgHandler<LogViewMsg> + Clone>(sender: T) -> (gtk::Box, gtk::Box, gtk::Label, ComboBoxText) {
+    let toolbar = gtk::builders::BoxBuilder::new()
+        .margin_start(DEFAULT_MARGIN)
+        .margin_end(DEFAULT_MARGIN)
+        .margin_bottom(DEFAULT_MARGIN)
+        .build();
+
+    let kind_selector = gtk::builders::ComboBoxTextBuilder::new()
+        .margin_end(DEFAULT_MARGIN)
+        .build();
+    kind_selector.append(Some("include"), "Include");
+    kind_selector.append(Some("exclude"), "Exclude");
+    kind_selector.append(Some("field"), "Field");
+    kind_selector.set_active_id(Some("include"));
+    toolbar.append(&kind_selector);
+
+    let filter_entry = gtk::builders::EntryBuilder::new()
+        .placeholder_text("Filter regex")
+        .tooltip_text("Regex, or a field filter like level>=warn or trace_id=abc")
+        .margin_end(DEFAULT_MARGIN)
+        .build();
+    let tx = sender.clone();
+    filter_entry.connect_activate(move |entry| {
+        let pattern = entry.text().to_string();
+        if pattern.len() > 0 {
+            let kind = match kind_selector.active_id().as_deref() {
+                Some("exclude") => FilterKind::Exclude,
+                Some("field") => FilterKind::Field,
+                _ => FilterKind::Include,
+            };
+            tx(LogViewMsg::AddFilter(kind, pattern));
+            entry.set_text("");
+        }
+    });
+    toolbar.append(&filter_entry);
+
+    let filter_chips = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+    filter_chips.set_hexpand(true);
+    toolbar.append(&filter_chips);
+
+    let buffer_stats_lbl = gtk::builders::LabelBuilder::new()
+        .margin_end(DEFAULT_MARGIN)
+        .build();
+    toolbar.append(&buffer_stats_lbl);
+
+    let filter_set_selector = ComboBoxText::with_entry();
+    filter_set_selector.set_margin_end(DEFAULT_MARGIN);
+    filter_set_selector.set_tooltip_text(Some("Filter set"));
+    if let Ok(cfg) = CONFIG.lock() {
+        for filter_set in &cfg.filter_sets {
+            filter_set_selector.append(Some(&filter_set.name), &filter_set.name);
+        }
+    }
+    let tx = sender.clone();
+    filter_set_selector.connect_changed(move |selector| {
+        if let Some(name) = selector.active_id() {
+            tx(LogViewMsg::FilterSetSelected(name.to_string()));
+        }
+    });
+    toolbar.append(&filter_set_selector);
+
+    let save_btn = gtk::builders::ButtonBuilder::new()
+        .label("Save filters")
+        .build();
+    let selector = filter_set_selector.clone();
+    save_btn.connect_clicked(move |_| {
+        if let Some(name) = selector.active_text().map(|text| text.trim().to_string()).filter(|n| !n.is_empty()) {
+            sender(LogViewMsg::SaveFilterSet(name));
+        }
+    });
+    toolbar.append(&save_btn);
+
+    (toolbar, filter_chips, buffer_stats_lbl, filter_set_selector)
 }
 
 const SINCE_5M: u32 = 60*5;
-const SINCE_10M: u32 = 60*10;
+pub const SINCE_10M: u32 = 60*10;
 const SINCE_30M: u32 = 60*30;
 const SINCE_1H: u32 = 60*60;
 const SINCE_2H: u32 = 60*60*2;
@@ -745,6 +1883,27 @@ const SINCE_10H: u32 = 60*60*10;
 const SINCE_12H: u32 = 60*60*12;
 const SINCE_24H: u32 = 60*60*24;
 
+fn min_severity_selection<T: MsgHandler<LogViewMsg>>(tx: T, min_severity: Option<Severity>) -> ComboBoxText {
+    let min_severity_selector = gtk::builders::ComboBoxTextBuilder::new()
+        .tooltip_text("Minimum level, lines without a detected level are hidden")
+        .margin_end(DEFAULT_MARGIN)
+        .build();
+
+    min_severity_selector.append(Some(""), "All levels");
+    for severity in Severity::ALL {
+        min_severity_selector.append(Some(severity.name()), &format!("{}+", severity.name()));
+    }
+    min_severity_selector.set_active_id(Some(min_severity.map(|s| s.name()).unwrap_or("")));
+
+    min_severity_selector.connect_changed(move |selector| {
+        if let Some(active) = selector.active_id() {
+            tx(LogViewMsg::MinSeveritySelected(Severity::from_name(&active)));
+        }
+    });
+
+    min_severity_selector
+}
+
 fn since_duration_selection<T: MsgHandler<LogViewMsg>>(tx: T) -> ComboBoxText {
     let since_selector = gtk::builders::ComboBoxTextBuilder::new()
         .margin_end(DEFAULT_MARGIN)
@@ -779,6 +1938,9 @@ fn add_log_view_settings_menu<T: MsgHandler<LogViewMsg> + Clone>(action_group: R
     menu.append(Some("Show pod names"), Some("app.showPodNames"));
     menu.append(Some("Show container names "), Some("app.showContainerNames"));
     menu.append(Some("Show timestamps"), Some("app.showTimestamps"));
+    menu.append(Some("Show structured fields"), Some("app.showFields"));
+    menu.append(Some("Fold multi-line events"), Some("app.foldEvents"));
+    menu.append(Some("Render ANSI colors"), Some("app.renderAnsi"));
 
     let menu_btn =gtk::builders::MenuButtonBuilder::new()
         .icon_name("emblem-system-symbolic")
@@ -790,6 +1952,9 @@ fn add_log_view_settings_menu<T: MsgHandler<LogViewMsg> + Clone>(action_group: R
     add_property_action(&action_group, "showContainerNames", settings_obj, Settings::show_container_names, || LogViewMsg::ToggleShowContainerNames, sender.clone());
     add_property_action(&action_group, "showTimestamps", settings_obj, Settings::show_timestamps, || LogViewMsg::ToggleShowTimestamps, sender.clone());
     add_property_action(&action_group, "showPodNames", settings_obj, Settings::show_pod_names, || LogViewMsg::ToggleShowPodNames, sender.clone());
+    add_property_action(&action_group, "showFields", settings_obj, Settings::show_fields, || LogViewMsg::ToggleShowFields, sender.clone());
+    add_property_action(&action_group, "foldEvents", settings_obj, Settings::fold_events, || LogViewMsg::ToggleFoldEvents, sender.clone());
+    add_property_action(&action_group, "renderAnsi", settings_obj, Settings::render_ansi, || LogViewMsg::ToggleRenderAnsi, sender.clone());
     toolbar.append(&menu_btn);
 }
 
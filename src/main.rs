@@ -9,34 +9,51 @@ use gtk4_helper::{
     gio
 };
 use gtk4_helper::gtk::Orientation;
-use crate::cluster_list_view::{ClusterListInputData, ClusterListView, ClusterListViewMsg};
+use crate::cli::{Args, Command, Startup};
+use crate::cluster_list_view::{ClusterListInputData, ClusterListView, ClusterListViewMsg, NamespaceViewData};
 use crate::config::{CONFIG};
 use crate::gtk::Inhibit;
+use crate::log_tabs::{LogTabs, LogTabsMsg};
 use crate::log_view::{LogView, LogViewMsg};
 use flexi_logger::{Duplicate, FileSpec, Logger, WriteMode, detailed_format};
 
 mod k8s_client;
+mod cli;
+mod ansi;
 mod log_stream;
+mod log_buffer;
+mod log_fields;
+mod log_export;
+mod log_filter;
+mod log_grouping;
+mod log_line_decoder;
 mod column_view_helper;
 mod pod_list_view;
+mod pod_status;
+mod local_source;
 mod log_view;
+mod log_tabs;
 mod cluster_list_view;
 mod util;
 mod config;
 mod log_text_contrast;
 mod log_overview;
+mod recording;
 mod result;
 mod dirs;
 
-use crate::pod_list_view::{PodListView, PodListViewMsg};
+use crate::log_stream::PreviousLogs;
+use crate::pod_list_view::{PodListView, PodListViewMsg, PodViewData};
 
 pub enum AppMsg {
     PodListViewMsg(PodListViewMsg),
-    LogViewMsg(LogViewMsg),
+    LogTabsMsg(LogTabsMsg),
     ClusterListViewMsg(ClusterListViewMsg),
+    WorkspaceSelected(String),
+    SaveWorkspace(String),
 }
 
-fn build_ui(application: &gtk::Application) {
+fn build_ui(application: &gtk::Application, startup: Option<Startup>) {
     let window = gtk::ApplicationWindow::new(application);
     window.connect_close_request(|_| {
         log::info!("Stopping");
@@ -47,8 +64,6 @@ fn build_ui(application: &gtk::Application) {
     });
     window.set_title(Some("KTail"));
     window.set_default_size(1600, 768);
-    let global_actions = Rc::new(gio::SimpleActionGroup::new());
-    window.insert_action_group("app", Some(&*global_actions));
     let window = Rc::new(window);
 
     let (sender, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
@@ -58,12 +73,12 @@ fn build_ui(application: &gtk::Application) {
     }, window.clone());
 
     let tx = sender.clone();
-    let mut log_view = LogView::new_with_data(move |m| {
-        tx.send(AppMsg::LogViewMsg(m)).expect("Could not send log view msg");
-    }, global_actions.clone());
+    let mut log_tabs = LogTabs::new_with_data(move |m| {
+        tx.send(AppMsg::LogTabsMsg(m)).expect("Could not send log tabs msg");
+    }, window.clone());
 
     let horizontal_split = gtk::Paned::new(Orientation::Horizontal);
-    horizontal_split.set_position(250);
+    horizontal_split.set_position(450);
     horizontal_split.set_vexpand(true);
 
     let tx = sender.clone();
@@ -77,30 +92,89 @@ fn build_ui(application: &gtk::Application) {
     vertical_split.set_end_child(Some(pod_list.view()));
 
     horizontal_split.set_start_child(Some(&vertical_split));
-    horizontal_split.set_end_child(Some(log_view.view()));
+    horizontal_split.set_end_child(Some(log_tabs.view()));
 
+    let workspace_selector = add_header_bar(&window, sender.clone());
+
+    let main_wnd = window.clone();
+    let mut selected_cluster: Option<NamespaceViewData> = None;
     rx.attach(None, move |msg| {
         match msg {
             AppMsg::ClusterListViewMsg(msg) => {
                 if let ClusterListViewMsg::ClusterSelected(sel) = &msg  {
                     pod_list.update(PodListViewMsg::ClusterSelected(sel.clone()));
-                    log_view.update(LogViewMsg::ContextSelected(sel.clone()));
+                    log_tabs.update(LogTabsMsg::Active(LogViewMsg::ContextSelected(sel.clone())));
+                    selected_cluster = Some(sel.clone());
                 }
                 cluster_list.update(msg);
             }
             AppMsg::PodListViewMsg(msg) => {
-                if let PodListViewMsg::PodSelected(sel) = &msg {
-                    log_view.update(LogViewMsg::PodSelected(sel.clone()));
+                match &msg {
+                    PodListViewMsg::PodSelected(sel) => {
+                        log_tabs.update(LogTabsMsg::Active(LogViewMsg::PodSelected(sel.clone())));
+                    }
+                    PodListViewMsg::PreviousLogsSelected(sel, PreviousLogs::Only) => {
+                        if let Some(cluster) = selected_cluster.clone() {
+                            show_previous_logs_window(&main_wnd, cluster, sel.clone());
+                        }
+                    }
+                    PodListViewMsg::PreviousLogsSelected(sel, previous) => {
+                        log_tabs.update(LogTabsMsg::Active(LogViewMsg::PreviousLogsSelected(sel.clone(), *previous)));
+                    }
+                    PodListViewMsg::WorkloadSelected(sel) => {
+                        log_tabs.update(LogTabsMsg::Active(LogViewMsg::WorkloadSelected(sel.clone())));
+                    }
+                    PodListViewMsg::ContainerParserChanged(container, kind) => {
+                        log_tabs.update(LogTabsMsg::Active(LogViewMsg::ParserSelected(container.clone(), *kind)));
+                    }
+                    _ => {}
                 }
                 pod_list.update(msg);
             }
-            AppMsg::LogViewMsg(msg) => {
-                log_view.update(msg);
+            AppMsg::LogTabsMsg(msg) => {
+                // The pod list follows the namespace of the active tab
+                if let LogTabsMsg::ActiveTabChanged(Some(ctx)) = &msg {
+                    let selected = selected_cluster.as_ref()
+                        .map(|sel| sel.config_path == ctx.config_path && sel.context == ctx.context && sel.name == ctx.name)
+                        .unwrap_or(false);
+                    if !selected {
+                        pod_list.update(PodListViewMsg::ClusterSelected(ctx.clone()));
+                        selected_cluster = Some(ctx.clone());
+                    }
+                }
+                log_tabs.update(msg);
+            }
+            AppMsg::WorkspaceSelected(name) => {
+                let workspace = CONFIG.lock().ok().and_then(|cfg| cfg.workspace(&name).cloned());
+                if let Some(workspace) = workspace {
+                    log_tabs.update(LogTabsMsg::OpenWorkspace(workspace));
+                }
+            }
+            AppMsg::SaveWorkspace(name) => {
+                log_tabs.update(LogTabsMsg::Active(LogViewMsg::SaveWorkspace(name.clone())));
+                // Keeps the name in the entry without opening the workspace again
+                update_workspaces(&workspace_selector);
+                if let Some(entry) = workspace_selector.child().and_then(|c| c.downcast::<gtk::Entry>().ok()) {
+                    entry.set_text(&name);
+                }
             }
         }
         glib::Continue(true)
     });
 
+    match startup {
+        Some(Startup::Tail(target)) => {
+            let _ = sender.send(AppMsg::LogTabsMsg(LogTabsMsg::OpenTail(target)));
+        }
+        Some(Startup::Local(sources, follow)) => {
+            let _ = sender.send(AppMsg::LogTabsMsg(LogTabsMsg::OpenLocal(sources, follow)));
+        }
+        Some(Startup::Workspace(workspace)) => {
+            let _ = sender.send(AppMsg::LogTabsMsg(LogTabsMsg::OpenWorkspace(workspace)));
+        }
+        None => {}
+    }
+
     application.set_accels_for_action("app.search", &["<Ctrl>F"]);
     application.set_accels_for_action("app.scroll", &["<Ctrl>Q"]);
     application.set_accels_for_action("app.prevMatch", &["<Ctrl>P"]);
@@ -109,11 +183,109 @@ fn build_ui(application: &gtk::Application) {
     application.set_accels_for_action("app.showPodNames", &["<Alt>P"]);
     application.set_accels_for_action("app.showContainerNames", &["<Alt>C"]);
     application.set_accels_for_action("app.showTimestamps", &["<Alt>T"]);
+    application.set_accels_for_action("app.showFields", &["<Alt>F"]);
+    application.set_accels_for_action("app.foldEvents", &["<Alt>E"]);
+    application.set_accels_for_action("app.renderAnsi", &["<Alt>A"]);
     window.set_child(Some(&horizontal_split));
     window.show();
 }
 
+/// Adds the header bar with the workspace switcher and returns the switcher.
+fn add_header_bar(window: &gtk::ApplicationWindow, sender: glib::Sender<AppMsg>) -> gtk::ComboBoxText {
+    let header_bar = gtk::HeaderBar::new();
+
+    let workspace_selector = gtk::ComboBoxText::with_entry();
+    workspace_selector.set_tooltip_text(Some("Workspace"));
+    update_workspaces(&workspace_selector);
+    let tx = sender.clone();
+    workspace_selector.connect_changed(move |selector| {
+        if let Some(name) = selector.active_id() {
+            tx.send(AppMsg::WorkspaceSelected(name.to_string())).expect("Could not send workspace msg");
+        }
+    });
+    header_bar.pack_start(&workspace_selector);
+
+    let save_btn = gtk::builders::ButtonBuilder::new()
+        .label("Save workspace")
+        .build();
+    let selector = workspace_selector.clone();
+    save_btn.connect_clicked(move |_| {
+        if let Some(name) = selector.active_text().map(|text| text.trim().to_string()).filter(|n| !n.is_empty()) {
+            sender.send(AppMsg::SaveWorkspace(name)).expect("Could not send workspace msg");
+        }
+    });
+    header_bar.pack_start(&save_btn);
+
+    window.set_titlebar(Some(&header_bar));
+    workspace_selector
+}
+
+fn update_workspaces(selector: &gtk::ComboBoxText) {
+    selector.remove_all();
+    if let Ok(cfg) = CONFIG.lock() {
+        for workspace in &cfg.workspaces {
+            selector.append(Some(&workspace.name), &workspace.name);
+        }
+    }
+}
+
+/// Shows the logs of the previous container instances next to the current ones.
+fn show_previous_logs_window(main_wnd: &gtk::ApplicationWindow, cluster: NamespaceViewData, pods: Vec<PodViewData>) {
+    let window = gtk::ApplicationWindow::builder()
+        .title("KTail - Previous logs")
+        .default_width(1200)
+        .default_height(768)
+        .build();
+    window.set_application(main_wnd.application().as_ref());
+    let actions = Rc::new(gio::SimpleActionGroup::new());
+    window.insert_action_group("app", Some(&*actions));
+
+    // None stops the log view when the window gets closed
+    let (sender, rx) = glib::MainContext::channel::<Option<LogViewMsg>>(glib::PRIORITY_DEFAULT);
+    let tx = sender.clone();
+    let mut log_view = LogView::new_with_data(move |m| {
+        // Messages of running streams may arrive after the window was closed
+        let _ = tx.send(Some(m));
+    }, actions);
+    window.set_child(Some(log_view.view()));
+    window.connect_close_request(move |_| {
+        let _ = sender.send(None);
+        Inhibit(false)
+    });
+
+    log_view.update(LogViewMsg::ContextSelected(cluster));
+    log_view.update(LogViewMsg::PreviousLogsSelected(pods, PreviousLogs::Only));
+    rx.attach(None, move |msg| {
+        match msg {
+            Some(msg) => {
+                log_view.update(msg);
+                glib::Continue(true)
+            }
+            None => glib::Continue(false),
+        }
+    });
+    window.show();
+}
+
+/// The GUI subsystem starts without a console, `ktail tail` writes to the one of the calling shell.
+#[cfg(windows)]
+fn attach_parent_console() {
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+    extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+    }
+    // Fails if the parent has no console, e.g. when started from the explorer, the output is dropped then
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
 fn main() {
+    let args: Args = argh::from_env();
+    #[cfg(windows)]
+    if let Some(Command::Tail(_)) = args.command {
+        attach_parent_console();
+    }
     let log_level = env::var("LOG_LEVEL").unwrap_or("info".to_string());
     if let Err(e) = Logger::try_with_str(&log_level)
         .and_then(|l| l
@@ -136,14 +308,31 @@ fn main() {
         .build()
         .unwrap();
 
+    if let Some(Command::Tail(cmd)) = args.command {
+        if let Err(e) = rt.block_on(cli::run_tail(cmd)) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let startup = match args.startup() {
+        Ok(startup) => startup,
+        Err(e) => {
+            log::error!("Invalid arguments: {}", e);
+            std::process::exit(1);
+        }
+    };
+
     rt.block_on(async move {
         let application =
             gtk::Application::new(Some("de.ktail"), Default::default());
 
-        application.connect_activate(|app| {
-            build_ui(app);
+        application.connect_activate(move |app| {
+            build_ui(app, startup.clone());
         });
 
-        application.run();
+        // The arguments are ours, not gtk's
+        application.run_with_args::<&str>(&[]);
     });
 }
\ No newline at end of file
@@ -1,4 +1,5 @@
 use std::rc::Rc;
+use std::sync::Arc;
 use gtk4_helper::{
     prelude::*,
     gtk,
@@ -12,34 +13,137 @@ pub struct PodViewData {
     pub name: String,
     #[field]
     container_names: String,
+    #[field]
+    init_container_names: String,
+    #[field]
+    ephemeral_container_names: String,
+    // Containers the user does not want to tail
+    #[field]
+    excluded_container_names: String,
+    #[field]
+    pub status: String,
+    #[field]
+    pub ready: String,
+    #[field]
+    pub restarts: String,
+    #[field]
+    pub age: String,
+    // RFC 3339, used to sort by age
+    #[field]
+    pub created: String,
+    #[field]
+    pub node: String,
+    #[field]
+    pub owner: String,
+    #[field]
+    pub css_class: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ContainerKind {
+    Init,
+    Regular,
+    Ephemeral,
+}
+
+impl ContainerKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ContainerKind::Init => "init",
+            ContainerKind::Regular => "",
+            ContainerKind::Ephemeral => "ephemeral",
+        }
+    }
 }
 
 impl PodViewData {
-    pub fn containers(&self) -> Vec<String> {
-        self.container_names.split(";").into_iter().map(|s|s.to_string()).collect()
+    /// The containers which are not excluded.
+    pub fn containers(&self) -> Vec<(String, ContainerKind)> {
+        self.all_containers().into_iter()
+            .filter(|(name, _)| !self.is_excluded(name))
+            .collect()
+    }
+
+    pub fn all_containers(&self) -> Vec<(String, ContainerKind)> {
+        split_names(&self.init_container_names).into_iter().map(|name| (name, ContainerKind::Init))
+            .chain(split_names(&self.container_names).into_iter().map(|name| (name, ContainerKind::Regular)))
+            .chain(split_names(&self.ephemeral_container_names).into_iter().map(|name| (name, ContainerKind::Ephemeral)))
+            .collect()
+    }
+
+    pub fn is_excluded(&self, container: &str) -> bool {
+        split_names(&self.excluded_container_names).iter().any(|name| name == container)
+    }
+
+    /// Excludes the containers of the pod which are in the list.
+    pub fn exclude_containers(&mut self, excluded: &[String]) {
+        self.excluded_container_names = self.all_containers().into_iter()
+            .map(|(name, _)| name)
+            .filter(|name| excluded.contains(name))
+            .collect::<Vec<String>>()
+            .join(";");
     }
 }
 
+//TODO: Currently gtk helper model does not support Vec<String>
+fn split_names(names: &str) -> Vec<String> {
+    names.split(";").filter(|s| !s.is_empty()).map(|s| s.to_string()).collect()
+}
+
 use gtk4_helper::{
     gio,
     model::prelude::*,
 };
 
+use gtk4_helper::gtk::ComboBoxText;
 use crate::column_view_helper;
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use stream_cancel::{StreamExt as StreamCancelStreamExt, Trigger, Tripwire};
+use crate::k8s_client::{label_selector_query, ListWatchEvent, Pod, WorkloadKind};
 use crate::cluster_list_view::NamespaceViewData;
-use crate::result::AppResult;
+use crate::config::CONFIG;
+use crate::result::AppError;
+use crate::log_stream::{ParserKind, PreviousLogs};
+use crate::log_view::DEFAULT_MARGIN;
+use crate::pod_status::{format_age, PodSummary};
 use crate::util::{show_and_log_error, WidgetLoadingWrapper};
 
+#[derive(Clone, Debug)]
+pub struct WorkloadViewData {
+    pub kind: WorkloadKind,
+    pub name: String,
+    pub label_selector: String,
+}
+
 #[derive(Clone, Debug)]
 pub enum PodListViewMsg {
-    Loaded(AppResult<Vec<PodViewData>>),
+    Watching(u64, Arc<Trigger>),
+    PodsChanged(u64, ListWatchEvent<PodViewData>),
+    WatchFailed(u64, AppError),
+    WorkloadsLoaded(Vec<WorkloadViewData>),
     PodSelected(Vec<PodViewData>),
+    PodSelectionChanged,
+    ContainerExclusionChanged(String, bool),
+    ContainerParserChanged(String, ParserKind),
+    PreviousLogsSelected(Vec<PodViewData>, PreviousLogs),
+    WorkloadSelected(String),
     ClusterSelected(NamespaceViewData)
 }
 
 pub struct PodListView {
+    container: gtk::Box,
+    column_view: gtk::ColumnView,
+    container_list: gtk::ListBox,
     pod_list_data: gio::ListStore,
     pod_list_view: WidgetLoadingWrapper<gtk::ScrolledWindow>,
+    workload_selector: ComboBoxText,
+    selected_cluster: Option<NamespaceViewData>,
+    // Changes of previous watches are ignored
+    watch_generation: u64,
+    watch_trigger: Option<Arc<Trigger>>,
+    is_loading: bool,
+    sender: Arc<dyn MsgHandler<PodListViewMsg>>,
     app_wnd: Rc<ApplicationWindow>,
 }
 
@@ -54,78 +158,425 @@ impl Component for PodListView {
 
         let tx = sender.clone();
         column_view.connect_activate(move |view, _| {
-            if let Some(model) = view.model() {
-                let sel = model.selection();
-                let mut selected_pods = vec![];
-                for idx in 0..sel.size() {
-                    let sel = sel.nth(idx as u32);
-                    if let Some(sel_item) = model.item(sel) {
-                        let sel_pod: PodViewData = PodViewData::from_object(&sel_item);
-                        selected_pods.push(sel_pod);
-                    }
-                }
-                tx(PodListViewMsg::PodSelected(selected_pods))
-            }
+            tx(PodListViewMsg::PodSelected(selected_pods(view)))
         });
 
-        column_view_helper::create_label_column(&column_view, PodViewData::static_type(), PodViewData::name, "Pod", column_view_helper::string_sorter);
+        if let Some(model) = column_view.model() {
+            let tx = sender.clone();
+            model.connect_selection_changed(move |_, _, _| {
+                tx(PodListViewMsg::PodSelectionChanged)
+            });
+        }
+
+        let ty = PodViewData::static_type();
+        column_view_helper::create_column(&column_view, ty, PodViewData::name, "Pod", column_view_helper::string_sorter, create_pod_label);
+        column_view_helper::create_column(&column_view, ty, PodViewData::status, "Status", column_view_helper::string_sorter, create_pod_label);
+        column_view_helper::create_label_column(&column_view, ty, PodViewData::ready, "Ready", column_view_helper::string_sorter);
+        column_view_helper::create_label_column(&column_view, ty, PodViewData::restarts, "Restarts", |_| column_view_helper::custom_sorter(|obj| {
+            PodViewData::from_object(obj).restarts.parse::<u32>().unwrap_or(0)
+        }));
+        column_view_helper::create_label_column(&column_view, ty, PodViewData::age, "Age", |_| column_view_helper::custom_sorter(|obj| {
+            std::cmp::Reverse(PodViewData::from_object(obj).created)
+        }));
+        column_view_helper::create_label_column(&column_view, ty, PodViewData::node, "Node", column_view_helper::string_sorter);
+        column_view_helper::create_label_column(&column_view, ty, PodViewData::owner, "Owner", column_view_helper::string_sorter);
 
         let pod_list_view = WidgetLoadingWrapper::new(gtk::builders::ScrolledWindowBuilder::new()
             .vexpand(true)
             .child(&column_view)
             .build());
 
+        let container_list = gtk::ListBox::new();
+        container_list.set_selection_mode(gtk::SelectionMode::None);
+        let container_list_wnd = gtk::builders::ScrolledWindowBuilder::new()
+            .child(&container_list)
+            .build();
+
+        let pane = gtk::builders::PanedBuilder::new()
+            .orientation(gtk::Orientation::Vertical)
+            .position(300)
+            .vexpand(true)
+            .start_child(pod_list_view.container())
+            .end_child(&container_list_wnd)
+            .build();
+
+        let (workload_toolbar, workload_selector) = workload_toolbar(sender.clone());
+        workload_toolbar.append(&previous_logs_menu());
+        let container = gtk::builders::BoxBuilder::new()
+            .orientation(gtk::Orientation::Vertical)
+            .build();
+        container.append(&workload_toolbar);
+        container.insert_action_group("pods", Some(&previous_logs_actions(&column_view, sender.clone())));
+        container.append(&pane);
+
         let app_wnd = input.expect("Input is required!");
         Self {
+            container,
+            column_view,
+            container_list,
             pod_list_view,
             pod_list_data: list_store,
+            workload_selector,
+            selected_cluster: None,
+            watch_generation: 0,
+            watch_trigger: None,
+            is_loading: false,
+            sender: Arc::new(sender.clone()),
             app_wnd
         }
     }
 
     fn update(&mut self, msg: Self::Msg) -> Command<Self::Msg> {
         match msg {
-            PodListViewMsg::Loaded(res) => {
-                self.pod_list_view.set_is_loading(false);
-                match res {
-                    Ok(pvd) => {
-                        for pod_data in pvd {
-                            let obj = pod_data.to_object();
-                            self.pod_list_data.append(&obj);
-                        }
-                    }
-                    Err(e) => {
-                        show_and_log_error("Failed to load pods", &e.to_string(), Some(&*self.app_wnd.clone()));
+            PodListViewMsg::Watching(generation, trigger) => {
+                if generation == self.watch_generation {
+                    self.watch_trigger = Some(trigger);
+                }
+            }
+            PodListViewMsg::PodsChanged(generation, change) => {
+                if generation != self.watch_generation {
+                    return Command::None;
+                }
+                // New pods get the configured exclusions, the user's choice is kept for known pods
+                let excluded = self.configured_exclusions();
+                let change = change.map(|mut pod| {
+                    pod.exclude_containers(&excluded);
+                    pod
+                });
+                column_view_helper::apply_list_change(&self.pod_list_data, change, |pod| pod.to_object(), |obj| PodViewData::from_object(obj).name, &[PodViewData::excluded_container_names]);
+                if self.is_loading {
+                    self.is_loading = false;
+                    self.pod_list_view.set_is_loading(false);
+                    if let Some(cluster) = self.selected_cluster.clone() {
+                        return self.run_async(load_workloads(cluster));
                     }
                 }
             }
+            PodListViewMsg::WatchFailed(generation, e) => {
+                if generation != self.watch_generation {
+                    return Command::None;
+                }
+                if self.is_loading {
+                    self.is_loading = false;
+                    self.pod_list_view.set_is_loading(false);
+                    show_and_log_error("Failed to load pods", &e.to_string(), Some(&*self.app_wnd.clone()));
+                } else {
+                    log::warn!("Pod watch failed: {}", e);
+                }
+            }
+            PodListViewMsg::WorkloadsLoaded(workloads) => {
+                for workload in workloads {
+                    let text = format!("{}/{}", workload.kind.name(), workload.name);
+                    self.workload_selector.append(Some(&workload.label_selector), &text);
+                }
+            }
             PodListViewMsg::ClusterSelected(cluster) => {
                 self.pod_list_view.set_is_loading(true);
                 self.pod_list_data.remove_all();
-                return self.run_async(load_data(cluster));
+                self.workload_selector.remove_all();
+                self.update_container_list();
+                self.selected_cluster = Some(cluster.clone());
+                self.watch_trigger.take();
+                self.watch_generation += 1;
+                self.is_loading = true;
+                return self.run_async(watch_pods(cluster, self.watch_generation, self.sender.clone()));
             }
             PodListViewMsg::PodSelected(_) => {}
+            PodListViewMsg::PodSelectionChanged => {
+                self.update_container_list();
+            }
+            PodListViewMsg::ContainerParserChanged(container, kind) => {
+                if let Ok(mut cfg) = CONFIG.lock() {
+                    cfg.set_container_parser(&container, kind);
+                }
+            }
+            PodListViewMsg::ContainerExclusionChanged(container, excluded) => {
+                if let Some(cluster) = &self.selected_cluster {
+                    if let Ok(mut cfg) = CONFIG.lock() {
+                        cfg.set_container_excluded(&cluster.context, &cluster.name, &container, excluded);
+                    }
+                }
+                for pos in 0..self.pod_list_data.n_items() {
+                    if let Some(obj) = self.pod_list_data.item(pos) {
+                        set_container_excluded(&obj, &container, excluded);
+                    }
+                }
+                self.update_container_list();
+            }
+            PodListViewMsg::PreviousLogsSelected(_, _) => {}
+            PodListViewMsg::WorkloadSelected(_) => {}
         }
         Command::None
     }
 
     fn view(&self) -> &Self::View {
-        self.pod_list_view.container()
+        &self.container
     }
 }
 
-async fn load_data(cluster: NamespaceViewData) -> PodListViewMsg {
-    let client = crate::log_stream::k8s_client_with_timeout(&cluster.config_path, &cluster.context);
-    let res = client.pods(&cluster.name).await.and_then(|pods| {
-        Ok(pods.into_iter().map(|p| {
-            //TODO: Currently gtk helper model does not support Vec<String>
-            let container_names = p.spec.containers.iter().map(|c| c.name.as_str()).collect::<Vec<&str>>().join(";");
-            let pod_name = p.metadata.name.unwrap_or("failed".to_string());
-            PodViewData {
-                container_names,
-                name: pod_name,
+impl PodListView {
+    fn configured_exclusions(&self) -> Vec<String> {
+        match (&self.selected_cluster, CONFIG.lock()) {
+            (Some(cluster), Ok(cfg)) => cfg.excluded_containers(&cluster.context, &cluster.name),
+            _ => vec![],
+        }
+    }
+
+    /// Lists the containers of the selected pods so that single ones can be excluded.
+    fn update_container_list(&self) {
+        while let Some(row) = self.container_list.first_child() {
+            self.container_list.remove(&row);
+        }
+
+        let always_excluded = self.configured_exclusions();
+        let parsers = CONFIG.lock().map(|cfg| cfg.container_parsers()).unwrap_or_default();
+        for obj in selected_objects(&self.column_view) {
+            let pod = PodViewData::from_object(&obj);
+            for (container, kind) in pod.all_containers() {
+                let label = match kind {
+                    ContainerKind::Regular => format!("{} / {}", pod.name, container),
+                    kind => format!("{} / {} ({})", pod.name, container, kind.label()),
+                };
+                let check_btn = gtk::builders::CheckButtonBuilder::new()
+                    .label(&label)
+                    .active(!pod.is_excluded(&container))
+                    .hexpand(true)
+                    .build();
+                let name = container.clone();
+                let pod_obj = obj.clone();
+                check_btn.connect_toggled(move |btn| {
+                    set_container_excluded(&pod_obj, &name, !btn.is_active());
+                });
+
+                let exclude_btn = gtk::builders::ToggleButtonBuilder::new()
+                    .icon_name("action-unavailable-symbolic")
+                    .tooltip_text("Always exclude containers with this name in the namespace")
+                    .active(always_excluded.contains(&container))
+                    .build();
+                let parser_selector = gtk::builders::ComboBoxTextBuilder::new()
+                    .tooltip_text("Log format of containers with this name")
+                    .margin_end(DEFAULT_MARGIN)
+                    .build();
+                for kind in ParserKind::ALL {
+                    parser_selector.append(Some(kind.id()), kind.label());
+                }
+                parser_selector.set_active_id(Some(parsers.get(&container).unwrap_or(&ParserKind::Auto).id()));
+                let tx = self.sender.clone();
+                let name = container.clone();
+                parser_selector.connect_changed(move |selector| {
+                    if let Some(kind) = selector.active_id().and_then(|id| ParserKind::from_id(&id)) {
+                        tx(PodListViewMsg::ContainerParserChanged(name.clone(), kind));
+                    }
+                });
+
+                let tx = self.sender.clone();
+                exclude_btn.connect_toggled(move |btn| {
+                    tx(PodListViewMsg::ContainerExclusionChanged(container.clone(), btn.is_active()));
+                });
+
+                let row = gtk::builders::BoxBuilder::new()
+                    .margin_start(DEFAULT_MARGIN)
+                    .margin_end(DEFAULT_MARGIN)
+                    .build();
+                row.append(&check_btn);
+                row.append(&parser_selector);
+                row.append(&exclude_btn);
+                self.container_list.append(&row);
+            }
+        }
+    }
+}
+
+fn set_container_excluded(obj: &glib::Object, container: &str, excluded: bool) {
+    let pod = PodViewData::from_object(obj);
+    if !pod.all_containers().iter().any(|(name, _)| name == container) {
+        return;
+    }
+    let mut names = split_names(&pod.excluded_container_names);
+    names.retain(|name| name != container);
+    if excluded {
+        names.push(container.to_string());
+    }
+    obj.set_property(PodViewData::excluded_container_names, names.join(";"));
+}
+
+/// Keeps the pod list up to date until the returned trigger is dropped.
+/// The watch stops if the pods could not be loaded at all.
+async fn watch_pods(cluster: NamespaceViewData, generation: u64, tx: Arc<dyn MsgHandler<PodListViewMsg>>) -> PodListViewMsg {
+    let client = match crate::log_stream::k8s_client(&cluster.config_path, &cluster.context) {
+        Ok(client) => client,
+        Err(e) => return PodListViewMsg::WatchFailed(generation, e),
+    };
+
+    let (trigger, tripwire) = Tripwire::new();
+    let mut events = client.watch_pods(&cluster.name, None).take_until_if(tripwire);
+    tokio::task::spawn(async move {
+        let mut loaded = false;
+        while let Some(event) = events.next().await {
+            match event {
+                Ok(change) => {
+                    loaded = true;
+                    let now = Utc::now();
+                    tx(PodListViewMsg::PodsChanged(generation, change.map(|pod| pod_view_data(pod, now))));
+                }
+                Err(e) => {
+                    tx(PodListViewMsg::WatchFailed(generation, AppError::for_context(&cluster.context, e)));
+                    if !loaded {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+    PodListViewMsg::Watching(generation, Arc::new(trigger))
+}
+
+pub fn pod_view_data(pod: Pod, now: DateTime<Utc>) -> PodViewData {
+    let summary = PodSummary::new(&pod);
+    let container_names = pod.spec.containers.iter().map(|c| c.name.as_str()).collect::<Vec<&str>>().join(";");
+    let init_container_names = pod.spec.init_containers.iter().flatten().map(|c| c.name.as_str()).collect::<Vec<&str>>().join(";");
+    let pod_name = pod.metadata.name.unwrap_or("failed".to_string());
+    PodViewData {
+        container_names,
+        init_container_names,
+        ephemeral_container_names: pod.ephemeral_containers.join(";"),
+        excluded_container_names: String::new(),
+        name: pod_name,
+        status: summary.status,
+        ready: summary.ready,
+        restarts: summary.restarts.to_string(),
+        age: summary.created.map(|created| format_age(now - created)).unwrap_or_default(),
+        created: summary.created.map(|created| created.to_rfc3339()).unwrap_or_default(),
+        node: summary.node,
+        owner: summary.owner,
+        css_class: summary.health.css_class().to_string(),
+    }
+}
+
+/// Colors crash looping or pending pods.
+fn create_pod_label(item: &gtk::ListItem, property: &str) {
+    column_view_helper::create_item_label(item, property);
+    if let (Some(obj), Some(lbl)) = (item.item(), item.child()) {
+        // Bound so that the color changes along with the status of the pod
+        obj.bind_property(PodViewData::css_class, &lbl, "css-classes")
+            .transform_to(|_, value| {
+                let css_class = value.get::<String>().ok()?;
+                let css_classes: Vec<String> = if css_class.is_empty() { vec![] } else { vec![css_class] };
+                Some(css_classes.to_value())
+            })
+            .flags(glib::BindingFlags::SYNC_CREATE)
+            .build();
+    }
+}
+
+fn selected_objects(view: &gtk::ColumnView) -> Vec<glib::Object> {
+    let mut selected = vec![];
+    if let Some(model) = view.model() {
+        let sel = model.selection();
+        for idx in 0..sel.size() {
+            let sel = sel.nth(idx as u32);
+            if let Some(sel_item) = model.item(sel) {
+                selected.push(sel_item);
             }
-        }).collect())
-    }).map_err(|e| e.into());
-    PodListViewMsg::Loaded(res)
-}
\ No newline at end of file
+        }
+    }
+    selected
+}
+
+fn selected_pods(view: &gtk::ColumnView) -> Vec<PodViewData> {
+    selected_objects(view).iter().map(|obj| PodViewData::from_object(obj)).collect()
+}
+
+fn previous_logs_actions<T: MsgHandler<PodListViewMsg> + Clone>(column_view: &gtk::ColumnView, sender: T) -> gio::SimpleActionGroup {
+    let actions = gio::SimpleActionGroup::new();
+    for (name, previous) in [("previousMerged", PreviousLogs::Include), ("previousWindow", PreviousLogs::Only)] {
+        let action = gio::SimpleAction::new(name, None);
+        let view = column_view.clone();
+        let tx = sender.clone();
+        action.connect_activate(move |_, _| {
+            let pods = selected_pods(&view);
+            if !pods.is_empty() {
+                tx(PodListViewMsg::PreviousLogsSelected(pods, previous));
+            }
+        });
+        actions.add_action(&action);
+    }
+    actions
+}
+
+/// Logs of the previous container instances, e.g. of crashed containers.
+fn previous_logs_menu() -> gtk::MenuButton {
+    let menu = gio::Menu::new();
+    menu.append(Some("Previous logs merged with current logs"), Some("pods.previousMerged"));
+    menu.append(Some("Previous logs in new window"), Some("pods.previousWindow"));
+
+    gtk::builders::MenuButtonBuilder::new()
+        .icon_name("document-open-recent-symbolic")
+        .tooltip_text("Logs of the previous container instances of the selected pods")
+        .menu_model(&menu)
+        .build()
+}
+
+/// Lets the user pick a workload or enter a label selector to follow all of its pods.
+fn workload_toolbar<T: MsgHandler<PodListViewMsg> + Clone>(tx: T) -> (gtk::Box, ComboBoxText) {
+    let toolbar = gtk::builders::BoxBuilder::new()
+        .margin_start(DEFAULT_MARGIN)
+        .margin_end(DEFAULT_MARGIN)
+        .margin_top(DEFAULT_MARGIN)
+        .margin_bottom(DEFAULT_MARGIN)
+        .build();
+
+    let workload_selector = ComboBoxText::with_entry();
+    workload_selector.set_hexpand(true);
+    workload_selector.set_margin_end(DEFAULT_MARGIN);
+    workload_selector.set_tooltip_text(Some("Workload or label selector, e.g. app=nginx"));
+    toolbar.append(&workload_selector);
+
+    let follow_btn = gtk::builders::ButtonBuilder::new()
+        .label("Follow")
+        .margin_end(DEFAULT_MARGIN)
+        .build();
+    let selector = workload_selector.clone();
+    follow_btn.connect_clicked(move |_| {
+        // Entered text is used as label selector
+        let label_selector = selector.active_id()
+            .map(|id| id.to_string())
+            .or(selector.active_text().map(|text| text.trim().to_string()));
+        if let Some(label_selector) = label_selector.filter(|s| !s.is_empty()) {
+            tx(PodListViewMsg::WorkloadSelected(label_selector));
+        }
+    });
+    toolbar.append(&follow_btn);
+
+    (toolbar, workload_selector)
+}
+
+async fn load_workloads(cluster: NamespaceViewData) -> PodListViewMsg {
+    let client = match crate::log_stream::k8s_client_with_timeout(&cluster.config_path, &cluster.context) {
+        Ok(client) => client,
+        Err(e) => {
+            log::warn!("Could not load workloads: {}", e);
+            return PodListViewMsg::WorkloadsLoaded(vec![]);
+        }
+    };
+
+    let mut workloads = vec![];
+    for kind in WorkloadKind::ALL {
+        match client.workloads(&cluster.name, kind).await {
+            Ok(items) => {
+                for item in items {
+                    let label_selector = item.spec.as_ref()
+                        .and_then(|spec| spec.selector.as_ref())
+                        .map(label_selector_query)
+                        .filter(|s| !s.is_empty());
+                    if let (Some(name), Some(label_selector)) = (item.metadata.name, label_selector) {
+                        workloads.push(WorkloadViewData { kind, name, label_selector });
+                    }
+                }
+            }
+            // Missing permissions for a single kind should not hide the others
+            Err(e) => log::warn!("Could not load {} workloads: {}", kind.name(), e),
+        }
+    }
+    PodListViewMsg::WorkloadsLoaded(workloads)
+}
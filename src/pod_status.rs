@@ -0,0 +1,146 @@
+use chrono::{DateTime, Duration, Utc};
+use crate::k8s_client::Pod;
+
+/// How urgent a look at the pod is, used to color it in the pod list.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PodHealth {
+    Ok,
+    Warning,
+    Error,
+}
+
+impl PodHealth {
+    pub fn css_class(&self) -> &'static str {
+        match self {
+            PodHealth::Ok => "",
+            PodHealth::Warning => "warning",
+            PodHealth::Error => "error",
+        }
+    }
+}
+
+/// The pod state as summarized by `kubectl get pods`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PodSummary {
+    pub status: String,
+    pub ready: String,
+    pub restarts: u32,
+    pub created: Option<DateTime<Utc>>,
+    pub node: String,
+    pub owner: String,
+    pub health: PodHealth,
+}
+
+const ERROR_REASONS: [&str; 6] = ["CrashLoopBackOff", "Error", "ImagePullBackOff", "ErrImagePull", "OOMKilled", "CreateContainerConfigError"];
+
+impl PodSummary {
+    pub fn new(pod: &Pod) -> Self {
+        let status = pod.status.as_ref();
+        let container_statuses = status.and_then(|s| s.container_statuses.as_ref());
+        let phase = status.and_then(|s| s.phase.clone()).unwrap_or("Unknown".to_string());
+
+        let mut ready = 0;
+        let mut restarts = 0;
+        // Waiting or terminated containers explain better than the phase what is wrong
+        let mut reason = None;
+        for container in container_statuses.into_iter().flatten() {
+            restarts += container.restart_count.max(0) as u32;
+            if container.ready {
+                ready += 1;
+            }
+            let state = container.state.as_ref();
+            let container_reason = state.and_then(|s| s.waiting.as_ref()).and_then(|w| w.reason.clone())
+                .or(state.and_then(|s| s.terminated.as_ref()).and_then(|t| t.reason.clone()));
+            if reason.is_none() {
+                reason = container_reason;
+            }
+        }
+
+        let status = if pod.metadata.deletion_timestamp.is_some() {
+            "Terminating".to_string()
+        } else {
+            reason.or(status.and_then(|s| s.reason.clone())).unwrap_or(phase.clone())
+        };
+
+        let health = if ERROR_REASONS.contains(&status.as_str()) || phase == "Failed" {
+            PodHealth::Error
+        } else if phase == "Pending" || phase == "Unknown" {
+            PodHealth::Warning
+        } else {
+            PodHealth::Ok
+        };
+
+        let owner = pod.metadata.owner_references.iter().flatten()
+            .find(|o| o.controller.unwrap_or(false))
+            .or(pod.metadata.owner_references.iter().flatten().next())
+            .map(|o| format!("{}/{}", o.kind, o.name))
+            .unwrap_or_default();
+
+        Self {
+            status,
+            ready: format!("{}/{}", ready, pod.spec.containers.len()),
+            restarts,
+            created: pod.metadata.creation_timestamp.as_ref().map(|ts| ts.0),
+            node: pod.spec.node_name.clone().unwrap_or_default(),
+            owner,
+            health,
+        }
+    }
+}
+
+/// Formats the age like kubectl, e.g. 45s, 12m, 5h or 3d.
+pub fn format_age(age: Duration) -> String {
+    if age < Duration::minutes(2) {
+        format!("{}s", age.num_seconds().max(0))
+    } else if age < Duration::hours(2) {
+        format!("{}m", age.num_minutes())
+    } else if age < Duration::hours(48) {
+        format!("{}h", age.num_hours())
+    } else {
+        format!("{}d", age.num_days())
+    }
+}
+
+#[test]
+pub fn test_pod_summary() -> anyhow::Result<()> {
+    let pod: Pod = serde_json::from_str(r#"{
+        "metadata": {
+            "name": "api-7c9f",
+            "creationTimestamp": "2022-01-02T10:00:00Z",
+            "ownerReferences": [{"apiVersion": "apps/v1", "kind": "ReplicaSet", "name": "api-5d8", "uid": "1", "controller": true}]
+        },
+        "spec": {"nodeName": "node-1", "containers": [{"name": "api"}, {"name": "proxy"}]},
+        "status": {
+            "phase": "Running",
+            "containerStatuses": [
+                {"name": "api", "ready": false, "restartCount": 4, "image": "api", "imageID": "",
+                 "state": {"waiting": {"reason": "CrashLoopBackOff"}}},
+                {"name": "proxy", "ready": true, "restartCount": 1, "image": "proxy", "imageID": "",
+                 "state": {"running": {}}}
+            ]
+        }
+    }"#)?;
+
+    let summary = PodSummary::new(&pod);
+    assert_eq!(summary.status, "CrashLoopBackOff");
+    assert_eq!(summary.ready, "1/2");
+    assert_eq!(summary.restarts, 5);
+    assert_eq!(summary.node, "node-1");
+    assert_eq!(summary.owner, "ReplicaSet/api-5d8");
+    assert_eq!(summary.health, PodHealth::Error);
+    assert_eq!(summary.created, Some(DateTime::parse_from_rfc3339("2022-01-02T10:00:00Z")?.with_timezone(&Utc)));
+
+    let pending: Pod = serde_json::from_str(r#"{"metadata": {}, "spec": {"containers": []}, "status": {"phase": "Pending"}}"#)?;
+    let summary = PodSummary::new(&pending);
+    assert_eq!(summary.status, "Pending");
+    assert_eq!(summary.health, PodHealth::Warning);
+    Ok(())
+}
+
+#[test]
+pub fn test_format_age() {
+    assert_eq!(format_age(Duration::seconds(45)), "45s");
+    assert_eq!(format_age(Duration::minutes(12)), "12m");
+    assert_eq!(format_age(Duration::hours(5)), "5h");
+    assert_eq!(format_age(Duration::days(3)), "3d");
+}
@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use anyhow::{anyhow, bail};
+use chrono::{Local, TimeZone, Utc};
+use futures::Stream;
+use stream_cancel::{StreamExt as StreamCancelStreamExt, Trigger, Tripwire};
+use tokio_stream::wrappers::ReceiverStream;
+use crate::log_stream::{LogData, LogStreamEvent};
+use crate::tokio;
+
+const MAGIC: &[u8; 8] = b"KTAILREC";
+const VERSION: u8 = 1;
+pub const EXTENSION: &str = "ktrec";
+
+/// Defines the pod and container of the following lines
+const SOURCE_RECORD: u8 = 0;
+const LINE_RECORD: u8 = 1;
+
+/// Recorder shared with the stream of a log view, None while not recording.
+pub type SharedRecorder = Arc<Mutex<Option<SessionRecorder<BufWriter<File>>>>>;
+
+/// New recording file named after the session.
+pub fn recording_path(name: &str) -> PathBuf {
+    let name: String = name.chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    crate::dirs::recordings_dir().join(format!("{}-{}.{}", name, Local::now().format("%Y%m%d-%H%M%S"), EXTENSION))
+}
+
+/// Writes the lines of a tail session, pod and container names are only written once per source.
+pub struct SessionRecorder<W: Write> {
+    out: W,
+    sources: HashMap<(String, String), u32>,
+}
+
+impl SessionRecorder<BufWriter<File>> {
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> SessionRecorder<W> {
+    pub fn new(mut out: W) -> anyhow::Result<Self> {
+        out.write_all(MAGIC)?;
+        out.write_all(&[VERSION])?;
+        Ok(Self {
+            out,
+            sources: HashMap::new(),
+        })
+    }
+
+    pub fn record(&mut self, datum: &LogData) -> anyhow::Result<()> {
+        let key = (datum.pod.clone(), datum.container.clone());
+        let source = match self.sources.get(&key) {
+            Some(source) => *source,
+            None => {
+                let source = self.sources.len() as u32;
+                self.out.write_all(&[SOURCE_RECORD])?;
+                self.out.write_all(&source.to_le_bytes())?;
+                write_str(&mut self.out, &datum.pod)?;
+                write_str(&mut self.out, &datum.container)?;
+                self.sources.insert(key, source);
+                source
+            }
+        };
+        self.out.write_all(&[LINE_RECORD])?;
+        self.out.write_all(&source.to_le_bytes())?;
+        self.out.write_all(&datum.timestamp.timestamp_nanos().to_le_bytes())?;
+        self.out.write_all(&[datum.previous as u8])?;
+        write_str(&mut self.out, &datum.text)
+    }
+
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        Ok(self.out.flush()?)
+    }
+}
+
+fn write_str<W: Write>(out: &mut W, value: &str) -> anyhow::Result<()> {
+    out.write_all(&(value.len() as u32).to_le_bytes())?;
+    out.write_all(value.as_bytes())?;
+    Ok(())
+}
+
+/// Reads the lines of a recording in the order they were recorded.
+pub struct SessionReader<R: Read> {
+    input: R,
+    sources: Vec<(String, String)>,
+}
+
+impl SessionReader<BufReader<File>> {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> SessionReader<R> {
+    pub fn new(mut input: R) -> anyhow::Result<Self> {
+        let mut magic = [0u8; 9];
+        input.read_exact(&mut magic)?;
+        if &magic[..8] != MAGIC {
+            bail!("Not a KTail recording");
+        }
+        if magic[8] != VERSION {
+            bail!("Unsupported recording version {}", magic[8]);
+        }
+        Ok(Self {
+            input,
+            sources: vec![],
+        })
+    }
+
+    fn read_record(&mut self) -> anyhow::Result<Option<LogData>> {
+        loop {
+            let mut kind = [0u8; 1];
+            match self.input.read_exact(&mut kind) {
+                Ok(_) => {}
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+                Err(e) => return Err(e.into()),
+            }
+            let source = u32::from_le_bytes(self.read_array()?) as usize;
+            match kind[0] {
+                SOURCE_RECORD => {
+                    let pod = self.read_str()?;
+                    let container = self.read_str()?;
+                    if source != self.sources.len() {
+                        bail!("Unexpected source {}", source);
+                    }
+                    self.sources.push((pod, container));
+                }
+                LINE_RECORD => {
+                    let timestamp = i64::from_le_bytes(self.read_array()?);
+                    let [previous] = self.read_array()?;
+                    let text = self.read_str()?;
+                    let (pod, container) = self.sources.get(source).cloned().ok_or(anyhow!("Unknown source {}", source))?;
+                    return Ok(Some(LogData {
+                        text,
+                        pod,
+                        container,
+                        timestamp: Utc.timestamp_nanos(timestamp),
+                        previous: previous != 0,
+                        fields: None,
+                        severity: None,
+                        styles: vec![],
+                    }));
+                }
+                kind => bail!("Unknown record {}", kind),
+            }
+        }
+    }
+
+    fn read_array<const N: usize>(&mut self) -> anyhow::Result<[u8; N]> {
+        let mut buf = [0u8; N];
+        self.input.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn read_str(&mut self) -> anyhow::Result<String> {
+        let len = u32::from_le_bytes(self.read_array()?) as u64;
+        // The length of a damaged recording may be anything, so only what's there is allocated
+        let mut buf = vec![];
+        (&mut self.input).take(len).read_to_end(&mut buf)?;
+        if buf.len() as u64 != len {
+            bail!("Recording is truncated");
+        }
+        Ok(String::from_utf8(buf)?)
+    }
+}
+
+impl<R: Read> Iterator for SessionReader<R> {
+    type Item = anyhow::Result<LogData>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+/// Streams the lines of a recording like a live log stream.
+pub fn replay_stream(path: &Path) -> anyhow::Result<(impl Stream<Item = LogStreamEvent>, Trigger)> {
+    let reader = SessionReader::open(path)?;
+    let (tx, rx) = tokio::sync::mpsc::channel::<LogStreamEvent>(1000);
+    let (trigger, tripwire) = Tripwire::new();
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        for datum in reader {
+            match datum {
+                Ok(datum) => {
+                    if tx.blocking_send(LogStreamEvent::Data(datum)).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    // The end of recordings which were not closed properly may be cut off
+                    log::warn!("Could not read {}: {}", path.display(), e);
+                    break;
+                }
+            }
+        }
+    });
+    Ok((ReceiverStream::new(rx).take_until_if(tripwire), trigger))
+}
+
+#[test]
+pub fn test_record_and_replay() -> anyhow::Result<()> {
+    let lines = vec![
+        LogData { pod: "pod-a".to_string(), timestamp: Utc.timestamp_nanos(1_641_031_200_123_456_789), ..LogData::test("started\n", 0) },
+        LogData { pod: "pod-b".to_string(), previous: true, ..LogData::test("\x1b[31mfailed\x1b[0m ü\n", 1) },
+        LogData { pod: "pod-a".to_string(), ..LogData::test("done\n", 2) },
+    ];
+    let mut recorder = SessionRecorder::new(vec![])?;
+    for datum in &lines {
+        recorder.record(datum)?;
+    }
+    recorder.flush()?;
+    let recording = recorder.out;
+
+    let replayed = SessionReader::new(recording.as_slice())?.collect::<anyhow::Result<Vec<LogData>>>()?;
+    assert_eq!(replayed.len(), 3);
+    for (replayed, recorded) in replayed.iter().zip(lines.iter()) {
+        assert_eq!(replayed.pod, recorded.pod);
+        assert_eq!(replayed.container, recorded.container);
+        assert_eq!(replayed.text, recorded.text);
+        assert_eq!(replayed.timestamp, recorded.timestamp);
+        assert_eq!(replayed.previous, recorded.previous);
+    }
+
+    // Records cut off at the end are reported
+    let mut reader = SessionReader::new(&recording[..recording.len() - 2])?;
+    assert!(reader.next().unwrap().is_ok());
+    assert!(reader.next().unwrap().is_ok());
+    assert!(reader.next().unwrap().is_err());
+
+    // A damaged length doesn't allocate more than the remaining data
+    let mut damaged = recording.clone();
+    let len_pos = damaged.len() - "done\n".len() - 4;
+    damaged[len_pos..len_pos + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+    let mut reader = SessionReader::new(damaged.as_slice())?;
+    assert!(reader.next().unwrap().is_ok());
+    assert!(reader.next().unwrap().is_ok());
+    assert!(reader.next().unwrap().is_err());
+
+    assert!(SessionReader::new(&b"something else"[..]).is_err());
+    Ok(())
+}
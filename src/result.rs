@@ -1,9 +1,22 @@
 use thiserror::Error;
+use crate::k8s_client::KubeConfigError;
 
 #[derive(Error, Debug, Clone)]
 pub enum AppError {
     #[error("{0}")]
     Msg(String),
+    #[error("Context {context}: {error}")]
+    KubeConfig { context: String, error: KubeConfigError },
+}
+
+impl AppError {
+    /// Attaches the name of the kubernetes context which caused the error.
+    pub fn for_context<E: Into<anyhow::Error>>(context: &str, e: E) -> Self {
+        match e.into().downcast::<KubeConfigError>() {
+            Ok(error) => Self::KubeConfig { context: context.to_string(), error },
+            Err(e) => Self::Msg(format!("Context {}: {}", context, e)),
+        }
+    }
 }
 
 impl From<anyhow::Error> for AppError {
@@ -12,4 +25,4 @@ impl From<anyhow::Error> for AppError {
     }
 }
 
-pub type AppResult<T> = Result<T, AppError>;
\ No newline at end of file
+pub type AppResult<T> = Result<T, AppError>;